@@ -0,0 +1,105 @@
+#![feature(test)]
+
+extern crate test;
+extern crate tokio_core;
+extern crate byteorder;
+extern crate framecodecs;
+
+use framecodecs::frame::{LengthFieldCodec, VarIntLengthFieldCodec, FixedLengthCodec, DelimiterCodec,
+                         Delimiter};
+use tokio_core::io::{Codec, EasyBuf};
+use byteorder::BigEndian;
+use test::Bencher;
+
+const FRAME_LENGTH: usize = 4096;
+const FRAMES: usize = 256;
+
+fn encoded<C: Codec<Out = Vec<u8>>>(codec: &mut C) -> Vec<u8> {
+    let mut buf = vec![];
+    for _ in 0..FRAMES {
+        codec.encode(vec![0xAB; FRAME_LENGTH], &mut buf).unwrap();
+    }
+    buf
+}
+
+fn bench_decode<C>(b: &mut Bencher, mut codec: C, to_vec: bool)
+    where C: Codec<In = EasyBuf, Out = Vec<u8>>
+{
+    let data = encoded(&mut codec);
+    b.bytes = data.len() as u64;
+    b.iter(|| {
+        let mut buf = EasyBuf::from(data.clone());
+        while let Some(frame) = codec.decode(&mut buf).unwrap() {
+            if to_vec {
+                test::black_box(frame.as_slice().to_vec());
+            } else {
+                test::black_box(frame);
+            }
+        }
+    });
+}
+
+#[bench]
+fn fixed_length_easybuf(b: &mut Bencher) {
+    bench_decode(b, FixedLengthCodec::new(FRAME_LENGTH), false);
+}
+
+#[bench]
+fn fixed_length_to_vec(b: &mut Bencher) {
+    bench_decode(b, FixedLengthCodec::new(FRAME_LENGTH), true);
+}
+
+#[bench]
+fn length_field_easybuf(b: &mut Bencher) {
+    bench_decode(b, LengthFieldCodec::<BigEndian>::new(4), false);
+}
+
+#[bench]
+fn length_field_to_vec(b: &mut Bencher) {
+    bench_decode(b, LengthFieldCodec::<BigEndian>::new(4), true);
+}
+
+#[bench]
+fn varint_easybuf(b: &mut Bencher) {
+    bench_decode(b, VarIntLengthFieldCodec::new(), false);
+}
+
+#[bench]
+fn varint_to_vec(b: &mut Bencher) {
+    bench_decode(b, VarIntLengthFieldCodec::new(), true);
+}
+
+const TRICKLE_FRAME_LENGTH: usize = 1 << 20;
+const TRICKLE_CHUNK: usize = 64;
+
+/// Decodes a single large frame arriving in small chunks.
+fn bench_trickle<D: Delimiter + Clone>(b: &mut Bencher, delimiter: D) {
+    let mut data = vec![b'a'; TRICKLE_FRAME_LENGTH];
+    delimiter.write_delimiter(&mut data);
+    b.bytes = data.len() as u64;
+    b.iter(|| {
+        let mut codec = DelimiterCodec::new(delimiter.clone());
+        let mut buf = EasyBuf::new();
+        for chunk in data.chunks(TRICKLE_CHUNK) {
+            buf.get_mut().extend_from_slice(chunk);
+            if let Some(frame) = codec.decode(&mut buf).unwrap() {
+                test::black_box(frame);
+            }
+        }
+    });
+}
+
+#[bench]
+fn delimiter_trickle_u8(b: &mut Bencher) {
+    bench_trickle(b, b'\n');
+}
+
+#[bench]
+fn delimiter_trickle_bytes(b: &mut Bencher) {
+    bench_trickle(b, &b"\r\n"[..]);
+}
+
+#[bench]
+fn delimiter_trickle_char(b: &mut Bencher) {
+    bench_trickle(b, '\n');
+}
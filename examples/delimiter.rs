@@ -26,7 +26,7 @@ fn main() {
     let proto = DelimiterProto::new(LineDelimiter::Lf);
 
     thread::spawn(move || {
-        TcpServer::new(proto, addr).serve(|| Ok(EchoService));
+        TcpServer::new(proto.with_out::<EasyBuf>(), addr).serve(|| Ok(EchoService));
     });
 
     thread::sleep(Duration::from_millis(50));
@@ -43,11 +43,11 @@ struct EchoService;
 
 impl Service for EchoService {
     type Request = EasyBuf;
-    type Response = Vec<u8>;
+    type Response = EasyBuf;
     type Error = io::Error;
     type Future = FutureResult<Self::Response, Self::Error>;
 
     fn call(&self, req: EasyBuf) -> Self::Future {
-        futures::future::finished(req.as_slice().to_vec())
+        futures::future::finished(req)
     }
 }
@@ -5,6 +5,7 @@ extern crate tokio_service;
 extern crate service_fn;
 extern crate byteorder;
 
+use tokio_core::io::EasyBuf;
 use tokio_core::reactor::Core;
 use tokio_proto::{TcpServer, TcpClient};
 use tokio_service::Service;
@@ -21,7 +22,9 @@ fn main() {
 
         thread::spawn(move || {
             TcpServer::new(proto, "0.0.0.0:8000".parse().unwrap()).serve(|| {
-                Ok(service_fn::service_fn(|x: Vec<u8>| Ok(x.into_iter().rev().collect())))
+                Ok(service_fn::service_fn(|x: EasyBuf| {
+                    Ok(x.as_slice().iter().rev().cloned().collect())
+                }))
             })
         });
     }
@@ -35,8 +38,8 @@ fn main() {
         reactor.run(TcpClient::new(proto).connect(&"0.0.0.0:8000".parse().unwrap(), &handle))
             .unwrap();
 
-    assert_eq!(reactor.run(client.call(b"hello".to_vec())).unwrap(),
-               b"olleh".to_vec());
-    assert_eq!(reactor.run(client.call(b"good bye".to_vec())).unwrap(),
-               b"eyb doog".to_vec());
+    assert_eq!(reactor.run(client.call(b"hello".to_vec())).unwrap().as_slice(),
+               b"olleh");
+    assert_eq!(reactor.run(client.call(b"good bye".to_vec())).unwrap().as_slice(),
+               b"eyb doog");
 }
@@ -19,7 +19,7 @@ fn main() {
     let ts = core.run(ts.send(vec![70, 71, 72, 73, 74])).unwrap();
 
     core.run(ts.for_each(|v| {
-            println!("{:?}", v);
+            println!("{:?}", v.as_slice());
             Ok(())
         }))
         .unwrap();
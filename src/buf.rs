@@ -0,0 +1,79 @@
+//! Buffers the codecs are implemented over, so that the same framing logic serves both
+//! tokio-core's `Codec` and tokio-util's `Decoder` / `Encoder`.
+
+use tokio_core::io::EasyBuf;
+#[cfg(feature = "tokio-util-codec")]
+use bytes::BytesMut;
+
+/// A read buffer from which frames are split off.
+pub trait FrameBuf: AsRef<[u8]> + Sized {
+    /// Returns the number of bytes in the buffer.
+    fn len(&self) -> usize;
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes the first `at` bytes from the buffer and returns them.
+    fn split_to(&mut self, at: usize) -> Self;
+}
+
+impl FrameBuf for EasyBuf {
+    #[inline]
+    fn len(&self) -> usize {
+        EasyBuf::len(self)
+    }
+
+    #[inline]
+    fn split_to(&mut self, at: usize) -> EasyBuf {
+        self.drain_to(at)
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl FrameBuf for BytesMut {
+    #[inline]
+    fn len(&self) -> usize {
+        BytesMut::len(self)
+    }
+
+    #[inline]
+    fn split_to(&mut self, at: usize) -> BytesMut {
+        BytesMut::split_to(self, at)
+    }
+}
+
+/// A write buffer to which encoded frames are appended.
+pub trait FrameBufMut {
+    /// Reserves capacity for at least `additional` more bytes.
+    fn reserve(&mut self, additional: usize);
+
+    /// Appends `src` to the buffer.
+    fn put_slice(&mut self, src: &[u8]);
+}
+
+impl FrameBufMut for Vec<u8> {
+    #[inline]
+    fn reserve(&mut self, additional: usize) {
+        Vec::reserve(self, additional);
+    }
+
+    #[inline]
+    fn put_slice(&mut self, src: &[u8]) {
+        self.extend_from_slice(src);
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl FrameBufMut for BytesMut {
+    #[inline]
+    fn reserve(&mut self, additional: usize) {
+        BytesMut::reserve(self, additional);
+    }
+
+    #[inline]
+    fn put_slice(&mut self, src: &[u8]) {
+        self.extend_from_slice(src);
+    }
+}
@@ -6,6 +6,11 @@ use futures::{Future, IntoFuture, Stream, Sink, StartSend, Poll, Async};
 use std::marker::PhantomData;
 use std::io;
 
+/// A wrapper protocol that converts the `EasyBuf` frames decoded by the inner protocol into
+/// `Vec<u8>`.
+///
+/// The codecs in [`frame`](../frame/index.html) yield `EasyBuf`s sharing the read buffer, which
+/// avoids copying every frame. This protocol can be used when owned `Vec<u8>`s are preferred.
 pub struct DecodeToVecProto<P> {
     inner: P,
 }
@@ -44,6 +49,34 @@ impl<P, T> multiplex::ServerProto<T> for DecodeToVecProto<P>
     }
 }
 
+impl<P, T> pipeline::ClientProto<T> for DecodeToVecProto<P>
+    where P: pipeline::ClientProto<T, Response = EasyBuf>,
+          T: Io + 'static
+{
+    type Request = P::Request;
+    type Response = Vec<u8>;
+    type Transport = DecodeToVecTransport<P::Transport, Pipeline>;
+    type BindTransport = DecodeToVecBind<<P::BindTransport as IntoFuture>::Future, Pipeline>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        DecodeToVecBind::new(self.inner.bind_transport(io).into_future())
+    }
+}
+
+impl<P, T> multiplex::ClientProto<T> for DecodeToVecProto<P>
+    where P: multiplex::ClientProto<T, Response = EasyBuf>,
+          T: Io + 'static
+{
+    type Request = P::Request;
+    type Response = Vec<u8>;
+    type Transport = DecodeToVecTransport<P::Transport, Multiplex>;
+    type BindTransport = DecodeToVecBind<<P::BindTransport as IntoFuture>::Future, Multiplex>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        DecodeToVecBind::new(self.inner.bind_transport(io).into_future())
+    }
+}
+
 pub struct DecodeToVecBind<F, Kind> {
     fut: F,
     _kind: PhantomData<Kind>,
@@ -0,0 +1,77 @@
+//! Errors produced by the codecs of this crate.
+//!
+//! Codecs report these errors wrapped in an `io::Error`, from which they can be recovered with
+//! `get_ref()` and `downcast_ref()`:
+//!
+//! ```rust
+//! use framecodecs::Error;
+//! use std::io;
+//!
+//! let err: io::Error = Error::FrameTooLarge.into();
+//! assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+//!            Some(&Error::FrameTooLarge));
+//! ```
+
+use std::error;
+use std::fmt;
+use std::io;
+
+/// A framing error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// A frame exceeds the maximum frame length of the codec.
+    FrameTooLarge,
+    /// A frame is not valid UTF-8.
+    InvalidUtf8,
+    /// A length field has a value which does not describe a valid frame.
+    InvalidLength,
+    /// A varint does not fit in the integer type, or is longer than allowed.
+    VarIntOverflow,
+    /// An item to be encoded does not have the length required by the codec.
+    WrongFrameLength,
+    /// The stream ended in the middle of a frame.
+    UnexpectedEof,
+    /// A frame is not validly encoded, or an item cannot be represented in the encoding.
+    InvalidEncoding,
+}
+
+impl Error {
+    /// Returns the `io::ErrorKind` used when converting this error into an `io::Error`.
+    pub fn kind(&self) -> io::ErrorKind {
+        match *self {
+            Error::WrongFrameLength => io::ErrorKind::InvalidInput,
+            Error::UnexpectedEof => io::ErrorKind::UnexpectedEof,
+            _ => io::ErrorKind::InvalidData,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match *self {
+            Error::FrameTooLarge => "frame too large",
+            Error::InvalidUtf8 => "frame is not valid UTF-8",
+            Error::InvalidLength => "invalid length field",
+            Error::VarIntOverflow => "varint overflow",
+            Error::WrongFrameLength => "wrong frame length",
+            Error::UnexpectedEof => "unexpected end of stream in the middle of a frame",
+            Error::InvalidEncoding => "invalid frame encoding",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        io::Error::new(err.kind(), err)
+    }
+}
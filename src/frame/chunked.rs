@@ -0,0 +1,619 @@
+use tokio_core::io::{Codec, Io, EasyBuf, Framed};
+use tokio_proto::pipeline::{ServerProto, ClientProto};
+#[cfg(feature = "tokio-util-codec")]
+use bytes::BytesMut;
+use buf::{FrameBuf, FrameBufMut};
+use error::Error;
+use std::marker::PhantomData;
+use std::{cmp, io, mem};
+
+/// An item of a chunked body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Chunk<B> {
+    /// A chunk of data, with its extensions following `;` in the chunk-size line.
+    Data { data: B, extensions: Vec<u8> },
+    /// The last chunk, with its extensions and the trailer fields as `(name, value)` pairs.
+    End {
+        extensions: Vec<u8>,
+        trailers: Vec<(Vec<u8>, Vec<u8>)>,
+    },
+}
+
+impl<B> Chunk<B> {
+    /// Creates a chunk of `data` without extensions.
+    pub fn data(data: B) -> Self {
+        Chunk::Data {
+            data,
+            extensions: Vec::new(),
+        }
+    }
+
+    /// Creates the last chunk without extensions or trailers.
+    pub fn end() -> Self {
+        Chunk::End {
+            extensions: Vec::new(),
+            trailers: Vec::new(),
+        }
+    }
+}
+
+/// HTTP/1.1 chunked transfer coding.
+///
+/// A protocol such that data is sent in chunks prefixed by their length in hexadecimal, and ended
+/// by a zero-length chunk followed by optional trailer fields.
+///
+/// Decoded items are [`Chunk`](./enum.Chunk.html)s of `EasyBuf`s sharing the read buffer.
+/// Outgoing items are chunks of `Vec<u8>` by default, and can be chunks of any `AsRef<[u8]>` type
+/// selected with [`with_out`](#method.with_out).
+#[derive(Debug, PartialEq, Eq)]
+pub struct ChunkedProto<O = Vec<u8>> {
+    codec: ChunkedCodec<O>,
+}
+
+impl<O> Clone for ChunkedProto<O> {
+    fn clone(&self) -> Self {
+        ChunkedProto { codec: self.codec.clone() }
+    }
+}
+
+impl ChunkedProto {
+    pub fn new() -> Self {
+        ChunkedProto { codec: ChunkedCodec::new() }
+    }
+}
+
+impl Default for ChunkedProto {
+    fn default() -> Self {
+        ChunkedProto::new()
+    }
+}
+
+impl<O> ChunkedProto<O> {
+    /// Changes the type of outgoing chunks.
+    pub fn with_out<U: AsRef<[u8]>>(self) -> ChunkedProto<U> {
+        ChunkedProto { codec: self.codec.with_out() }
+    }
+
+    /// Sets the maximum length of the data of a chunk.
+    pub fn max_chunk_length(self, max_chunk_length: usize) -> Self {
+        ChunkedProto { codec: self.codec.max_chunk_length(max_chunk_length) }
+    }
+
+    /// Sets the maximum length of a chunk-size or trailer line, excluding the line ending.
+    pub fn max_line_length(self, max_line_length: usize) -> Self {
+        ChunkedProto { codec: self.codec.max_line_length(max_line_length) }
+    }
+
+    /// Sets the maximum total length of the trailer lines of a body, excluding the line endings.
+    pub fn max_trailers_length(self, max_trailers_length: usize) -> Self {
+        ChunkedProto { codec: self.codec.max_trailers_length(max_trailers_length) }
+    }
+}
+
+impl<T: Io + 'static, O: AsRef<[u8]> + 'static> ServerProto<T> for ChunkedProto<O> {
+    type Request = Chunk<EasyBuf>;
+    type Response = Chunk<O>;
+    type Transport = Framed<T, ChunkedCodec<O>>;
+    type BindTransport = io::Result<Self::Transport>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(io.framed(self.codec.clone()))
+    }
+}
+
+impl<T: Io + 'static, O: AsRef<[u8]> + 'static> ClientProto<T> for ChunkedProto<O> {
+    type Request = Chunk<O>;
+    type Response = Chunk<EasyBuf>;
+    type Transport = Framed<T, ChunkedCodec<O>>;
+    type BindTransport = io::Result<Self::Transport>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(io.framed(self.codec.clone()))
+    }
+}
+
+#[cfg(feature = "async-service")]
+impl<O> ::service::NewCodec for ChunkedProto<O> {
+    type Codec = ChunkedCodec<O>;
+
+    fn new_codec(&self) -> ChunkedCodec<O> {
+        self.codec.clone()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum State {
+    /// Reading a chunk-size line.
+    Size,
+    /// Reading the data of a chunk with the length and extensions.
+    Data(usize, Vec<u8>),
+    /// Reading the trailer fields after the last chunk with the extensions, and the length of the
+    /// trailer lines read so far.
+    Trailers(Vec<u8>, Vec<(Vec<u8>, Vec<u8>)>, usize),
+}
+
+/// Protocol codec used by [`ChunkedProto`](./struct.ChunkedProto.html).
+///
+/// Lines must be terminated by CRLF. After the last chunk and its trailers, the codec expects
+/// the chunk-size line of a new chunked body.
+///
+/// An invalid chunk size fails the decode with
+/// [`Error::InvalidLength`](../error/enum.Error.html), and a malformed line ending, chunk data
+/// not followed by CRLF, or a trailer line without `:` with `Error::InvalidEncoding`. Chunks
+/// longer than `max_chunk_length`, lines longer than `max_line_length` and trailers longer than
+/// `max_trailers_length` in total fail with `Error::FrameTooLarge`.
+///
+/// Empty data chunks are not encoded, since a zero-length chunk ends the body. Encoding
+/// extensions or trailers containing a line break fails with `Error::InvalidEncoding`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ChunkedCodec<O = Vec<u8>> {
+    max_chunk_length: Option<usize>,
+    max_line_length: Option<usize>,
+    max_trailers_length: Option<usize>,
+    state: State,
+    searched: usize,
+    _out: PhantomData<fn(O)>,
+}
+
+impl<O> Clone for ChunkedCodec<O> {
+    fn clone(&self) -> Self {
+        ChunkedCodec {
+            max_chunk_length: self.max_chunk_length,
+            max_line_length: self.max_line_length,
+            max_trailers_length: self.max_trailers_length,
+            state: self.state.clone(),
+            searched: self.searched,
+            _out: PhantomData,
+        }
+    }
+}
+
+impl ChunkedCodec {
+    pub fn new() -> Self {
+        ChunkedCodec {
+            max_chunk_length: None,
+            max_line_length: None,
+            max_trailers_length: None,
+            state: State::Size,
+            searched: 0,
+            _out: PhantomData,
+        }
+    }
+}
+
+impl Default for ChunkedCodec {
+    fn default() -> Self {
+        ChunkedCodec::new()
+    }
+}
+
+impl<O> ChunkedCodec<O> {
+    /// Changes the type of chunks to be encoded.
+    pub fn with_out<U: AsRef<[u8]>>(self) -> ChunkedCodec<U> {
+        ChunkedCodec {
+            max_chunk_length: self.max_chunk_length,
+            max_line_length: self.max_line_length,
+            max_trailers_length: self.max_trailers_length,
+            state: self.state,
+            searched: self.searched,
+            _out: PhantomData,
+        }
+    }
+
+    /// Sets the maximum length of the data of a chunk.
+    pub fn max_chunk_length(mut self, max_chunk_length: usize) -> Self {
+        self.max_chunk_length = Some(max_chunk_length);
+        self
+    }
+
+    /// Sets the maximum length of a chunk-size or trailer line, excluding the line ending.
+    pub fn max_line_length(mut self, max_line_length: usize) -> Self {
+        self.max_line_length = Some(max_line_length);
+        self
+    }
+
+    /// Sets the maximum total length of the trailer lines of a body, excluding the line endings.
+    pub fn max_trailers_length(mut self, max_trailers_length: usize) -> Self {
+        self.max_trailers_length = Some(max_trailers_length);
+        self
+    }
+
+    /// Pops a line terminated by CRLF and no longer than `max_len`, and returns it without the
+    /// line ending.
+    fn pop_line<F: FrameBuf>(&mut self, buf: &mut F, max_len: Option<usize>)
+                             -> io::Result<Option<Vec<u8>>> {
+        let from = cmp::min(self.searched, buf.len());
+        let end = match ::memchr::memchr(b'\n', &buf.as_ref()[from..]) {
+            Some(pos) => from + pos,
+            None => {
+                self.searched = buf.len();
+                if max_len.is_some_and(|max| buf.len() > max + 1) {
+                    return Err(Error::FrameTooLarge.into());
+                }
+                return Ok(None);
+            }
+        };
+        self.searched = 0;
+
+        let line = buf.split_to(end + 1);
+        let line = line.as_ref();
+        if end == 0 || line[end - 1] != b'\r' {
+            return Err(Error::InvalidEncoding.into());
+        }
+        if max_len.is_some_and(|max| end - 1 > max) {
+            return Err(Error::FrameTooLarge.into());
+        }
+        Ok(Some(line[..end - 1].to_vec()))
+    }
+
+    fn decode_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<Option<Chunk<F>>> {
+        loop {
+            match mem::replace(&mut self.state, State::Size) {
+                State::Size => {
+                    let max_len = self.max_line_length;
+                    let line = match self.pop_line(buf, max_len)? {
+                        Some(line) => line,
+                        None => return Ok(None),
+                    };
+
+                    let (size, extensions) = parse_size_line(&line)?;
+                    if self.max_chunk_length.is_some_and(|max| size > max) {
+                        return Err(Error::FrameTooLarge.into());
+                    }
+                    self.state = if size == 0 {
+                        State::Trailers(extensions, Vec::new(), 0)
+                    } else {
+                        State::Data(size, extensions)
+                    };
+                }
+
+                State::Data(size, extensions) => {
+                    if buf.len() < size || buf.len() - size < 2 {
+                        self.state = State::Data(size, extensions);
+                        return Ok(None);
+                    }
+                    if &buf.as_ref()[size..size + 2] != b"\r\n" {
+                        return Err(Error::InvalidEncoding.into());
+                    }
+
+                    let data = buf.split_to(size);
+                    buf.split_to(2);
+                    return Ok(Some(Chunk::Data {
+                        data,
+                        extensions,
+                    }));
+                }
+
+                State::Trailers(extensions, mut trailers, len) => {
+                    let max_len = match (self.max_line_length, self.max_trailers_length) {
+                        (Some(line), Some(total)) => Some(cmp::min(line, total - len)),
+                        (line, total) => line.or(total.map(|total| total - len)),
+                    };
+                    let line = match self.pop_line(buf, max_len)? {
+                        Some(line) => line,
+                        None => {
+                            self.state = State::Trailers(extensions, trailers, len);
+                            return Ok(None);
+                        }
+                    };
+
+                    if line.is_empty() {
+                        return Ok(Some(Chunk::End {
+                            extensions,
+                            trailers,
+                        }));
+                    }
+
+                    trailers.push(parse_trailer(&line)?);
+                    self.state = State::Trailers(extensions, trailers, len + line.len());
+                }
+            }
+        }
+    }
+
+    fn decode_eof_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<Option<Chunk<F>>> {
+        match self.decode_buf(buf)? {
+            Some(chunk) => Ok(Some(chunk)),
+            None if buf.is_empty() && self.state == State::Size => Ok(None),
+            None => Err(Error::UnexpectedEof.into()),
+        }
+    }
+
+    fn encode_buf<W: FrameBufMut>(&mut self, item: Chunk<&[u8]>, buf: &mut W) -> io::Result<()> {
+        match item {
+            Chunk::Data { data, extensions } => {
+                if data.is_empty() {
+                    return Ok(());
+                }
+                encode_size_line(data.len(), &extensions, buf)?;
+                buf.reserve(data.len() + 2);
+                buf.put_slice(data);
+                buf.put_slice(b"\r\n");
+            }
+            Chunk::End { extensions, trailers } => {
+                // nothing is written unless the whole terminator can be
+                let is_invalid = |(name, value): &(Vec<u8>, Vec<u8>)| {
+                    name.is_empty() || name.contains(&b':') || has_line_break(name) ||
+                    has_line_break(value)
+                };
+                if trailers.iter().any(is_invalid) {
+                    return Err(Error::InvalidEncoding.into());
+                }
+
+                encode_size_line(0, &extensions, buf)?;
+                for (name, value) in &trailers {
+                    buf.put_slice(name);
+                    buf.put_slice(b": ");
+                    buf.put_slice(value);
+                    buf.put_slice(b"\r\n");
+                }
+                buf.put_slice(b"\r\n");
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_whitespace(b: u8) -> bool {
+    b == b' ' || b == b'\t'
+}
+
+fn has_line_break(s: &[u8]) -> bool {
+    s.iter().any(|&b| b == b'\r' || b == b'\n')
+}
+
+fn trim(mut s: &[u8]) -> &[u8] {
+    while s.first().is_some_and(|&b| is_whitespace(b)) {
+        s = &s[1..];
+    }
+    while s.last().is_some_and(|&b| is_whitespace(b)) {
+        s = &s[..s.len() - 1];
+    }
+    s
+}
+
+/// Parses a chunk-size line into the size and the extensions.
+fn parse_size_line(line: &[u8]) -> io::Result<(usize, Vec<u8>)> {
+    let (size, extensions) = match ::memchr::memchr(b';', line) {
+        Some(pos) => (&line[..pos], &line[pos + 1..]),
+        None => (line, &[][..]),
+    };
+
+    let size = trim(size);
+    if size.is_empty() {
+        return Err(Error::InvalidLength.into());
+    }
+
+    let mut len: usize = 0;
+    for &b in size {
+        let digit = match (b as char).to_digit(16) {
+            Some(d) => d as usize,
+            None => return Err(Error::InvalidLength.into()),
+        };
+        len = match len.checked_mul(16).and_then(|l| l.checked_add(digit)) {
+            Some(l) => l,
+            None => return Err(Error::InvalidLength.into()),
+        };
+    }
+
+    Ok((len, extensions.to_vec()))
+}
+
+/// Parses a trailer field line into the name and the value.
+fn parse_trailer(line: &[u8]) -> io::Result<(Vec<u8>, Vec<u8>)> {
+    match ::memchr::memchr(b':', line) {
+        Some(pos) if pos > 0 => Ok((line[..pos].to_vec(), trim(&line[pos + 1..]).to_vec())),
+        _ => Err(Error::InvalidEncoding.into()),
+    }
+}
+
+fn encode_size_line<W: FrameBufMut>(size: usize, extensions: &[u8], buf: &mut W)
+                                    -> io::Result<()> {
+    if has_line_break(extensions) {
+        return Err(Error::InvalidEncoding.into());
+    }
+    buf.put_slice(format!("{:X}", size).as_bytes());
+    if !extensions.is_empty() {
+        buf.put_slice(b";");
+        buf.put_slice(extensions);
+    }
+    buf.put_slice(b"\r\n");
+    Ok(())
+}
+
+/// Borrows the data of an outgoing chunk.
+fn as_slice_chunk<O: AsRef<[u8]>>(item: &Chunk<O>) -> Chunk<&[u8]> {
+    match *item {
+        Chunk::Data { ref data, ref extensions } => {
+            Chunk::Data {
+                data: data.as_ref(),
+                extensions: extensions.clone(),
+            }
+        }
+        Chunk::End { ref extensions, ref trailers } => {
+            Chunk::End {
+                extensions: extensions.clone(),
+                trailers: trailers.clone(),
+            }
+        }
+    }
+}
+
+impl<O: AsRef<[u8]>> Codec for ChunkedCodec<O> {
+    type In = Chunk<EasyBuf>;
+    type Out = Chunk<O>;
+
+    fn decode(&mut self, buf: &mut EasyBuf) -> io::Result<Option<Chunk<EasyBuf>>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut EasyBuf) -> io::Result<Chunk<EasyBuf>> {
+        match self.decode_eof_buf(buf)? {
+            Some(chunk) => Ok(chunk),
+            None => Err(Error::UnexpectedEof.into()),
+        }
+    }
+
+    fn encode(&mut self, item: Chunk<O>, buf: &mut Vec<u8>) -> io::Result<()> {
+        self.encode_buf(as_slice_chunk(&item), buf)
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl<O> ::tokio_util::codec::Decoder for ChunkedCodec<O> {
+    type Item = Chunk<BytesMut>;
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<Chunk<BytesMut>>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> io::Result<Option<Chunk<BytesMut>>> {
+        self.decode_eof_buf(buf)
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl<O: AsRef<[u8]>> ::tokio_util::codec::Encoder<Chunk<O>> for ChunkedCodec<O> {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Chunk<O>, buf: &mut BytesMut) -> io::Result<()> {
+        self.encode_buf(as_slice_chunk(&item), buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_vec(chunk: Chunk<EasyBuf>) -> Chunk<Vec<u8>> {
+        match chunk {
+            Chunk::Data { data, extensions } => {
+                Chunk::Data {
+                    data: data.as_slice().to_vec(),
+                    extensions,
+                }
+            }
+            Chunk::End { extensions, trailers } => {
+                Chunk::End {
+                    extensions,
+                    trailers,
+                }
+            }
+        }
+    }
+
+    fn decode_all(p: &mut ChunkedCodec, input: &[u8]) -> Vec<Chunk<Vec<u8>>> {
+        let mut buf = EasyBuf::new();
+        let mut chunks = vec![];
+        for &b in input {
+            buf.get_mut().push(b);
+            while let Some(chunk) = p.decode(&mut buf).unwrap() {
+                chunks.push(to_vec(chunk));
+            }
+        }
+        assert_eq!(buf.len(), 0);
+        chunks
+    }
+
+    #[test]
+    fn test_chunked() {
+        let mut p = ChunkedCodec::new();
+        let chunks = decode_all(&mut p,
+                                b"4\r\nWiki\r\n7;name=\"va;lue\"\r\npedia i\r\n\
+                                  B\r\nn \r\nchunks.\r\n\
+                                  0;last\r\nExpires: never\r\nX-Checksum:abc \r\n\r\n\
+                                  0\r\n\r\n");
+        assert_eq!(chunks,
+                   vec![Chunk::data(b"Wiki".to_vec()),
+                        Chunk::Data {
+                            data: b"pedia i".to_vec(),
+                            extensions: b"name=\"va;lue\"".to_vec(),
+                        },
+                        Chunk::data(b"n \r\nchunks.".to_vec()),
+                        Chunk::End {
+                            extensions: b"last".to_vec(),
+                            trailers: vec![(b"Expires".to_vec(), b"never".to_vec()),
+                                           (b"X-Checksum".to_vec(), b"abc".to_vec())],
+                        },
+                        Chunk::end()]);
+    }
+
+    #[test]
+    fn test_chunked_encode() {
+        let mut p = ChunkedCodec::new();
+        let mut v = vec![];
+        p.encode(Chunk::data(vec![b'a'; 26]), &mut v).unwrap();
+        p.encode(Chunk::data(vec![]), &mut v).unwrap();
+        p.encode(Chunk::End {
+                     extensions: vec![],
+                     trailers: vec![(b"Expires".to_vec(), b"never".to_vec())],
+                 },
+                 &mut v)
+            .unwrap();
+
+        let mut expected = b"1A\r\n".to_vec();
+        expected.extend_from_slice(&[b'a'; 26]);
+        expected.extend_from_slice(b"\r\n0\r\nExpires: never\r\n\r\n");
+        assert_eq!(v, expected);
+
+        let err = p.encode(Chunk::Data {
+                               data: b"a".to_vec(),
+                               extensions: b"\r\n".to_vec(),
+                           },
+                           &mut vec![])
+            .unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::InvalidEncoding));
+
+        // nothing is written for a last chunk with an invalid trailer
+        let mut v = vec![];
+        let err = p.encode(Chunk::End {
+                               extensions: vec![],
+                               trailers: vec![(b"Expires".to_vec(), b"never".to_vec()),
+                                              (b"Bad:Name".to_vec(), b"x".to_vec())],
+                           },
+                           &mut v)
+            .unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::InvalidEncoding));
+        assert!(v.is_empty());
+    }
+
+    #[test]
+    fn test_chunked_invalid() {
+        fn decode_err(p: &mut ChunkedCodec, input: &[u8]) -> Error {
+            let mut buf = EasyBuf::from(input.to_vec());
+            loop {
+                match p.decode(&mut buf) {
+                    Ok(Some(_)) => {}
+                    Ok(None) => panic!("no error"),
+                    Err(e) => return *e.get_ref().and_then(|e| e.downcast_ref::<Error>()).unwrap(),
+                }
+            }
+        }
+
+        assert_eq!(decode_err(&mut ChunkedCodec::new(), b"x\r\n"), Error::InvalidLength);
+        assert_eq!(decode_err(&mut ChunkedCodec::new(), b";a\r\n"), Error::InvalidLength);
+        assert_eq!(decode_err(&mut ChunkedCodec::new(), b"10000000000000000\r\n"),
+                   Error::InvalidLength);
+        assert_eq!(decode_err(&mut ChunkedCodec::new(), b"1\n"), Error::InvalidEncoding);
+        assert_eq!(decode_err(&mut ChunkedCodec::new(), b"1\r\nab\r\n"), Error::InvalidEncoding);
+        assert_eq!(decode_err(&mut ChunkedCodec::new(), b"0\r\nfoo\r\n"), Error::InvalidEncoding);
+
+        let mut p = ChunkedCodec::new().max_chunk_length(4).max_line_length(4);
+        assert_eq!(decode_err(&mut p, b"5\r\n"), Error::FrameTooLarge);
+        let mut p = ChunkedCodec::new().max_chunk_length(4).max_line_length(4);
+        assert_eq!(decode_err(&mut p, b"1;abcd"), Error::FrameTooLarge);
+        let mut p = ChunkedCodec::new().max_line_length(4).max_trailers_length(10);
+        assert_eq!(decode_err(&mut p, b"0\r\na: b\r\na: b\r\na: b\r\n"), Error::FrameTooLarge);
+        let mut p = ChunkedCodec::new().max_trailers_length(10);
+        assert_eq!(decode_err(&mut p, b"0\r\na: b\r\nab: cdefg"), Error::FrameTooLarge);
+
+        let mut p = ChunkedCodec::new();
+        let mut buf = EasyBuf::from(b"4\r\nab".to_vec());
+        let err = p.decode_eof(&mut buf).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::UnexpectedEof));
+    }
+}
@@ -0,0 +1,347 @@
+use tokio_core::io::{Codec, Io, EasyBuf, Framed};
+use tokio_proto::pipeline::{ServerProto, ClientProto};
+#[cfg(feature = "tokio-util-codec")]
+use bytes::BytesMut;
+use buf::{FrameBuf, FrameBufMut};
+use super::stuffing::split_frame;
+use error::Error;
+use std::marker::PhantomData;
+use std::cmp;
+use std::io;
+
+/// COBS (Consistent Overhead Byte Stuffing) protocol.
+///
+/// A protocol such that frames are COBS-encoded, which removes zero bytes from the payload, and
+/// terminated with a zero byte.
+///
+/// Decoded frames are `Vec<u8>`s. Outgoing frames are `Vec<u8>` by default, and can be any
+/// `AsRef<[u8]>` type selected with [`with_out`](#method.with_out).
+#[derive(Debug, PartialEq, Eq)]
+pub struct CobsProto<O = Vec<u8>> {
+    codec: CobsCodec<O>,
+}
+
+impl<O> Clone for CobsProto<O> {
+    fn clone(&self) -> Self {
+        CobsProto { codec: self.codec.clone() }
+    }
+}
+
+impl CobsProto {
+    pub fn new() -> Self {
+        CobsProto { codec: CobsCodec::new() }
+    }
+}
+
+impl Default for CobsProto {
+    fn default() -> Self {
+        CobsProto::new()
+    }
+}
+
+impl<O> CobsProto<O> {
+    /// Changes the type of outgoing frames.
+    pub fn with_out<U: AsRef<[u8]>>(self) -> CobsProto<U> {
+        CobsProto { codec: self.codec.with_out() }
+    }
+
+    /// Sets the maximum length of an encoded frame, excluding the terminating zero.
+    pub fn max_frame_length(self, max_frame_length: usize) -> Self {
+        CobsProto { codec: self.codec.max_frame_length(max_frame_length) }
+    }
+}
+
+impl<T: Io + 'static, O: AsRef<[u8]> + 'static> ServerProto<T> for CobsProto<O> {
+    type Request = Vec<u8>;
+    type Response = O;
+    type Transport = Framed<T, CobsCodec<O>>;
+    type BindTransport = io::Result<Self::Transport>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(io.framed(self.codec.clone()))
+    }
+}
+
+impl<T: Io + 'static, O: AsRef<[u8]> + 'static> ClientProto<T> for CobsProto<O> {
+    type Request = O;
+    type Response = Vec<u8>;
+    type Transport = Framed<T, CobsCodec<O>>;
+    type BindTransport = io::Result<Self::Transport>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(io.framed(self.codec.clone()))
+    }
+}
+
+#[cfg(feature = "async-service")]
+impl<O> ::service::NewCodec for CobsProto<O> {
+    type Codec = CobsCodec<O>;
+
+    fn new_codec(&self) -> CobsCodec<O> {
+        self.codec.clone()
+    }
+}
+
+/// Protocol codec used by [`CobsProto`](./struct.CobsProto.html).
+///
+/// Consecutive zero bytes are ignored. A frame with a code byte pointing past its end fails the
+/// decode with [`Error::InvalidEncoding`](../error/enum.Error.html).
+#[derive(Debug, PartialEq, Eq)]
+pub struct CobsCodec<O = Vec<u8>> {
+    max_frame_length: Option<usize>,
+    searched: usize,
+    _out: PhantomData<fn(O)>,
+}
+
+impl<O> Clone for CobsCodec<O> {
+    fn clone(&self) -> Self {
+        CobsCodec {
+            max_frame_length: self.max_frame_length,
+            searched: self.searched,
+            _out: PhantomData,
+        }
+    }
+}
+
+impl CobsCodec {
+    pub fn new() -> Self {
+        CobsCodec {
+            max_frame_length: None,
+            searched: 0,
+            _out: PhantomData,
+        }
+    }
+}
+
+impl Default for CobsCodec {
+    fn default() -> Self {
+        CobsCodec::new()
+    }
+}
+
+impl<O> CobsCodec<O> {
+    /// Changes the type of items to be encoded.
+    pub fn with_out<U: AsRef<[u8]>>(self) -> CobsCodec<U> {
+        CobsCodec {
+            max_frame_length: self.max_frame_length,
+            searched: self.searched,
+            _out: PhantomData,
+        }
+    }
+
+    /// Sets the maximum length of an encoded frame, excluding the terminating zero.
+    pub fn max_frame_length(mut self, max_frame_length: usize) -> Self {
+        self.max_frame_length = Some(max_frame_length);
+        self
+    }
+
+    fn decode_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<Option<Vec<u8>>> {
+        match split_frame(buf, 0, &mut self.searched, self.max_frame_length)? {
+            Some(frame) => cobs_decode(frame.as_ref()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn decode_eof_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<Option<Vec<u8>>> {
+        match self.decode_buf(buf)? {
+            Some(frame) => Ok(Some(frame)),
+            None if buf.is_empty() => Ok(None),
+            None => Err(Error::UnexpectedEof.into()),
+        }
+    }
+}
+
+fn cobs_encode<W: FrameBufMut>(item: &[u8], buf: &mut W) {
+    buf.reserve(item.len() + item.len() / 254 + 2);
+
+    // each block is a code byte followed by up to 254 non-zero bytes, and the code is the length
+    // of the block, which is followed by a zero unless the code is `0xFF`. A full block ending the
+    // data is not followed by an empty one.
+    let mut rest = item;
+    loop {
+        let limit = cmp::min(rest.len(), 0xFE);
+        match rest[..limit].iter().position(|&b| b == 0) {
+            Some(pos) => {
+                buf.put_slice(&[pos as u8 + 1]);
+                buf.put_slice(&rest[..pos]);
+                rest = &rest[pos + 1..];
+            }
+            None if limit == 0xFE => {
+                buf.put_slice(&[0xFF]);
+                buf.put_slice(&rest[..limit]);
+                rest = &rest[limit..];
+                if rest.is_empty() {
+                    break;
+                }
+            }
+            None => {
+                buf.put_slice(&[limit as u8 + 1]);
+                buf.put_slice(rest);
+                break;
+            }
+        }
+    }
+
+    buf.put_slice(&[0]);
+}
+
+fn cobs_decode(frame: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(frame.len());
+    let mut pos = 0;
+
+    while pos < frame.len() {
+        let code = frame[pos] as usize;
+        let end = pos + code;
+        if end > frame.len() {
+            return Err(Error::InvalidEncoding.into());
+        }
+        out.extend_from_slice(&frame[pos + 1..end]);
+        pos = end;
+        if code < 0xFF && pos < frame.len() {
+            out.push(0);
+        }
+    }
+
+    Ok(out)
+}
+
+impl<O: AsRef<[u8]>> Codec for CobsCodec<O> {
+    type In = Vec<u8>;
+    type Out = O;
+
+    fn decode(&mut self, buf: &mut EasyBuf) -> io::Result<Option<Vec<u8>>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut EasyBuf) -> io::Result<Vec<u8>> {
+        match self.decode_eof_buf(buf)? {
+            Some(frame) => Ok(frame),
+            None => Err(Error::UnexpectedEof.into()),
+        }
+    }
+
+    fn encode(&mut self, item: O, buf: &mut Vec<u8>) -> io::Result<()> {
+        cobs_encode(item.as_ref(), buf);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl<O> ::tokio_util::codec::Decoder for CobsCodec<O> {
+    type Item = Vec<u8>;
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<Vec<u8>>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> io::Result<Option<Vec<u8>>> {
+        self.decode_eof_buf(buf)
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl<O: AsRef<[u8]>> ::tokio_util::codec::Encoder<O> for CobsCodec<O> {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: O, buf: &mut BytesMut) -> io::Result<()> {
+        cobs_encode(item.as_ref(), buf);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::{assert_err, decode_chunks, encode_all};
+
+    #[test]
+    fn test_cobs() {
+        // examples from the paper and Wikipedia
+        let cases: &[(&[u8], &[u8])] = &[(&[], &[0x01, 0]),
+                                         (&[0x00], &[0x01, 0x01, 0]),
+                                         (&[0x00, 0x00], &[0x01, 0x01, 0x01, 0]),
+                                         (&[0x11, 0x22, 0x00, 0x33], &[0x03, 0x11, 0x22, 0x02, 0x33, 0]),
+                                         (&[0x11, 0x00, 0x00, 0x00], &[0x02, 0x11, 0x01, 0x01, 0x01, 0])];
+
+        let mut p = CobsCodec::new().with_out::<&'static [u8]>();
+        for &(data, encoded) in cases {
+            let mut v = vec![];
+            p.encode(data, &mut v).unwrap();
+            assert_eq!(v, encoded);
+
+            let mut buf = EasyBuf::from(v);
+            assert_eq!(p.decode(&mut buf).unwrap().unwrap(), data);
+            assert_eq!(buf.len(), 0);
+        }
+
+        let data: Vec<u8> = (1..256).map(|b| b as u8).collect();
+        let mut v = vec![];
+        CobsCodec::new().encode(data.clone(), &mut v).unwrap();
+        assert_eq!(v.len(), data.len() + 3);
+        assert_eq!(&v[..2], &[0xFF, 0x01]);
+        assert_eq!(&v[255..], &[0x02, 0xFF, 0]);
+
+        // payloads ending on a full block
+        for &len in &[254, 508] {
+            let data = vec![0x11; len];
+            let mut v = vec![];
+            CobsCodec::new().encode(data.clone(), &mut v).unwrap();
+            let expected: Vec<u8> = data.chunks(254)
+                .flat_map(|block| Some(0xFF).into_iter().chain(block.iter().cloned()))
+                .chain(Some(0))
+                .collect();
+            assert_eq!(v, expected, "length {}", len);
+
+            let mut buf = EasyBuf::from(v);
+            assert_eq!(CobsCodec::new().decode(&mut buf).unwrap().unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_cobs_invalid() {
+        let mut p = CobsCodec::new();
+        let mut buf = EasyBuf::from(vec![0, 0, 0x03, 0x11, 0, 0x02, 0x22, 0]);
+        assert_err(p.decode(&mut buf), Error::InvalidEncoding);
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap(), [0x22]);
+
+        let mut buf = EasyBuf::from(vec![0x02, 0x11]);
+        assert_err(p.decode_eof(&mut buf), Error::UnexpectedEof);
+    }
+
+    #[cfg(feature = "tokio-util-codec")]
+    #[test]
+    fn test_cobs_tokio_util() {
+        use tokio_util::codec::{Decoder, Encoder};
+
+        let mut p = CobsCodec::new();
+
+        let mut buf = BytesMut::new();
+        Encoder::encode(&mut p, vec![0x11, 0x22, 0x00, 0x33], &mut buf).unwrap();
+        assert_eq!(&buf[..], [0x03, 0x11, 0x22, 0x02, 0x33, 0]);
+
+        assert_eq!(Decoder::decode(&mut p, &mut buf).unwrap().unwrap(), [0x11, 0x22, 0x00, 0x33]);
+        assert!(Decoder::decode_eof(&mut p, &mut buf).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_cobs_roundtrip() {
+        use quickcheck::quickcheck;
+
+        fn roundtrip(frames: Vec<Vec<u8>>, chunk: usize) -> bool {
+            let mut p = CobsCodec::new();
+            let v = encode_all(&mut p, frames.clone());
+            // zeros appear only as terminators
+            if v.iter().filter(|&&b| b == 0).count() != frames.len() {
+                return false;
+            }
+
+            decode_chunks(&mut p, &v, chunk % 16 + 1) == frames
+        }
+
+        quickcheck(roundtrip as fn(Vec<Vec<u8>>, usize) -> bool);
+
+        let long: Vec<u8> = (0..1000).map(|i| (i % 7) as u8).collect();
+        assert!(roundtrip(vec![long, vec![0xFF; 600]], 5));
+    }
+}
@@ -1,79 +1,377 @@
 use tokio_core::io::{Codec, Io, EasyBuf, Framed};
 use tokio_proto::pipeline::{ServerProto, ClientProto};
-use std::io;
+#[cfg(feature = "tokio-util-codec")]
+use bytes::BytesMut;
+use buf::FrameBuf;
+use buf::FrameBufMut;
+use error::Error;
+use std::marker::PhantomData;
+use std::{cmp, io, str};
 
 /// Delimitered protocol.
 ///
 /// A protocol such that frames are separated with specified delimiters.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct DelimiterProto<D>(D);
+///
+/// Outgoing frames are `Vec<u8>` by default, and can be any `AsRef<[u8]>` type selected with
+/// [`with_out`](#method.with_out).
+#[derive(Debug, PartialEq, Eq)]
+pub struct DelimiterProto<D, O = Vec<u8>> {
+    codec: DelimiterCodec<D, O>,
+}
+
+impl<D: Clone, O> Clone for DelimiterProto<D, O> {
+    fn clone(&self) -> Self {
+        DelimiterProto { codec: self.codec.clone() }
+    }
+}
+
+impl<D: Copy, O> Copy for DelimiterProto<D, O> {}
 
 impl<D: Delimiter> DelimiterProto<D> {
     /// Creates a `DelimiterProto` from the specified delimiter.
     pub fn new(delimiter: D) -> Self {
-        DelimiterProto(delimiter)
+        DelimiterProto { codec: DelimiterCodec::new(delimiter) }
+    }
+}
+
+impl<D: Delimiter, O> DelimiterProto<D, O> {
+    /// Changes the type of outgoing frames.
+    pub fn with_out<U: AsRef<[u8]>>(self) -> DelimiterProto<D, U> {
+        DelimiterProto { codec: self.codec.with_out() }
+    }
+
+    /// Sets the maximum length of a frame, excluding the delimiter.
+    pub fn max_frame_length(self, max_frame_length: usize) -> Self {
+        DelimiterProto { codec: self.codec.max_frame_length(max_frame_length) }
+    }
+
+    /// Sets whether oversized frames are discarded instead of failing the decode.
+    pub fn discard_oversized(self, discard_oversized: bool) -> Self {
+        DelimiterProto { codec: self.codec.discard_oversized(discard_oversized) }
+    }
+
+    /// Sets whether unterminated data at the end of the stream is yielded as the last frame.
+    pub fn yield_unterminated(self, yield_unterminated: bool) -> Self {
+        DelimiterProto { codec: self.codec.yield_unterminated(yield_unterminated) }
+    }
+
+    /// Sets whether decoded frames include the delimiter that terminated them.
+    pub fn keep_delimiter(self, keep_delimiter: bool) -> Self {
+        DelimiterProto { codec: self.codec.keep_delimiter(keep_delimiter) }
     }
 }
 
-impl<T, D> ServerProto<T> for DelimiterProto<D>
+impl<T, D, O> ServerProto<T> for DelimiterProto<D, O>
     where T: Io + 'static,
-          D: Delimiter + Clone + 'static
+          D: Delimiter + Clone + 'static,
+          O: AsRef<[u8]> + 'static
 {
     type Request = EasyBuf;
-    type Response = Vec<u8>;
-    type Transport = Framed<T, DelimiterCodec<D>>;
+    type Response = O;
+    type Transport = Framed<T, DelimiterCodec<D, O>>;
     type BindTransport = Result<Self::Transport, io::Error>;
 
     fn bind_transport(&self, io: T) -> Self::BindTransport {
-        Ok(io.framed(DelimiterCodec(self.0.clone())))
+        Ok(io.framed(self.codec.clone()))
     }
 }
 
-impl<T: Io, D> ClientProto<T> for DelimiterProto<D>
+impl<T: Io, D, O> ClientProto<T> for DelimiterProto<D, O>
     where T: Io + 'static,
-          D: Delimiter + Clone + 'static
+          D: Delimiter + Clone + 'static,
+          O: AsRef<[u8]> + 'static
 {
-    type Request = Vec<u8>;
+    type Request = O;
     type Response = EasyBuf;
-    type Transport = Framed<T, DelimiterCodec<D>>;
+    type Transport = Framed<T, DelimiterCodec<D, O>>;
     type BindTransport = io::Result<Self::Transport>;
 
     fn bind_transport(&self, io: T) -> Self::BindTransport {
-        Ok(io.framed(DelimiterCodec(self.0.clone())))
+        Ok(io.framed(self.codec.clone()))
+    }
+}
+
+#[cfg(feature = "async-service")]
+impl<D: Clone, O> ::service::NewCodec for DelimiterProto<D, O> {
+    type Codec = DelimiterCodec<D, O>;
+
+    fn new_codec(&self) -> DelimiterCodec<D, O> {
+        self.codec.clone()
     }
 }
 
 /// Protocol codec used by [`DelimiterProto`](./struct.DelimiterProto.html).
-#[derive(Debug, Clone)]
-pub struct DelimiterCodec<D>(D);
+///
+/// If a frame exceeds `max_frame_length`, decoding fails with
+/// [`Error::FrameTooLarge`](../error/enum.Error.html), or the frame is skipped up to the next
+/// delimiter if the codec is set to `discard_oversized`.
+///
+/// If the stream ends with data not terminated by the delimiter, decoding fails with
+/// [`Error::UnexpectedEof`](../error/enum.Error.html), unless the codec is set to
+/// `yield_unterminated`, in which case the data is yielded as the last frame.
+///
+/// The delimiter is removed from decoded frames, unless the codec is set to `keep_delimiter`. The
+/// delimiter that terminated a frame can then be told with `AnyOf::matched` or
+/// `AnyLineEnding::matched` when several delimiters are accepted.
+///
+/// The codec remembers how far the buffer has been searched for the delimiter, so a frame arriving
+/// in many small reads is scanned only once.
+#[derive(Debug, PartialEq, Eq)]
+pub struct DelimiterCodec<D, O = Vec<u8>> {
+    delimiter: D,
+    max_frame_length: Option<usize>,
+    discard_oversized: bool,
+    yield_unterminated: bool,
+    keep_delimiter: bool,
+    discarding: bool,
+    /// Length of the head of the buffer known not to contain the delimiter
+    searched: usize,
+    _out: PhantomData<fn(O)>,
+}
+
+impl<D: Clone, O> Clone for DelimiterCodec<D, O> {
+    fn clone(&self) -> Self {
+        DelimiterCodec {
+            delimiter: self.delimiter.clone(),
+            max_frame_length: self.max_frame_length,
+            discard_oversized: self.discard_oversized,
+            yield_unterminated: self.yield_unterminated,
+            keep_delimiter: self.keep_delimiter,
+            discarding: self.discarding,
+            searched: self.searched,
+            _out: PhantomData,
+        }
+    }
+}
+
+impl<D: Copy, O> Copy for DelimiterCodec<D, O> {}
 
 impl<D> DelimiterCodec<D> {
     pub fn new(delimiter: D) -> DelimiterCodec<D> {
-        DelimiterCodec(delimiter)
+        DelimiterCodec {
+            delimiter,
+            max_frame_length: None,
+            discard_oversized: false,
+            yield_unterminated: false,
+            keep_delimiter: false,
+            discarding: false,
+            searched: 0,
+            _out: PhantomData,
+        }
+    }
+}
+
+impl<D, O> DelimiterCodec<D, O> {
+    /// Changes the type of items to be encoded.
+    pub fn with_out<U: AsRef<[u8]>>(self) -> DelimiterCodec<D, U> {
+        DelimiterCodec {
+            delimiter: self.delimiter,
+            max_frame_length: self.max_frame_length,
+            discard_oversized: self.discard_oversized,
+            yield_unterminated: self.yield_unterminated,
+            keep_delimiter: self.keep_delimiter,
+            discarding: self.discarding,
+            searched: self.searched,
+            _out: PhantomData,
+        }
+    }
+
+    /// Sets the maximum length of a frame, excluding the delimiter.
+    pub fn max_frame_length(mut self, max_frame_length: usize) -> Self {
+        self.max_frame_length = Some(max_frame_length);
+        self
+    }
+
+    /// Sets whether oversized frames are discarded instead of failing the decode.
+    pub fn discard_oversized(mut self, discard_oversized: bool) -> Self {
+        self.discard_oversized = discard_oversized;
+        self
+    }
+
+    /// Sets whether unterminated data at the end of the stream is yielded as the last frame.
+    pub fn yield_unterminated(mut self, yield_unterminated: bool) -> Self {
+        self.yield_unterminated = yield_unterminated;
+        self
+    }
+
+    /// Sets whether decoded frames include the delimiter that terminated them.
+    pub fn keep_delimiter(mut self, keep_delimiter: bool) -> Self {
+        self.keep_delimiter = keep_delimiter;
+        self
+    }
+
+    /// Returns the delimiter of this codec.
+    pub fn delimiter(&self) -> &D {
+        &self.delimiter
+    }
+
+    fn is_too_large(&self, len: usize) -> bool {
+        self.max_frame_length.is_some_and(|max| len > max)
+    }
+}
+
+impl<D: Delimiter, O> DelimiterCodec<D, O> {
+    #[inline]
+    fn decode_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<Option<F>> {
+        self.decode_frame(buf, false)
+    }
+
+    fn decode_frame<F: FrameBuf>(&mut self, buf: &mut F, eof: bool) -> io::Result<Option<F>> {
+        loop {
+            match find_delimiter(&self.delimiter, buf.as_ref(), &mut self.searched, eof)? {
+                Some((start, end)) => {
+                    let frame = split_frame(buf, start, end, self.keep_delimiter);
+                    if self.discarding {
+                        self.discarding = false;
+                    } else if !self.is_too_large(start) {
+                        return Ok(Some(frame));
+                    } else if !self.discard_oversized {
+                        return Err(Error::FrameTooLarge.into());
+                    }
+                }
+
+                None => {
+                    let pending = unterminated_len(&self.delimiter, buf.as_ref(), self.searched);
+                    if self.discarding || self.is_too_large(pending) {
+                        if !self.discard_oversized {
+                            return Err(Error::FrameTooLarge.into());
+                        }
+                        self.discarding = true;
+                        buf.split_to(pending);
+                        self.searched = 0;
+                    }
+
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    fn decode_eof_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<F> {
+        if let Some(frame) = self.decode_frame(buf, true)? {
+            return Ok(frame);
+        }
+
+        if !self.yield_unterminated || self.discarding {
+            return Err(Error::UnexpectedEof.into());
+        }
+        if self.is_too_large(buf.len()) {
+            return Err(Error::FrameTooLarge.into());
+        }
+
+        let len = buf.len();
+        Ok(buf.split_to(len))
+    }
+
+    #[inline]
+    fn encode_buf<W: FrameBufMut>(&mut self, item: &[u8], buf: &mut W) -> io::Result<()> {
+        buf.reserve(item.len());
+        buf.put_slice(item);
+        self.delimiter.write_delimiter(buf);
+        Ok(())
     }
 }
 
-impl<D> Codec for DelimiterCodec<D>
-    where D: Delimiter + Clone
+impl<D, O> Codec for DelimiterCodec<D, O>
+    where D: Delimiter + Clone,
+          O: AsRef<[u8]>
 {
     type In = EasyBuf;
-    type Out = Vec<u8>;
+    type Out = O;
 
     #[inline]
     fn decode(&mut self, buf: &mut EasyBuf) -> io::Result<Option<EasyBuf>> {
-        self.0.pop_buf(buf)
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut EasyBuf) -> io::Result<EasyBuf> {
+        self.decode_eof_buf(buf)
     }
 
     #[inline]
-    fn encode(&mut self, item: Vec<u8>, buf: &mut Vec<u8>) -> io::Result<()> {
-        buf.extend_from_slice(item.as_slice());
-        self.0.write_delimiter(buf);
-        Ok(())
+    fn encode(&mut self, item: O, buf: &mut Vec<u8>) -> io::Result<()> {
+        self.encode_buf(item.as_ref(), buf)
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl<D: Delimiter, O> ::tokio_util::codec::Decoder for DelimiterCodec<D, O> {
+    type Item = BytesMut;
+    type Error = io::Error;
+
+    #[inline]
+    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+        if buf.is_empty() {
+            Ok(None)
+        } else {
+            self.decode_eof_buf(buf).map(Some)
+        }
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl<D: Delimiter, O: AsRef<[u8]>> ::tokio_util::codec::Encoder<O> for DelimiterCodec<D, O> {
+    type Error = io::Error;
+
+    #[inline]
+    fn encode(&mut self, item: O, buf: &mut BytesMut) -> io::Result<()> {
+        self.encode_buf(item.as_ref(), buf)
     }
 }
 
 /// A delimiter.
+///
+/// Implementations provide `find` and `write_delimiter`. Delimiters written against the former
+/// interface, providing `pop_buf` instead of `find`, implement [`PopBuf`](./trait.PopBuf.html)
+/// and are used through [`PopBufDelimiter`](./struct.PopBufDelimiter.html).
 pub trait Delimiter {
+    /// Finds the first occurence of this delimiter in `buf`, and returns the start and end
+    /// offsets of the occurence.
+    fn find(&self, buf: &[u8]) -> io::Result<Option<(usize, usize)>>;
+    /// Appends this delimiter to `buf`.
+    fn write_delimiter<W: FrameBufMut>(&self, buf: &mut W);
+
+    /// Same as `find`, but called at the end of the stream, when no more data can complete a
+    /// longer occurence of this delimiter.
+    fn find_eof(&self, buf: &[u8]) -> io::Result<Option<(usize, usize)>> {
+        self.find(buf)
+    }
+
+    /// Returns the maximum length of an occurence of this delimiter.
+    fn max_len(&self) -> usize {
+        let mut v = vec![];
+        self.write_delimiter(&mut v);
+        v.len()
+    }
+
+    /// Returns the offset from which the search can be resumed after `find` found no occurence
+    /// in `buf`, so that the already searched part is not scanned again when more data arrives.
+    ///
+    /// By default, this is the length of `buf` less the bytes that may be a prefix of this
+    /// delimiter.
+    fn resume_at(&self, buf: &[u8]) -> usize {
+        buf.len().saturating_sub(self.max_len().saturating_sub(1))
+    }
+
+    /// Removes elements from `buf` including next occurence of this delimiter,
+    /// and returns the removed part except the delimiter.
+    ///
+    /// By default, this splits `buf` at the occurence returned by `find`.
+    fn pop_buf(&self, buf: &mut EasyBuf) -> io::Result<Option<EasyBuf>> {
+        Ok(match self.find(buf.as_slice())? {
+            Some((start, end)) => Some(split_frame(buf, start, end, false)),
+            None => None,
+        })
+    }
+}
+
+/// A delimiter written against the former interface of `Delimiter`, which pops frames off an
+/// `EasyBuf`.
+pub trait PopBuf {
     /// Removes elements from `buf` including next occurence of this delimiter,
     /// and returns the removed part except the delimiter.
     fn pop_buf(&self, buf: &mut EasyBuf) -> io::Result<Option<EasyBuf>>;
@@ -81,42 +379,124 @@ pub trait Delimiter {
     fn write_delimiter(&self, buf: &mut Vec<u8>);
 }
 
-impl Delimiter for u8 {
-    fn pop_buf(&self, buf: &mut EasyBuf) -> io::Result<Option<EasyBuf>> {
-        let pos = ::memchr::memchr(*self, buf.as_slice());
+/// Adapts a [`PopBuf`](./trait.PopBuf.html) delimiter to `Delimiter`.
+///
+/// Every search copies the buffer, and starts over from its head, as the length of an occurence
+/// of the delimiter is unknown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PopBufDelimiter<D>(pub D);
 
-        Ok(pos.map(move |pos| {
-            let mut fir = buf.drain_to(pos + 1);
+impl<D: PopBuf> Delimiter for PopBufDelimiter<D> {
+    fn find(&self, buf: &[u8]) -> io::Result<Option<(usize, usize)>> {
+        let mut rest = EasyBuf::from(buf.to_vec());
+        Ok(self.0.pop_buf(&mut rest)?.map(|frame| (frame.len(), buf.len() - rest.len())))
+    }
 
-            let len = fir.len();
-            debug_assert!(len > 0);
-            fir.get_mut().truncate(len - 1);
+    fn write_delimiter<W: FrameBufMut>(&self, buf: &mut W) {
+        let mut delimiter = vec![];
+        self.0.write_delimiter(&mut delimiter);
+        buf.put_slice(&delimiter);
+    }
+
+    fn max_len(&self) -> usize {
+        usize::MAX
+    }
+
+    fn resume_at(&self, _buf: &[u8]) -> usize {
+        0
+    }
+}
+
+/// Finds the first occurence of `delimiter` in `buf`, skipping the `searched` head of `buf` known
+/// not to contain its start, and returns the start and end offsets of the occurence. `searched`
+/// is updated for the next call.
+pub(crate) fn find_delimiter<D>(delimiter: &D,
+                                buf: &[u8],
+                                searched: &mut usize,
+                                eof: bool)
+                                -> io::Result<Option<(usize, usize)>>
+    where D: Delimiter + ?Sized
+{
+    let from = cmp::min(*searched, buf.len());
+    let found = if eof {
+        delimiter.find_eof(&buf[from..])?
+    } else {
+        delimiter.find(&buf[from..])?
+    };
+
+    match found {
+        Some((start, end)) => {
+            *searched = 0;
+            Ok(Some((from + start, from + end)))
+        }
+        None => {
+            // no delimiter starts before `searched`, but the tail of `buf` may be a part of the
+            // delimiter
+            *searched = from + delimiter.resume_at(&buf[from..]);
+            Ok(None)
+        }
+    }
+}
 
-            fir
-        }))
+/// Returns the length of the head of `buf` that belongs to the frame being received, after
+/// `find_delimiter` found no occurence of `delimiter`.
+pub(crate) fn unterminated_len<D>(delimiter: &D, buf: &[u8], searched: usize) -> usize
+    where D: Delimiter + ?Sized
+{
+    // a delimiter of unbounded length may span all the unterminated data, so the frame is bounded
+    // by that data instead
+    if delimiter.max_len() == usize::MAX {
+        buf.len()
+    } else {
+        searched
     }
+}
 
-    fn write_delimiter(&self, buf: &mut Vec<u8>) {
-        buf.extend_from_slice(&[*self]);
+/// Splits a frame terminated by the occurence of a delimiter at `start..end` off `buf`.
+fn split_frame<F: FrameBuf>(buf: &mut F, start: usize, end: usize, keep_delimiter: bool) -> F {
+    if keep_delimiter {
+        buf.split_to(end)
+    } else {
+        let frame = buf.split_to(start);
+        buf.split_to(end - start);
+        frame
+    }
+}
+
+impl Delimiter for u8 {
+    fn find(&self, buf: &[u8]) -> io::Result<Option<(usize, usize)>> {
+        Ok(::memchr::memchr(*self, buf).map(|pos| (pos, pos + 1)))
+    }
+
+    fn write_delimiter<W: FrameBufMut>(&self, buf: &mut W) {
+        buf.put_slice(&[*self]);
+    }
+}
+
+/// Validates `buf` as UTF-8, except for an incomplete character at the end, and returns the valid
+/// part.
+fn utf8_prefix(buf: &[u8]) -> io::Result<&str> {
+    match str::from_utf8(buf) {
+        Ok(s) => Ok(s),
+        Err(ref e) if e.error_len().is_none() => Ok(str::from_utf8(&buf[..e.valid_up_to()]).unwrap()),
+        Err(_) => Err(Error::InvalidUtf8.into()),
     }
 }
 
 impl Delimiter for char {
-    fn pop_buf(&self, buf: &mut EasyBuf) -> io::Result<Option<EasyBuf>> {
-        let pos = ::std::str::from_utf8(buf.as_ref())
-            .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?
-            .char_indices()
-            .find(|&(_, c)| c == *self)
-            .map(|(i, _)| i);
+    fn find(&self, buf: &[u8]) -> io::Result<Option<(usize, usize)>> {
+        let pos = utf8_prefix(buf)?.find(*self);
+
+        Ok(pos.map(|pos| (pos, pos + self.len_utf8())))
+    }
 
-        Ok(pos.map(move |pos| {
-            let bs = buf.drain_to(pos);
-            let _ = buf.drain_to(self.len_utf8());
-            bs
-        }))
+    fn resume_at(&self, buf: &[u8]) -> usize {
+        // keep an incomplete character at the end, so that the search resumes at a character
+        // boundary
+        utf8_prefix(buf).map(|s| s.len()).unwrap_or(0)
     }
 
-    fn write_delimiter(&self, buf: &mut Vec<u8>) {
+    fn write_delimiter<W: FrameBufMut>(&self, buf: &mut W) {
         // TODO: use `char::encode_utf8` once it is stabilized
 
         // from rust/src/libcore:
@@ -132,19 +512,19 @@ impl Delimiter for char {
 
         let code = *self as u32;
         if code < MAX_ONE_B {
-            buf.extend_from_slice(&[code as u8]);
+            buf.put_slice(&[code as u8]);
         } else if code < MAX_TWO_B {
-            buf.extend_from_slice(&[(code >> 6 & 0x1F) as u8 | TAG_TWO_B,
-                                    (code & 0x3F) as u8 | TAG_CONT]);
+            buf.put_slice(&[(code >> 6 & 0x1F) as u8 | TAG_TWO_B,
+                            (code & 0x3F) as u8 | TAG_CONT]);
         } else if code < MAX_THREE_B {
-            buf.extend_from_slice(&[(code >> 12 & 0x0F) as u8 | TAG_THREE_B,
-                                    (code >> 6 & 0x3F) as u8 | TAG_CONT,
-                                    (code & 0x3F) as u8 | TAG_CONT]);
+            buf.put_slice(&[(code >> 12 & 0x0F) as u8 | TAG_THREE_B,
+                            (code >> 6 & 0x3F) as u8 | TAG_CONT,
+                            (code & 0x3F) as u8 | TAG_CONT]);
         } else {
-            buf.extend_from_slice(&[(code >> 18 & 0x07) as u8 | TAG_FOUR_B,
-                                    (code >> 12 & 0x3F) as u8 | TAG_CONT,
-                                    (code >> 6 & 0x3F) as u8 | TAG_CONT,
-                                    (code & 0x3F) as u8 | TAG_CONT]);
+            buf.put_slice(&[(code >> 18 & 0x07) as u8 | TAG_FOUR_B,
+                            (code >> 12 & 0x3F) as u8 | TAG_CONT,
+                            (code >> 6 & 0x3F) as u8 | TAG_CONT,
+                            (code & 0x3F) as u8 | TAG_CONT]);
         }
     }
 }
@@ -175,64 +555,249 @@ impl LineDelimiter {
 }
 
 impl Delimiter for LineDelimiter {
-    fn pop_buf(&self, buf: &mut EasyBuf) -> io::Result<Option<EasyBuf>> {
-        self.as_slice().pop_buf(buf)
+    fn find(&self, buf: &[u8]) -> io::Result<Option<(usize, usize)>> {
+        self.as_slice().find(buf)
     }
 
-    fn write_delimiter(&self, buf: &mut Vec<u8>) {
-        buf.extend_from_slice(self.as_slice());
+    fn write_delimiter<W: FrameBufMut>(&self, buf: &mut W) {
+        buf.put_slice(self.as_slice());
     }
 }
 
-impl<'a> Delimiter for &'a [u8] {
-    fn pop_buf(&self, buf: &mut EasyBuf) -> io::Result<Option<EasyBuf>> {
-        if buf.len() < self.len() {
-            return Ok(None);
+/// A line break delimiter matching any of `\r\n`, `\n` and a bare `\r`.
+///
+/// Lines are encoded with the `LineDelimiter` given to `new`. A `\r` at the end of the received
+/// data is not taken as a delimiter until the next byte arrives or the stream ends, as it may be
+/// followed by `\n`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnyLineEnding {
+    encode: LineDelimiter,
+}
+
+impl AnyLineEnding {
+    /// Creates an `AnyLineEnding` which encodes lines with `encode`.
+    pub fn new(encode: LineDelimiter) -> Self {
+        AnyLineEnding { encode }
+    }
+
+    fn find_line(&self, buf: &[u8], eof: bool) -> Option<(usize, usize)> {
+        let pos = ::memchr::memchr2(b'\r', b'\n', buf)?;
+
+        if buf[pos] == b'\n' {
+            return Some((pos, pos + 1));
+        }
+
+        match buf.get(pos + 1) {
+            Some(&b'\n') => Some((pos, pos + 2)),
+            Some(_) => Some((pos, pos + 1)),
+            None if eof => Some((pos, pos + 1)),
+            None => None,
+        }
+    }
+
+    /// Returns the line break at the end of `frame`, which has been decoded with the delimiter
+    /// kept.
+    pub fn matched(&self, frame: &[u8]) -> Option<LineDelimiter> {
+        if frame.ends_with(b"\r\n") {
+            Some(LineDelimiter::CrLf)
+        } else if frame.ends_with(b"\n") {
+            Some(LineDelimiter::Lf)
+        } else if frame.ends_with(b"\r") {
+            Some(LineDelimiter::Cr)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for AnyLineEnding {
+    fn default() -> Self {
+        AnyLineEnding::new(LineDelimiter::CrLf)
+    }
+}
+
+impl Delimiter for AnyLineEnding {
+    fn find(&self, buf: &[u8]) -> io::Result<Option<(usize, usize)>> {
+        Ok(self.find_line(buf, false))
+    }
+
+    fn find_eof(&self, buf: &[u8]) -> io::Result<Option<(usize, usize)>> {
+        Ok(self.find_line(buf, true))
+    }
+
+    fn write_delimiter<W: FrameBufMut>(&self, buf: &mut W) {
+        buf.put_slice(self.encode.as_slice());
+    }
+
+    fn max_len(&self) -> usize {
+        2
+    }
+}
+
+/// A delimiter matching any of several byte sequences.
+///
+/// Frames are split at the earliest occurence of any of the delimiters, preferring the longest
+/// one if several match at the same position. As with
+/// [`AnyLineEnding`](./struct.AnyLineEnding.html), a match is not taken while the received data
+/// may still complete a longer delimiter at the same position.
+///
+/// Encoding appends the first delimiter, or the one selected with [`encode_with`](#method.encode_with).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnyOf {
+    delimiters: Vec<Vec<u8>>,
+    /// Distinct first bytes of `delimiters`
+    first_bytes: Vec<u8>,
+    encode: usize,
+}
+
+impl AnyOf {
+    /// Creates an `AnyOf` from the delimiters.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `delimiters` is empty or contains an empty delimiter.
+    pub fn new<I>(delimiters: I) -> Self
+        where I: IntoIterator,
+              I::Item: AsRef<[u8]>
+    {
+        let delimiters: Vec<Vec<u8>> = delimiters.into_iter().map(|d| d.as_ref().to_vec()).collect();
+        assert!(!delimiters.is_empty(), "no delimiters");
+        assert!(delimiters.iter().all(|d| !d.is_empty()), "empty delimiter");
+
+        let mut first_bytes: Vec<u8> = delimiters.iter().map(|d| d[0]).collect();
+        first_bytes.sort();
+        first_bytes.dedup();
+
+        AnyOf {
+            delimiters,
+            first_bytes,
+            encode: 0,
+        }
+    }
+
+    /// Selects the delimiter at `index` to be used for encoding.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn encode_with(mut self, index: usize) -> Self {
+        assert!(index < self.delimiters.len(), "delimiter index out of bounds");
+        self.encode = index;
+        self
+    }
+
+    /// Returns the index of the delimiter at the end of `frame`, which has been decoded with the
+    /// delimiter kept.
+    pub fn matched(&self, frame: &[u8]) -> Option<usize> {
+        self.delimiters
+            .iter()
+            .enumerate()
+            .filter(|&(_, d)| frame.ends_with(d))
+            .max_by_key(|&(_, d)| d.len())
+            .map(|(i, _)| i)
+    }
+
+    fn next_candidate(&self, buf: &[u8]) -> Option<usize> {
+        let b = &self.first_bytes;
+        match b.len() {
+            1 => ::memchr::memchr(b[0], buf),
+            2 => ::memchr::memchr2(b[0], b[1], buf),
+            3 => ::memchr::memchr3(b[0], b[1], b[2], buf),
+            _ => buf.iter().position(|c| b.binary_search(c).is_ok()),
         }
+    }
+
+    fn find_any(&self, buf: &[u8], eof: bool) -> Option<(usize, usize)> {
+        let mut start = 0;
+
+        while let Some(i) = self.next_candidate(&buf[start..]) {
+            let pos = start + i;
+            let rest = &buf[pos..];
+
+            let mut longest = None;
+            let mut incomplete = false;
+            for d in &self.delimiters {
+                if rest.starts_with(d) {
+                    longest = cmp::max(longest, Some(d.len()));
+                } else if !eof && d.starts_with(rest) {
+                    incomplete = true;
+                }
+            }
 
-        match ::twoway::find_bytes(buf.as_slice(), self) {
-            Some(i) => {
-                let b = buf.drain_to(i);
-                buf.drain_to(self.len());
-                Ok(Some(b))
+            if incomplete {
+                // a longer delimiter may follow once more data arrives
+                return None;
             }
+            if let Some(len) = longest {
+                return Some((pos, pos + len));
+            }
+
+            start = pos + 1;
+        }
+
+        None
+    }
+}
+
+impl Delimiter for AnyOf {
+    fn find(&self, buf: &[u8]) -> io::Result<Option<(usize, usize)>> {
+        Ok(self.find_any(buf, false))
+    }
+
+    fn find_eof(&self, buf: &[u8]) -> io::Result<Option<(usize, usize)>> {
+        Ok(self.find_any(buf, true))
+    }
+
+    fn write_delimiter<W: FrameBufMut>(&self, buf: &mut W) {
+        buf.put_slice(&self.delimiters[self.encode]);
+    }
 
-            None => Ok(None),
+    fn max_len(&self) -> usize {
+        self.delimiters.iter().map(|d| d.len()).max().unwrap()
+    }
+}
+
+impl<'a> Delimiter for &'a [u8] {
+    fn find(&self, buf: &[u8]) -> io::Result<Option<(usize, usize)>> {
+        if buf.len() < self.len() {
+            return Ok(None);
         }
+
+        Ok(::twoway::find_bytes(buf, self).map(|i| (i, i + self.len())))
     }
 
-    fn write_delimiter(&self, buf: &mut Vec<u8>) {
-        buf.extend_from_slice(self);
+    fn write_delimiter<W: FrameBufMut>(&self, buf: &mut W) {
+        buf.put_slice(self);
     }
 }
 
 impl Delimiter for Vec<u8> {
-    fn pop_buf(&self, buf: &mut EasyBuf) -> io::Result<Option<EasyBuf>> {
-        self.as_slice().pop_buf(buf)
+    fn find(&self, buf: &[u8]) -> io::Result<Option<(usize, usize)>> {
+        self.as_slice().find(buf)
     }
 
-    fn write_delimiter(&self, buf: &mut Vec<u8>) {
-        buf.extend_from_slice(self.as_slice());
+    fn write_delimiter<W: FrameBufMut>(&self, buf: &mut W) {
+        buf.put_slice(self.as_slice());
     }
 }
 
 impl<'a> Delimiter for &'a str {
-    fn pop_buf(&self, buf: &mut EasyBuf) -> io::Result<Option<EasyBuf>> {
-        self.as_bytes().pop_buf(buf)
+    fn find(&self, buf: &[u8]) -> io::Result<Option<(usize, usize)>> {
+        self.as_bytes().find(buf)
     }
 
-    fn write_delimiter(&self, buf: &mut Vec<u8>) {
-        buf.extend_from_slice(self.as_bytes());
+    fn write_delimiter<W: FrameBufMut>(&self, buf: &mut W) {
+        buf.put_slice(self.as_bytes());
     }
 }
 
 impl Delimiter for String {
-    fn pop_buf(&self, buf: &mut EasyBuf) -> io::Result<Option<EasyBuf>> {
-        self.as_bytes().pop_buf(buf)
+    fn find(&self, buf: &[u8]) -> io::Result<Option<(usize, usize)>> {
+        self.as_bytes().find(buf)
     }
 
-    fn write_delimiter(&self, buf: &mut Vec<u8>) {
-        buf.extend_from_slice(self.as_bytes());
+    fn write_delimiter<W: FrameBufMut>(&self, buf: &mut W) {
+        buf.put_slice(self.as_bytes());
     }
 }
 
@@ -253,6 +818,178 @@ mod tests {
         assert!(buf.len() == 0);
     }
 
+    #[test]
+    fn test_delimiter_max_frame_length() {
+        let mut p = DelimiterCodec::new(LineDelimiter::CrLf).max_frame_length(3);
+
+        let mut buf = EasyBuf::from(b"abc\r".to_vec());
+        assert!(p.decode(&mut buf).unwrap().is_none());
+        buf.get_mut().extend_from_slice(b"\nabcde");
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), b"abc");
+        let err = p.decode(&mut buf).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::FrameTooLarge));
+
+        let mut p = DelimiterCodec::new(LineDelimiter::CrLf)
+            .max_frame_length(3)
+            .discard_oversized(true);
+
+        let mut buf = EasyBuf::from(b"abcdefg\r".to_vec());
+        assert!(p.decode(&mut buf).unwrap().is_none());
+        assert_eq!(buf.as_slice(), b"\r");
+        buf.get_mut().extend_from_slice(b"hij\r\nabcd\r\nab\r\n");
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), b"ab");
+    }
+
+    #[test]
+    fn test_delimiter_eof() {
+        let mut p = DelimiterCodec::new(b'\n');
+        let mut buf = EasyBuf::from(b"abc\ndef".to_vec());
+        assert_eq!(p.decode_eof(&mut buf).unwrap().as_slice(), b"abc");
+        let err = p.decode_eof(&mut buf).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::UnexpectedEof));
+
+        let mut p = DelimiterCodec::new(b'\n').yield_unterminated(true);
+        let mut buf = EasyBuf::from(b"abc\ndef".to_vec());
+        assert_eq!(p.decode_eof(&mut buf).unwrap().as_slice(), b"abc");
+        assert_eq!(p.decode_eof(&mut buf).unwrap().as_slice(), b"def");
+        assert_eq!(buf.len(), 0);
+    }
+
+    #[test]
+    fn test_delimiter_incremental() {
+        let mut p = DelimiterCodec::new("\r\n");
+        let mut buf = EasyBuf::new();
+        for &b in b"abc\r\r\ndef\r\n" {
+            buf.get_mut().push(b);
+            if let Some(frame) = p.decode(&mut buf).unwrap() {
+                assert_eq!(frame.as_slice(), b"abc\r");
+                break;
+            }
+        }
+        assert_eq!(p.searched, 0);
+
+        let mut p = DelimiterCodec::new('、').max_frame_length(6);
+        let mut buf = EasyBuf::new();
+        let mut frames = vec![];
+        for &b in "あめ、つち、".as_bytes() {
+            buf.get_mut().push(b);
+            if let Some(frame) = p.decode(&mut buf).unwrap() {
+                frames.push(frame.as_slice().to_vec());
+            }
+            assert!(p.searched <= buf.len());
+        }
+        assert_eq!(frames, vec!["あめ".as_bytes(), "つち".as_bytes()]);
+        assert_eq!(buf.len(), 0);
+    }
+
+    #[test]
+    fn test_delimiter_keep_delimiter() {
+        let mut p = DelimiterCodec::new(AnyLineEnding::default())
+            .keep_delimiter(true)
+            .max_frame_length(2);
+        let mut buf = EasyBuf::from(b"ab\r\ncd\ne\rf".to_vec());
+
+        let frame = p.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(frame.as_slice(), b"ab\r\n");
+        assert_eq!(AnyLineEnding::default().matched(frame.as_slice()), Some(LineDelimiter::CrLf));
+        let frame = p.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(frame.as_slice(), b"cd\n");
+        assert_eq!(AnyLineEnding::default().matched(frame.as_slice()), Some(LineDelimiter::Lf));
+        let frame = p.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(frame.as_slice(), b"e\r");
+        assert_eq!(AnyLineEnding::default().matched(frame.as_slice()), Some(LineDelimiter::Cr));
+        assert!(p.decode(&mut buf).unwrap().is_none());
+
+        let d = AnyOf::new(vec!["\n", "\r\n", ";"]);
+        let mut p = DelimiterCodec::new(d.clone()).keep_delimiter(true);
+        let mut buf = EasyBuf::from(b"a\r\nb;".to_vec());
+        let frame = p.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(frame.as_slice(), b"a\r\n");
+        assert_eq!(d.matched(frame.as_slice()), Some(1));
+        let frame = p.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(frame.as_slice(), b"b;");
+        assert_eq!(d.matched(frame.as_slice()), Some(2));
+    }
+
+    #[test]
+    fn test_delimiter_with_out() {
+        let mut p = DelimiterCodec::new(b'\n').with_out::<&'static [u8]>();
+        let mut v = vec![];
+        p.encode(b"abc", &mut v).unwrap();
+        assert_eq!(v, b"abc\n");
+
+        let mut buf = EasyBuf::from(v);
+        let frame = p.decode(&mut buf).unwrap().unwrap();
+        let mut p = p.with_out::<EasyBuf>();
+        let mut v = vec![];
+        p.encode(frame, &mut v).unwrap();
+        assert_eq!(v, b"abc\n");
+    }
+
+    #[cfg(feature = "tokio-util-codec")]
+    #[test]
+    fn test_delimiter_tokio_util() {
+        use tokio_util::codec::{Decoder, Encoder};
+
+        let mut p = DelimiterCodec::new(LineDelimiter::CrLf).yield_unterminated(true);
+
+        let mut buf = BytesMut::new();
+        Encoder::encode(&mut p, b"abc".to_vec(), &mut buf).unwrap();
+        assert_eq!(&buf[..], b"abc\r\n");
+        buf.extend_from_slice(b"def");
+
+        assert_eq!(&Decoder::decode(&mut p, &mut buf).unwrap().unwrap()[..], b"abc");
+        assert!(Decoder::decode(&mut p, &mut buf).unwrap().is_none());
+        assert_eq!(&Decoder::decode_eof(&mut p, &mut buf).unwrap().unwrap()[..], b"def");
+        assert!(Decoder::decode_eof(&mut p, &mut buf).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_delimiter_pop_buf_only() {
+        // a delimiter implemented against the interface without `find`
+        #[derive(Clone)]
+        struct Semicolon;
+
+        impl PopBuf for Semicolon {
+            fn pop_buf(&self, buf: &mut EasyBuf) -> io::Result<Option<EasyBuf>> {
+                let pos = ::memchr::memchr(b';', buf.as_slice());
+                Ok(pos.map(|pos| {
+                    let frame = buf.drain_to(pos);
+                    buf.drain_to(1);
+                    frame
+                }))
+            }
+
+            fn write_delimiter(&self, buf: &mut Vec<u8>) {
+                buf.push(b';');
+            }
+        }
+
+        let d = PopBufDelimiter(Semicolon);
+        assert_eq!(d.find(b"ab;c;").unwrap(), Some((2, 3)));
+        assert_eq!(d.find(b"abc").unwrap(), None);
+
+        let mut p = DelimiterCodec::new(d);
+        let mut buf = EasyBuf::new();
+        let mut frames = vec![];
+        for &b in b"ab;;cde;" {
+            buf.get_mut().push(b);
+            while let Some(frame) = p.decode(&mut buf).unwrap() {
+                frames.push(frame.as_slice().to_vec());
+            }
+        }
+        assert_eq!(frames, vec![b"ab".to_vec(), vec![], b"cde".to_vec()]);
+
+        // the occurence may be longer than the encoded delimiter, so unterminated data is bounded
+        // as a whole
+        let mut p = DelimiterCodec::new(PopBufDelimiter(Semicolon)).max_frame_length(2);
+        let err = p.decode(&mut EasyBuf::from(b"abc".to_vec())).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::FrameTooLarge));
+    }
+
     #[test]
     fn test_delimiter_u8() {
         let d = 0;
@@ -276,6 +1013,14 @@ mod tests {
         assert_eq!(v, "、".as_bytes());
     }
 
+    #[test]
+    fn test_delimiter_char_invalid_utf8() {
+        let mut buf = EasyBuf::from(vec![0xFF, b'a', b'\n']);
+        let err = '\n'.pop_buf(&mut buf).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::InvalidUtf8));
+    }
+
     #[test]
     fn test_delimiter_line() {
         let d = LineDelimiter::CrLf;
@@ -289,6 +1034,60 @@ mod tests {
         assert_eq!(v, b"\r\n");
     }
 
+    #[test]
+    fn test_delimiter_any_line_ending() {
+        let d = AnyLineEnding::new(LineDelimiter::Lf);
+
+        test_delimiter(&d,
+                       "a\r\nb\nc\rd\n\n\r\r\n",
+                       vec!["a", "b", "c", "d", "", "", ""]);
+
+        let mut buf = EasyBuf::from(b"a\r".to_vec());
+        assert!(d.pop_buf(&mut buf).unwrap().is_none());
+        buf.get_mut().extend_from_slice(b"\n");
+        assert_eq!(d.pop_buf(&mut buf).unwrap().unwrap().as_slice(), b"a");
+        assert_eq!(buf.len(), 0);
+
+        let mut v = vec![];
+        d.write_delimiter(&mut v);
+        assert_eq!(v, b"\n");
+    }
+
+    #[test]
+    fn test_delimiter_any_line_ending_eof() {
+        let mut p = DelimiterCodec::new(AnyLineEnding::default()).max_frame_length(1);
+
+        let mut buf = EasyBuf::from(b"a\r".to_vec());
+        assert!(p.decode(&mut buf).unwrap().is_none());
+        assert_eq!(p.decode_eof(&mut buf).unwrap().as_slice(), b"a");
+        assert_eq!(buf.len(), 0);
+
+        let mut v = vec![];
+        p.encode(b"b".to_vec(), &mut v).unwrap();
+        assert_eq!(v, b"b\r\n");
+    }
+
+    #[test]
+    fn test_delimiter_any_of() {
+        let d = AnyOf::new(vec!["\r\n", ";", "\r"]).encode_with(1);
+
+        test_delimiter(&d, "a;b\r\nc\rd;;", vec!["a", "b", "c", "d", ""]);
+
+        let mut buf = EasyBuf::from(b"a\r".to_vec());
+        assert!(d.pop_buf(&mut buf).unwrap().is_none());
+        assert_eq!(d.max_len(), 2);
+
+        let mut v = vec![];
+        d.write_delimiter(&mut v);
+        assert_eq!(v, b";");
+
+        // more first bytes than memchr3 handles
+        let d = AnyOf::new(vec![&b"<>"[..], b"<<>>", b",", b".", b"!"]);
+        test_delimiter(&d,
+                       "a<>b<<>>c,d.e!f<g<<>>",
+                       vec!["a", "b", "c", "d", "e", "f<g"]);
+    }
+
     #[test]
     fn test_delimiter_vec() {
         let d = (&b"#\0#"[..]).to_vec();
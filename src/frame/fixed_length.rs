@@ -1,29 +1,64 @@
 use tokio_core::io::{Codec, Io, EasyBuf, Framed};
 use tokio_proto::pipeline::{ServerProto, ClientProto};
+#[cfg(feature = "tokio-util-codec")]
+use bytes::BytesMut;
+use buf::{FrameBuf, FrameBufMut};
+use error::Error;
+use std::marker::PhantomData;
 use std::io;
 
 /// Fixed-length protocol.
 ///
 /// A protocol such that frames are continuous and have the same specified length.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct FixedLengthProto {
+///
+/// Decoded frames are `EasyBuf`s sharing the read buffer. Wrap this protocol in
+/// [`DecodeToVecProto`](../decode_to_vec/struct.DecodeToVecProto.html) to receive `Vec<u8>`s.
+/// Outgoing frames are `Vec<u8>` by default, and can be any `AsRef<[u8]>` type selected with
+/// [`with_out`](#method.with_out).
+#[derive(Debug, PartialEq, Eq)]
+pub struct FixedLengthProto<O = Vec<u8>> {
     pub length: usize,
+    _out: PhantomData<fn(O)>,
+}
+
+impl<O> Clone for FixedLengthProto<O> {
+    fn clone(&self) -> Self {
+        *self
+    }
 }
 
+impl<O> Copy for FixedLengthProto<O> {}
+
 impl FixedLengthProto {
     pub fn new(length: usize) -> FixedLengthProto {
-        FixedLengthProto { length: length }
+        FixedLengthProto {
+            length,
+            _out: PhantomData,
+        }
+    }
+}
+
+impl<O> FixedLengthProto<O> {
+    /// Changes the type of outgoing frames.
+    pub fn with_out<U: AsRef<[u8]>>(self) -> FixedLengthProto<U> {
+        FixedLengthProto {
+            length: self.length,
+            _out: PhantomData,
+        }
     }
 
-    fn codec(&self) -> FixedLengthCodec {
-        FixedLengthCodec { length: self.length }
+    fn codec(&self) -> FixedLengthCodec<O> {
+        FixedLengthCodec {
+            length: self.length,
+            _out: PhantomData,
+        }
     }
 }
 
-impl<T: Io + 'static> ServerProto<T> for FixedLengthProto {
-    type Request = Vec<u8>;
-    type Response = Vec<u8>;
-    type Transport = Framed<T, FixedLengthCodec>;
+impl<T: Io + 'static, O: AsRef<[u8]> + 'static> ServerProto<T> for FixedLengthProto<O> {
+    type Request = EasyBuf;
+    type Response = O;
+    type Transport = Framed<T, FixedLengthCodec<O>>;
     type BindTransport = io::Result<Self::Transport>;
 
     fn bind_transport(&self, io: T) -> Self::BindTransport {
@@ -31,10 +66,10 @@ impl<T: Io + 'static> ServerProto<T> for FixedLengthProto {
     }
 }
 
-impl<T: Io + 'static> ClientProto<T> for FixedLengthProto {
-    type Request = Vec<u8>;
-    type Response = Vec<u8>;
-    type Transport = Framed<T, FixedLengthCodec>;
+impl<T: Io + 'static, O: AsRef<[u8]> + 'static> ClientProto<T> for FixedLengthProto<O> {
+    type Request = O;
+    type Response = EasyBuf;
+    type Transport = Framed<T, FixedLengthCodec<O>>;
     type BindTransport = io::Result<Self::Transport>;
 
     fn bind_transport(&self, io: T) -> Self::BindTransport {
@@ -42,56 +77,185 @@ impl<T: Io + 'static> ClientProto<T> for FixedLengthProto {
     }
 }
 
+#[cfg(feature = "async-service")]
+impl<O> ::service::NewCodec for FixedLengthProto<O> {
+    type Codec = FixedLengthCodec<O>;
+
+    fn new_codec(&self) -> FixedLengthCodec<O> {
+        self.codec()
+    }
+}
+
 /// Protocol codec used by [`FixedLengthProto`](./struct.FixedLengthProto.html).
-#[derive(Debug, Clone)]
-pub struct FixedLengthCodec {
+///
+/// Encoding an item whose length differs from `length` fails with
+/// [`Error::WrongFrameLength`](../error/enum.Error.html).
+#[derive(Debug)]
+pub struct FixedLengthCodec<O = Vec<u8>> {
     length: usize,
+    _out: PhantomData<fn(O)>,
+}
+
+impl<O> Clone for FixedLengthCodec<O> {
+    fn clone(&self) -> Self {
+        FixedLengthCodec {
+            length: self.length,
+            _out: PhantomData,
+        }
+    }
 }
 
 impl FixedLengthCodec {
     pub fn new(length: usize) -> FixedLengthCodec {
-        FixedLengthCodec { length: length }
+        FixedLengthCodec {
+            length,
+            _out: PhantomData,
+        }
+    }
+}
+
+impl<O> FixedLengthCodec<O> {
+    /// Changes the type of items to be encoded.
+    pub fn with_out<U: AsRef<[u8]>>(self) -> FixedLengthCodec<U> {
+        FixedLengthCodec {
+            length: self.length,
+            _out: PhantomData,
+        }
     }
 
     pub fn length(&self) -> usize {
         self.length
     }
-}
-
-impl Codec for FixedLengthCodec {
-    type In = Vec<u8>;
-    type Out = Vec<u8>;
 
     #[inline]
-    fn decode(&mut self, buf: &mut EasyBuf) -> io::Result<Option<Vec<u8>>> {
+    fn decode_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<Option<F>> {
         Ok(if buf.len() >= self.length {
-            let bs = buf.drain_to(self.length);
-            Some(bs.as_slice().to_vec())
+            Some(buf.split_to(self.length))
         } else {
             None
         })
     }
 
+    fn decode_eof_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<F> {
+        match self.decode_buf(buf)? {
+            Some(frame) => Ok(frame),
+            None => Err(Error::UnexpectedEof.into()),
+        }
+    }
+
     #[inline]
-    fn encode(&mut self, item: Vec<u8>, buf: &mut Vec<u8>) -> io::Result<()> {
-        assert_eq!(item.len(), self.length);
-        buf.extend_from_slice(&item);
+    fn encode_buf<W: FrameBufMut>(&mut self, item: &[u8], buf: &mut W) -> io::Result<()> {
+        if item.len() != self.length {
+            return Err(Error::WrongFrameLength.into());
+        }
+        buf.put_slice(item);
         Ok(())
     }
 }
 
+impl<O: AsRef<[u8]>> Codec for FixedLengthCodec<O> {
+    type In = EasyBuf;
+    type Out = O;
+
+    #[inline]
+    fn decode(&mut self, buf: &mut EasyBuf) -> io::Result<Option<EasyBuf>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut EasyBuf) -> io::Result<EasyBuf> {
+        self.decode_eof_buf(buf)
+    }
+
+    #[inline]
+    fn encode(&mut self, item: O, buf: &mut Vec<u8>) -> io::Result<()> {
+        self.encode_buf(item.as_ref(), buf)
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl<O> ::tokio_util::codec::Decoder for FixedLengthCodec<O> {
+    type Item = BytesMut;
+    type Error = io::Error;
+
+    #[inline]
+    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+        if buf.is_empty() {
+            Ok(None)
+        } else {
+            self.decode_eof_buf(buf).map(Some)
+        }
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl<O: AsRef<[u8]>> ::tokio_util::codec::Encoder<O> for FixedLengthCodec<O> {
+    type Error = io::Error;
+
+    #[inline]
+    fn encode(&mut self, item: O, buf: &mut BytesMut) -> io::Result<()> {
+        self.encode_buf(item.as_ref(), buf)
+    }
+}
+
 #[test]
 fn test_fixed_length() {
-    let mut p = FixedLengthCodec { length: 5 };
+    let mut p = FixedLengthCodec::new(5);
 
     let mut buf = EasyBuf::new();
     buf.get_mut().extend_from_slice(b"abcdefghijkl");
 
-    assert_eq!(p.decode(&mut buf).unwrap(), Some(b"abcde".to_vec()));
-    assert_eq!(p.decode(&mut buf).unwrap(), Some(b"fghij".to_vec()));
+    assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), b"abcde");
+    assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), b"fghij");
     assert!(p.decode(&mut buf).unwrap().is_none());
 
     buf.get_mut().extend_from_slice(b"mno");
 
-    assert_eq!(p.decode(&mut buf).unwrap(), Some(b"klmno".to_vec()));
+    assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), b"klmno");
+}
+
+#[test]
+fn test_fixed_length_wrong_length() {
+    let mut p = FixedLengthCodec::new(5);
+    let mut buf = vec![];
+
+    let err = p.encode(b"abcd".to_vec(), &mut buf).unwrap_err();
+    assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+               Some(&Error::WrongFrameLength));
+    assert!(buf.is_empty());
+
+    p.encode(b"abcde".to_vec(), &mut buf).unwrap();
+    assert_eq!(buf, b"abcde");
+}
+
+#[test]
+fn test_fixed_length_eof() {
+    let mut p = FixedLengthCodec::new(3);
+
+    let mut buf = EasyBuf::new();
+    buf.get_mut().extend_from_slice(b"abcde");
+
+    assert_eq!(p.decode_eof(&mut buf).unwrap().as_slice(), b"abc");
+    let err = p.decode_eof(&mut buf).unwrap_err();
+    assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+               Some(&Error::UnexpectedEof));
+}
+
+#[cfg(feature = "tokio-util-codec")]
+#[test]
+fn test_fixed_length_tokio_util() {
+    use tokio_util::codec::{Decoder, Encoder};
+
+    let mut p = FixedLengthCodec::new(5);
+
+    let mut buf = BytesMut::new();
+    Encoder::encode(&mut p, b"abcde".to_vec(), &mut buf).unwrap();
+    buf.extend_from_slice(b"fgh");
+
+    assert_eq!(&Decoder::decode(&mut p, &mut buf).unwrap().unwrap()[..], b"abcde");
+    assert!(Decoder::decode(&mut p, &mut buf).unwrap().is_none());
+    assert!(Decoder::decode_eof(&mut p, &mut buf).is_err());
 }
@@ -0,0 +1,406 @@
+use tokio_core::io::{Codec, Io, EasyBuf, Framed};
+use tokio_proto::pipeline::{ServerProto, ClientProto};
+#[cfg(feature = "tokio-util-codec")]
+use bytes::BytesMut;
+use buf::{FrameBuf, FrameBufMut};
+use super::stuffing::split_frame;
+use error::Error;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::io;
+
+const FLAG: u8 = 0x7E;
+const ESCAPE: u8 = 0x7D;
+const XOR: u8 = 0x20;
+
+/// Frame check sequence appended to HDLC frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fcs {
+    /// 16-bit FCS (CRC-16/X.25) as in RFC 1662.
+    Fcs16,
+    /// 32-bit FCS (CRC-32) as in RFC 1662.
+    Fcs32,
+}
+
+impl Fcs {
+    fn len(&self) -> usize {
+        match *self {
+            Fcs::Fcs16 => 2,
+            Fcs::Fcs32 => 4,
+        }
+    }
+
+    /// Computes the FCS of `data`, in the order it is transmitted.
+    fn compute(&self, data: &[u8]) -> ([u8; 4], usize) {
+        let mut fcs = [0; 4];
+        match *self {
+            Fcs::Fcs16 => {
+                let crc = !crc16(0xFFFF, data);
+                fcs[0] = crc as u8;
+                fcs[1] = (crc >> 8) as u8;
+            }
+            Fcs::Fcs32 => {
+                let crc = !crc32(0xFFFF_FFFF, data);
+                for (i, b) in fcs.iter_mut().enumerate() {
+                    *b = (crc >> (8 * i)) as u8;
+                }
+            }
+        }
+        (fcs, self.len())
+    }
+
+    /// Checks the FCS at the end of `frame`.
+    fn check(&self, frame: &[u8]) -> bool {
+        match *self {
+            Fcs::Fcs16 => crc16(0xFFFF, frame) == 0xF0B8,
+            Fcs::Fcs32 => crc32(0xFFFF_FFFF, frame) == 0xDEBB_20E3,
+        }
+    }
+}
+
+fn crc16(mut crc: u16, data: &[u8]) -> u16 {
+    for &b in data {
+        crc ^= b as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0x8408 } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+fn crc32(mut crc: u32, data: &[u8]) -> u32 {
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+/// HDLC-like framing protocol (RFC 1662).
+///
+/// A protocol such that frames are delimited with flag bytes (`0x7E`), flag and escape (`0x7D`)
+/// bytes are escaped by `0x7D` followed by the byte XORed with `0x20`, and a frame check sequence
+/// is appended to the payload.
+///
+/// Decoded frames are `Vec<u8>`s without the FCS. Outgoing frames are `Vec<u8>` by default, and can
+/// be any `AsRef<[u8]>` type selected with [`with_out`](#method.with_out).
+#[derive(Debug)]
+pub struct HdlcProto<O = Vec<u8>> {
+    codec: HdlcCodec<O>,
+}
+
+impl<O> Clone for HdlcProto<O> {
+    fn clone(&self) -> Self {
+        HdlcProto { codec: self.codec.clone() }
+    }
+}
+
+impl HdlcProto {
+    pub fn new(fcs: Fcs) -> Self {
+        HdlcProto { codec: HdlcCodec::new(fcs) }
+    }
+}
+
+impl<O> HdlcProto<O> {
+    /// Changes the type of outgoing frames.
+    pub fn with_out<U: AsRef<[u8]>>(self) -> HdlcProto<U> {
+        HdlcProto { codec: self.codec.with_out() }
+    }
+
+    /// Sets the maximum length of an escaped frame, excluding the flags.
+    pub fn max_frame_length(self, max_frame_length: usize) -> Self {
+        HdlcProto { codec: self.codec.max_frame_length(max_frame_length) }
+    }
+
+    /// Returns the number of corrupted frames dropped on all connections of this protocol.
+    pub fn dropped_frames(&self) -> usize {
+        self.codec.dropped_frames()
+    }
+}
+
+impl<T: Io + 'static, O: AsRef<[u8]> + 'static> ServerProto<T> for HdlcProto<O> {
+    type Request = Vec<u8>;
+    type Response = O;
+    type Transport = Framed<T, HdlcCodec<O>>;
+    type BindTransport = io::Result<Self::Transport>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(io.framed(self.codec.clone()))
+    }
+}
+
+impl<T: Io + 'static, O: AsRef<[u8]> + 'static> ClientProto<T> for HdlcProto<O> {
+    type Request = O;
+    type Response = Vec<u8>;
+    type Transport = Framed<T, HdlcCodec<O>>;
+    type BindTransport = io::Result<Self::Transport>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(io.framed(self.codec.clone()))
+    }
+}
+
+#[cfg(feature = "async-service")]
+impl<O> ::service::NewCodec for HdlcProto<O> {
+    type Codec = HdlcCodec<O>;
+
+    fn new_codec(&self) -> HdlcCodec<O> {
+        self.codec.clone()
+    }
+}
+
+/// Protocol codec used by [`HdlcProto`](./struct.HdlcProto.html).
+///
+/// Frames with a wrong FCS, invalid escapes, or too short to contain the FCS are dropped without
+/// failing the decode, and counted in [`dropped_frames`](#method.dropped_frames). Codecs cloned
+/// from each other share the count. Repeated flags between frames are ignored.
+#[derive(Debug)]
+pub struct HdlcCodec<O = Vec<u8>> {
+    fcs: Fcs,
+    max_frame_length: Option<usize>,
+    searched: usize,
+    dropped: Arc<AtomicUsize>,
+    _out: PhantomData<fn(O)>,
+}
+
+impl<O> Clone for HdlcCodec<O> {
+    fn clone(&self) -> Self {
+        HdlcCodec {
+            fcs: self.fcs,
+            max_frame_length: self.max_frame_length,
+            searched: self.searched,
+            dropped: self.dropped.clone(),
+            _out: PhantomData,
+        }
+    }
+}
+
+impl HdlcCodec {
+    pub fn new(fcs: Fcs) -> Self {
+        HdlcCodec {
+            fcs,
+            max_frame_length: None,
+            searched: 0,
+            dropped: Arc::new(AtomicUsize::new(0)),
+            _out: PhantomData,
+        }
+    }
+}
+
+impl<O> HdlcCodec<O> {
+    /// Changes the type of items to be encoded.
+    pub fn with_out<U: AsRef<[u8]>>(self) -> HdlcCodec<U> {
+        HdlcCodec {
+            fcs: self.fcs,
+            max_frame_length: self.max_frame_length,
+            searched: self.searched,
+            dropped: self.dropped,
+            _out: PhantomData,
+        }
+    }
+
+    /// Sets the maximum length of an escaped frame, excluding the flags.
+    pub fn max_frame_length(mut self, max_frame_length: usize) -> Self {
+        self.max_frame_length = Some(max_frame_length);
+        self
+    }
+
+    /// Returns the number of corrupted frames dropped.
+    pub fn dropped_frames(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    fn decode_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<Option<Vec<u8>>> {
+        while let Some(frame) = split_frame(buf, FLAG, &mut self.searched, self.max_frame_length)? {
+            match self.unescape(frame.as_ref()) {
+                Some(frame) => return Ok(Some(frame)),
+                None => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn decode_eof_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<Option<Vec<u8>>> {
+        match self.decode_buf(buf)? {
+            Some(frame) => Ok(Some(frame)),
+            None if buf.is_empty() => Ok(None),
+            None => Err(Error::UnexpectedEof.into()),
+        }
+    }
+
+    /// Unescapes `frame` and strips the FCS, or returns `None` if the frame is corrupted.
+    fn unescape(&self, frame: &[u8]) -> Option<Vec<u8>> {
+        let mut out = Vec::with_capacity(frame.len());
+        let mut iter = frame.iter();
+        while let Some(&b) = iter.next() {
+            if b == ESCAPE {
+                match iter.next() {
+                    Some(&b) => out.push(b ^ XOR),
+                    None => return None,
+                }
+            } else {
+                out.push(b);
+            }
+        }
+
+        if out.len() < self.fcs.len() || !self.fcs.check(&out) {
+            return None;
+        }
+
+        let len = out.len() - self.fcs.len();
+        out.truncate(len);
+        Some(out)
+    }
+
+    fn encode_buf<W: FrameBufMut>(&mut self, item: &[u8], buf: &mut W) -> io::Result<()> {
+        let (fcs, fcs_len) = self.fcs.compute(item);
+
+        buf.reserve(item.len() + fcs_len + 2);
+        buf.put_slice(&[FLAG]);
+        escape(item, buf);
+        escape(&fcs[..fcs_len], buf);
+        buf.put_slice(&[FLAG]);
+        Ok(())
+    }
+}
+
+fn escape<W: FrameBufMut>(data: &[u8], buf: &mut W) {
+    let mut rest = data;
+    while let Some(pos) = rest.iter().position(|&b| b == FLAG || b == ESCAPE) {
+        buf.put_slice(&rest[..pos]);
+        buf.put_slice(&[ESCAPE, rest[pos] ^ XOR]);
+        rest = &rest[pos + 1..];
+    }
+    buf.put_slice(rest);
+}
+
+impl<O: AsRef<[u8]>> Codec for HdlcCodec<O> {
+    type In = Vec<u8>;
+    type Out = O;
+
+    fn decode(&mut self, buf: &mut EasyBuf) -> io::Result<Option<Vec<u8>>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut EasyBuf) -> io::Result<Vec<u8>> {
+        match self.decode_eof_buf(buf)? {
+            Some(frame) => Ok(frame),
+            None => Err(Error::UnexpectedEof.into()),
+        }
+    }
+
+    fn encode(&mut self, item: O, buf: &mut Vec<u8>) -> io::Result<()> {
+        self.encode_buf(item.as_ref(), buf)
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl<O> ::tokio_util::codec::Decoder for HdlcCodec<O> {
+    type Item = Vec<u8>;
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<Vec<u8>>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> io::Result<Option<Vec<u8>>> {
+        self.decode_eof_buf(buf)
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl<O: AsRef<[u8]>> ::tokio_util::codec::Encoder<O> for HdlcCodec<O> {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: O, buf: &mut BytesMut) -> io::Result<()> {
+        self.encode_buf(item.as_ref(), buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::roundtrip;
+
+    #[test]
+    fn test_hdlc_fcs() {
+        // check values of CRC-16/X-25 and CRC-32
+        assert_eq!(Fcs::Fcs16.compute(b"123456789"), ([0x6E, 0x90, 0, 0], 2));
+        assert_eq!(Fcs::Fcs32.compute(b"123456789"), ([0x26, 0x39, 0xF4, 0xCB], 4));
+    }
+
+    #[test]
+    fn test_hdlc() {
+        for &fcs in &[Fcs::Fcs16, Fcs::Fcs32] {
+            let mut p = HdlcCodec::new(fcs);
+
+            let mut v = vec![];
+            p.encode(vec![1, FLAG, 2, ESCAPE], &mut v).unwrap();
+            assert_eq!(&v[..7], &[FLAG, 1, ESCAPE, 0x5E, 2, ESCAPE, 0x5D]);
+            assert_eq!(*v.last().unwrap(), FLAG);
+            p.encode(vec![], &mut v).unwrap();
+
+            let mut buf = EasyBuf::from(vec![FLAG, FLAG]);
+            buf.get_mut().extend_from_slice(&v);
+            assert_eq!(p.decode(&mut buf).unwrap().unwrap(), [1, FLAG, 2, ESCAPE]);
+            assert_eq!(p.decode(&mut buf).unwrap().unwrap(), []);
+            assert!(p.decode(&mut buf).unwrap().is_none());
+            assert_eq!(p.dropped_frames(), 0);
+        }
+    }
+
+    #[test]
+    fn test_hdlc_corrupted() {
+        let mut p = HdlcCodec::new(Fcs::Fcs16);
+        let counter = p.clone();
+
+        let mut good = vec![];
+        p.encode(b"abc".to_vec(), &mut good).unwrap();
+        let mut bad = good.clone();
+        bad[2] ^= 1;
+
+        let mut buf = EasyBuf::from(bad);
+        // too short to contain the FCS
+        buf.get_mut().extend_from_slice(&[0x01, FLAG]);
+        // dangling escape
+        buf.get_mut().extend_from_slice(&[0x01, 0x02, 0x03, ESCAPE, FLAG]);
+        buf.get_mut().extend_from_slice(&good);
+
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap(), b"abc");
+        assert_eq!(counter.dropped_frames(), 3);
+    }
+
+    #[cfg(feature = "tokio-util-codec")]
+    #[test]
+    fn test_hdlc_tokio_util() {
+        use tokio_util::codec::{Decoder, Encoder};
+
+        let mut p = HdlcCodec::new(Fcs::Fcs32);
+
+        let mut v = vec![];
+        Codec::encode(&mut p, vec![1, FLAG, 2, ESCAPE], &mut v).unwrap();
+        let mut buf = BytesMut::new();
+        Encoder::encode(&mut p, vec![1, FLAG, 2, ESCAPE], &mut buf).unwrap();
+        assert_eq!(&buf[..], &v[..]);
+
+        assert_eq!(Decoder::decode(&mut p, &mut buf).unwrap().unwrap(), [1, FLAG, 2, ESCAPE]);
+        assert!(Decoder::decode_eof(&mut p, &mut buf).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_hdlc_roundtrip() {
+        use quickcheck::quickcheck;
+
+        fn prop(frames: Vec<Vec<u8>>, chunk: usize) -> bool {
+            let mut p = HdlcCodec::new(Fcs::Fcs32);
+            roundtrip(&mut p, frames.clone(), chunk % 16 + 1) == frames && p.dropped_frames() == 0
+        }
+
+        quickcheck(prop as fn(Vec<Vec<u8>>, usize) -> bool);
+    }
+}
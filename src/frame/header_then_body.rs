@@ -0,0 +1,427 @@
+use tokio_core::io::{Codec, Io, EasyBuf, Framed};
+use tokio_proto::pipeline::{ServerProto, ClientProto};
+#[cfg(feature = "tokio-util-codec")]
+use bytes::BytesMut;
+use buf::{FrameBuf, FrameBufMut};
+use super::delimiter::{Delimiter, find_delimiter, unterminated_len};
+use super::length_field::adjust_length;
+use error::Error;
+use std::marker::PhantomData;
+use std::{fmt, io};
+
+/// Header-then-body protocol.
+///
+/// A protocol such that every frame consists of a header terminated by a delimiter, followed by a
+/// body whose length is announced in the header. The layout of a frame can be customized in the
+/// same way as the codec (see [`HeaderThenBodyCodec`](./struct.HeaderThenBodyCodec.html)).
+///
+/// Decoded frames are `(header, body)` pairs of `EasyBuf`s sharing the read buffer. Outgoing
+/// frames are pairs of `Vec<u8>` by default, and can be pairs of any `AsRef<[u8]>` type selected
+/// with [`with_out`](#method.with_out).
+pub struct HeaderThenBodyProto<D, L, O = Vec<u8>> {
+    codec: HeaderThenBodyCodec<D, L, O>,
+}
+
+impl<D: fmt::Debug, L, O> fmt::Debug for HeaderThenBodyProto<D, L, O> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HeaderThenBodyProto").field("codec", &self.codec).finish()
+    }
+}
+
+impl<D: Clone, L: Clone, O> Clone for HeaderThenBodyProto<D, L, O> {
+    fn clone(&self) -> Self {
+        HeaderThenBodyProto { codec: self.codec.clone() }
+    }
+}
+
+impl<D, L> HeaderThenBodyProto<D, L>
+    where D: Delimiter,
+          L: Fn(&[u8]) -> io::Result<usize>
+{
+    /// Creates a protocol whose headers are terminated by `delimiter`, and whose body lengths are
+    /// read from the headers by `length`.
+    pub fn new(delimiter: D, length: L) -> Self {
+        HeaderThenBodyProto { codec: HeaderThenBodyCodec::new(delimiter, length) }
+    }
+}
+
+impl<D, L, O> HeaderThenBodyProto<D, L, O> {
+    /// Changes the type of outgoing headers and bodies.
+    pub fn with_out<U: AsRef<[u8]>>(self) -> HeaderThenBodyProto<D, L, U> {
+        HeaderThenBodyProto { codec: self.codec.with_out() }
+    }
+
+    /// Sets the value added to the length read from the header to obtain the length of the body.
+    pub fn length_adjustment(self, length_adjustment: isize) -> Self {
+        HeaderThenBodyProto { codec: self.codec.length_adjustment(length_adjustment) }
+    }
+
+    /// Sets the maximum length of a header, excluding the delimiter.
+    pub fn max_header_length(self, max_header_length: usize) -> Self {
+        HeaderThenBodyProto { codec: self.codec.max_header_length(max_header_length) }
+    }
+
+    /// Sets the maximum length of a body.
+    pub fn max_body_length(self, max_body_length: usize) -> Self {
+        HeaderThenBodyProto { codec: self.codec.max_body_length(max_body_length) }
+    }
+}
+
+impl<T, D, L, O> ServerProto<T> for HeaderThenBodyProto<D, L, O>
+    where T: Io + 'static,
+          D: Delimiter + Clone + 'static,
+          L: Fn(&[u8]) -> io::Result<usize> + Clone + 'static,
+          O: AsRef<[u8]> + 'static
+{
+    type Request = (EasyBuf, EasyBuf);
+    type Response = (O, O);
+    type Transport = Framed<T, HeaderThenBodyCodec<D, L, O>>;
+    type BindTransport = io::Result<Self::Transport>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(io.framed(self.codec.clone()))
+    }
+}
+
+impl<T, D, L, O> ClientProto<T> for HeaderThenBodyProto<D, L, O>
+    where T: Io + 'static,
+          D: Delimiter + Clone + 'static,
+          L: Fn(&[u8]) -> io::Result<usize> + Clone + 'static,
+          O: AsRef<[u8]> + 'static
+{
+    type Request = (O, O);
+    type Response = (EasyBuf, EasyBuf);
+    type Transport = Framed<T, HeaderThenBodyCodec<D, L, O>>;
+    type BindTransport = io::Result<Self::Transport>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(io.framed(self.codec.clone()))
+    }
+}
+
+#[cfg(feature = "async-service")]
+impl<D: Clone, L: Clone, O> ::service::NewCodec for HeaderThenBodyProto<D, L, O> {
+    type Codec = HeaderThenBodyCodec<D, L, O>;
+
+    fn new_codec(&self) -> HeaderThenBodyCodec<D, L, O> {
+        self.codec.clone()
+    }
+}
+
+/// Protocol codec used by [`HeaderThenBodyProto`](./struct.HeaderThenBodyProto.html).
+///
+/// The header is searched for the delimiter in the same way as
+/// [`DelimiterCodec`](./struct.DelimiterCodec.html), and passed without the delimiter to the
+/// `length` closure, whose error fails the decode. As with
+/// [`LengthFieldCodec`](./struct.LengthFieldCodec.html), `length_adjustment` is then added to
+/// obtain the number of bytes of the body, so that a trailer such as the CRLF following a
+/// memcached data block is included in the body.
+///
+/// A header exceeding `max_header_length` or a body exceeding `max_body_length` fails the decode
+/// with [`Error::FrameTooLarge`](../error/enum.Error.html), and a body length made negative by the
+/// adjustment with `Error::InvalidLength`.
+///
+/// Encoding writes the header, the delimiter and the body; the header must announce the length
+/// of the body.
+pub struct HeaderThenBodyCodec<D, L, O = Vec<u8>> {
+    delimiter: D,
+    length: L,
+    length_adjustment: isize,
+    max_header_length: Option<usize>,
+    max_body_length: Option<usize>,
+    /// Length of the head of the buffer known not to contain the delimiter
+    searched: usize,
+    /// Lengths of the header, the header with the delimiter, and the body being received
+    current: Option<(usize, usize, usize)>,
+    _out: PhantomData<fn(O)>,
+}
+
+impl<D: fmt::Debug, L, O> fmt::Debug for HeaderThenBodyCodec<D, L, O> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HeaderThenBodyCodec")
+            .field("delimiter", &self.delimiter)
+            .field("length_adjustment", &self.length_adjustment)
+            .field("max_header_length", &self.max_header_length)
+            .field("max_body_length", &self.max_body_length)
+            .field("searched", &self.searched)
+            .field("current", &self.current)
+            .finish()
+    }
+}
+
+impl<D: Clone, L: Clone, O> Clone for HeaderThenBodyCodec<D, L, O> {
+    fn clone(&self) -> Self {
+        HeaderThenBodyCodec {
+            delimiter: self.delimiter.clone(),
+            length: self.length.clone(),
+            length_adjustment: self.length_adjustment,
+            max_header_length: self.max_header_length,
+            max_body_length: self.max_body_length,
+            searched: self.searched,
+            current: self.current,
+            _out: PhantomData,
+        }
+    }
+}
+
+impl<D, L> HeaderThenBodyCodec<D, L>
+    where D: Delimiter,
+          L: Fn(&[u8]) -> io::Result<usize>
+{
+    /// Creates a codec whose headers are terminated by `delimiter`, and whose body lengths are
+    /// read from the headers by `length`.
+    pub fn new(delimiter: D, length: L) -> Self {
+        HeaderThenBodyCodec {
+            delimiter,
+            length,
+            length_adjustment: 0,
+            max_header_length: None,
+            max_body_length: None,
+            searched: 0,
+            current: None,
+            _out: PhantomData,
+        }
+    }
+}
+
+impl<D, L, O> HeaderThenBodyCodec<D, L, O> {
+    /// Changes the type of headers and bodies to be encoded.
+    pub fn with_out<U: AsRef<[u8]>>(self) -> HeaderThenBodyCodec<D, L, U> {
+        HeaderThenBodyCodec {
+            delimiter: self.delimiter,
+            length: self.length,
+            length_adjustment: self.length_adjustment,
+            max_header_length: self.max_header_length,
+            max_body_length: self.max_body_length,
+            searched: self.searched,
+            current: self.current,
+            _out: PhantomData,
+        }
+    }
+
+    /// Sets the value added to the length read from the header to obtain the length of the body.
+    pub fn length_adjustment(mut self, length_adjustment: isize) -> Self {
+        self.length_adjustment = length_adjustment;
+        self
+    }
+
+    /// Sets the maximum length of a header, excluding the delimiter.
+    pub fn max_header_length(mut self, max_header_length: usize) -> Self {
+        self.max_header_length = Some(max_header_length);
+        self
+    }
+
+    /// Sets the maximum length of a body.
+    pub fn max_body_length(mut self, max_body_length: usize) -> Self {
+        self.max_body_length = Some(max_body_length);
+        self
+    }
+
+    /// Returns the delimiter of this codec.
+    pub fn delimiter(&self) -> &D {
+        &self.delimiter
+    }
+}
+
+impl<D, L, O> HeaderThenBodyCodec<D, L, O>
+    where D: Delimiter,
+          L: Fn(&[u8]) -> io::Result<usize>
+{
+    fn decode_frame<F: FrameBuf>(&mut self, buf: &mut F, eof: bool) -> io::Result<Option<(F, F)>> {
+        if self.current.is_none() {
+            let found = find_delimiter(&self.delimiter, buf.as_ref(), &mut self.searched, eof)?;
+            let (start, end) = match found {
+                Some(found) => found,
+                None => {
+                    let pending = unterminated_len(&self.delimiter, buf.as_ref(), self.searched);
+                    if self.max_header_length.is_some_and(|max| pending > max) {
+                        return Err(Error::FrameTooLarge.into());
+                    }
+                    return Ok(None);
+                }
+            };
+
+            if self.max_header_length.is_some_and(|max| start > max) {
+                return Err(Error::FrameTooLarge.into());
+            }
+
+            let len = (self.length)(&buf.as_ref()[..start])?;
+            let body_len = match adjust_length(len as u64, self.length_adjustment as i64) {
+                Some(l) if l <= usize::MAX as u64 => l as usize,
+                _ => return Err(Error::InvalidLength.into()),
+            };
+            if self.max_body_length.is_some_and(|max| body_len > max) {
+                return Err(Error::FrameTooLarge.into());
+            }
+
+            self.current = Some((start, end, body_len));
+        }
+
+        let (header_len, body_start, body_len) = self.current.unwrap();
+        if buf.len() - body_start < body_len {
+            return Ok(None);
+        }
+
+        self.current = None;
+        let header = buf.split_to(header_len);
+        buf.split_to(body_start - header_len);
+        let body = buf.split_to(body_len);
+        Ok(Some((header, body)))
+    }
+
+    fn decode_eof_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<(F, F)> {
+        match self.decode_frame(buf, true)? {
+            Some(frame) => Ok(frame),
+            None => Err(Error::UnexpectedEof.into()),
+        }
+    }
+
+    fn encode_buf<W: FrameBufMut>(&mut self, header: &[u8], body: &[u8], buf: &mut W)
+                                  -> io::Result<()> {
+        buf.reserve(header.len() + body.len());
+        buf.put_slice(header);
+        self.delimiter.write_delimiter(buf);
+        buf.put_slice(body);
+        Ok(())
+    }
+}
+
+impl<D, L, O> Codec for HeaderThenBodyCodec<D, L, O>
+    where D: Delimiter,
+          L: Fn(&[u8]) -> io::Result<usize>,
+          O: AsRef<[u8]>
+{
+    type In = (EasyBuf, EasyBuf);
+    type Out = (O, O);
+
+    fn decode(&mut self, buf: &mut EasyBuf) -> io::Result<Option<(EasyBuf, EasyBuf)>> {
+        self.decode_frame(buf, false)
+    }
+
+    fn decode_eof(&mut self, buf: &mut EasyBuf) -> io::Result<(EasyBuf, EasyBuf)> {
+        self.decode_eof_buf(buf)
+    }
+
+    fn encode(&mut self, (header, body): (O, O), buf: &mut Vec<u8>) -> io::Result<()> {
+        self.encode_buf(header.as_ref(), body.as_ref(), buf)
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl<D, L, O> ::tokio_util::codec::Decoder for HeaderThenBodyCodec<D, L, O>
+    where D: Delimiter,
+          L: Fn(&[u8]) -> io::Result<usize>
+{
+    type Item = (BytesMut, BytesMut);
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<(BytesMut, BytesMut)>> {
+        self.decode_frame(buf, false)
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> io::Result<Option<(BytesMut, BytesMut)>> {
+        if buf.is_empty() && self.current.is_none() {
+            Ok(None)
+        } else {
+            self.decode_eof_buf(buf).map(Some)
+        }
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl<D, L, O> ::tokio_util::codec::Encoder<(O, O)> for HeaderThenBodyCodec<D, L, O>
+    where D: Delimiter,
+          L: Fn(&[u8]) -> io::Result<usize>,
+          O: AsRef<[u8]>
+{
+    type Error = io::Error;
+
+    fn encode(&mut self, (header, body): (O, O), buf: &mut BytesMut) -> io::Result<()> {
+        self.encode_buf(header.as_ref(), body.as_ref(), buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str;
+
+    /// Reads the last word of a header as the body length.
+    fn last_word(header: &[u8]) -> io::Result<usize> {
+        str::from_utf8(header)
+            .ok()
+            .and_then(|h| h.rsplit(' ').next())
+            .and_then(|w| w.parse().ok())
+            .ok_or_else(|| Error::InvalidLength.into())
+    }
+
+    #[test]
+    fn test_header_then_body() {
+        let mut p = HeaderThenBodyCodec::new("\r\n", last_word).length_adjustment(2);
+
+        let input = b"VALUE k 0 7\r\nab\r\ncde\r\nVALUE j 0 0\r\n\r\n";
+        let mut buf = EasyBuf::new();
+        let mut frames = vec![];
+        for &b in &input[..] {
+            buf.get_mut().push(b);
+            while let Some((header, body)) = p.decode(&mut buf).unwrap() {
+                frames.push((header.as_slice().to_vec(), body.as_slice().to_vec()));
+            }
+        }
+
+        assert_eq!(frames,
+                   vec![(b"VALUE k 0 7".to_vec(), b"ab\r\ncde\r\n".to_vec()),
+                        (b"VALUE j 0 0".to_vec(), b"\r\n".to_vec())]);
+        assert_eq!(buf.len(), 0);
+
+        let mut v = vec![];
+        p.encode((b"VALUE k 0 1".to_vec(), b"a\r\n".to_vec()), &mut v).unwrap();
+        assert_eq!(v, b"VALUE k 0 1\r\na\r\n");
+    }
+
+    #[test]
+    fn test_header_then_body_invalid() {
+        let mut p = HeaderThenBodyCodec::new(b'\n', last_word);
+        let err = p.decode(&mut EasyBuf::from(b"LEN x\n".to_vec())).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::InvalidLength));
+
+        let mut p = HeaderThenBodyCodec::new(b'\n', last_word).length_adjustment(-2);
+        let err = p.decode(&mut EasyBuf::from(b"LEN 1\n".to_vec())).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::InvalidLength));
+
+        let mut p = HeaderThenBodyCodec::new(b'\n', last_word)
+            .max_header_length(5)
+            .max_body_length(3);
+        let err = p.decode(&mut EasyBuf::from(b"LEN 10".to_vec())).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::FrameTooLarge));
+        let err = p.decode(&mut EasyBuf::from(b"LEN 4\n".to_vec())).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::FrameTooLarge));
+
+        let mut p = HeaderThenBodyCodec::new(b'\n', last_word);
+        let err = p.decode_eof(&mut EasyBuf::from(b"LEN 4\nab".to_vec())).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::UnexpectedEof));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_header_then_body_regex() {
+        use frame::RegexDelimiter;
+        use regex::bytes::Regex;
+
+        let d = RegexDelimiter::unbounded(Regex::new(r"\r?\n").unwrap());
+        let mut p = HeaderThenBodyCodec::new(d, last_word).max_header_length(5);
+        let mut buf = EasyBuf::from(b"LEN 2\r\nab".to_vec());
+        let (header, body) = p.decode(&mut buf).unwrap().unwrap();
+        assert_eq!((header.as_slice(), body.as_slice()), (&b"LEN 2"[..], &b"ab"[..]));
+
+        // the delimiter has no maximum length, so the header is bounded by the unterminated data
+        let err = p.decode(&mut EasyBuf::from(b"LEN 10".to_vec())).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::FrameTooLarge));
+    }
+}
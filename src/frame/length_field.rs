@@ -1,41 +1,148 @@
 use tokio_core::io::{Codec, Io, EasyBuf, Framed};
 use tokio_proto::pipeline::{ServerProto, ClientProto};
 use byteorder::ByteOrder;
+#[cfg(feature = "tokio-util-codec")]
+use bytes::BytesMut;
+use buf::{FrameBuf, FrameBufMut};
+use error::Error;
 use std::marker::PhantomData;
-use std::io;
+use std::{cmp, io};
 
 /// Length field prepending protocol.
 ///
 /// A protocol such that every frame has length field prepended in specified size and byte-order.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct LengthFieldProto<B> {
+///
+/// The layout of a frame can be customized in the same way as the codec (see
+/// [`LengthFieldCodec`](./struct.LengthFieldCodec.html)).
+///
+/// Decoded frames are `EasyBuf`s sharing the read buffer. Wrap this protocol in
+/// [`DecodeToVecProto`](../decode_to_vec/struct.DecodeToVecProto.html) to receive `Vec<u8>`s.
+/// Outgoing frames are `Vec<u8>` by default, and can be any `AsRef<[u8]>` type selected with
+/// [`with_out`](#method.with_out).
+#[derive(Debug, PartialEq, Eq)]
+pub struct LengthFieldProto<B, O = Vec<u8>> {
     pub field_size: usize,
-    _byteorder: PhantomData<B>,
+    codec: LengthFieldCodec<B, O>,
 }
 
+impl<B, O> Clone for LengthFieldProto<B, O> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<B, O> Copy for LengthFieldProto<B, O> {}
+
 impl<B> LengthFieldProto<B> {
     pub fn new(field_size: usize) -> Self {
-        assert!(field_size <= ::std::mem::size_of::<usize>());
+        LengthFieldProto::with_encoding(field_size, LengthEncoding::Binary)
+    }
 
+    /// Creates a protocol whose length field is `field_size` bytes in `encoding`.
+    pub fn with_encoding(field_size: usize, encoding: LengthEncoding) -> Self {
         LengthFieldProto {
-            field_size: field_size,
-            _byteorder: PhantomData,
+            field_size,
+            codec: LengthFieldCodec::with_encoding(field_size, encoding),
         }
     }
+}
 
-    fn codec(&self) -> LengthFieldCodec<B> {
-        LengthFieldCodec {
+impl<B, O> LengthFieldProto<B, O> {
+    /// Changes the type of outgoing frames.
+    pub fn with_out<U: AsRef<[u8]>>(self) -> LengthFieldProto<B, U> {
+        LengthFieldProto {
             field_size: self.field_size,
-            current_len: None,
-            _byteorder: PhantomData,
+            codec: self.codec.with_out(),
         }
     }
+
+    /// Sets the number of bytes preceding the length field.
+    pub fn field_offset(self, field_offset: usize) -> Self {
+        LengthFieldProto {
+            field_size: self.field_size,
+            codec: self.codec.field_offset(field_offset),
+        }
+    }
+
+    /// Sets the value added to the length field to obtain the number of bytes following it.
+    pub fn length_adjustment(self, length_adjustment: isize) -> Self {
+        LengthFieldProto {
+            field_size: self.field_size,
+            codec: self.codec.length_adjustment(length_adjustment),
+        }
+    }
+
+    /// Sets whether the length field counts the whole frame, including the header.
+    pub fn inclusive(self, inclusive: bool) -> Self {
+        LengthFieldProto {
+            field_size: self.field_size,
+            codec: self.codec.inclusive(inclusive),
+        }
+    }
+
+    /// Sets the number of bytes removed from the beginning of each decoded frame.
+    pub fn strip(self, strip: usize) -> Self {
+        LengthFieldProto {
+            field_size: self.field_size,
+            codec: self.codec.strip(strip),
+        }
+    }
+
+    /// Sets the maximum length of a frame, including the header and the length field.
+    pub fn max_frame_length(self, max_frame_length: usize) -> Self {
+        LengthFieldProto {
+            field_size: self.field_size,
+            codec: self.codec.max_frame_length(max_frame_length),
+        }
+    }
+
+    /// Sets whether oversized frames are discarded instead of failing the decode.
+    pub fn discard_oversized(self, discard_oversized: bool) -> Self {
+        LengthFieldProto {
+            field_size: self.field_size,
+            codec: self.codec.discard_oversized(discard_oversized),
+        }
+    }
+
+    /// Sets whether the length field is a signed integer.
+    pub fn signed(self, signed: bool) -> Self {
+        LengthFieldProto {
+            field_size: self.field_size,
+            codec: self.codec.signed(signed),
+        }
+    }
+
+    /// Sets how frames with a negative length are handled.
+    pub fn negative_length(self, negative_length: NegativeLength) -> Self {
+        LengthFieldProto {
+            field_size: self.field_size,
+            codec: self.codec.negative_length(negative_length),
+        }
+    }
+
+    /// Sets the bits of the length field holding the length.
+    pub fn length_mask(self, length_mask: u64) -> Self {
+        LengthFieldProto {
+            field_size: self.field_size,
+            codec: self.codec.length_mask(length_mask),
+        }
+    }
+
+    fn codec(&self) -> LengthFieldCodec<B, O> {
+        assert!(self.field_size <= self.codec.encoding.max_field_size());
+
+        LengthFieldCodec { field_size: self.field_size, ..self.codec }
+    }
 }
 
-impl<B: ByteOrder + 'static, T: Io + 'static> ServerProto<T> for LengthFieldProto<B> {
-    type Request = Vec<u8>;
-    type Response = Vec<u8>;
-    type Transport = Framed<T, LengthFieldCodec<B>>;
+impl<B, O, T> ServerProto<T> for LengthFieldProto<B, O>
+    where B: ByteOrder + 'static,
+          O: AsRef<[u8]> + 'static,
+          T: Io + 'static
+{
+    type Request = EasyBuf;
+    type Response = O;
+    type Transport = Framed<T, LengthFieldCodec<B, O>>;
     type BindTransport = io::Result<Self::Transport>;
 
     fn bind_transport(&self, io: T) -> Self::BindTransport {
@@ -43,10 +150,14 @@ impl<B: ByteOrder + 'static, T: Io + 'static> ServerProto<T> for LengthFieldProt
     }
 }
 
-impl<B: ByteOrder + 'static, T: Io + 'static> ClientProto<T> for LengthFieldProto<B> {
-    type Request = Vec<u8>;
-    type Response = Vec<u8>;
-    type Transport = Framed<T, LengthFieldCodec<B>>;
+impl<B, O, T> ClientProto<T> for LengthFieldProto<B, O>
+    where B: ByteOrder + 'static,
+          O: AsRef<[u8]> + 'static,
+          T: Io + 'static
+{
+    type Request = O;
+    type Response = EasyBuf;
+    type Transport = Framed<T, LengthFieldCodec<B, O>>;
     type BindTransport = io::Result<Self::Transport>;
 
     fn bind_transport(&self, io: T) -> Self::BindTransport {
@@ -54,58 +165,534 @@ impl<B: ByteOrder + 'static, T: Io + 'static> ClientProto<T> for LengthFieldProt
     }
 }
 
+#[cfg(feature = "async-service")]
+impl<B, O> ::service::NewCodec for LengthFieldProto<B, O> {
+    type Codec = LengthFieldCodec<B, O>;
+
+    fn new_codec(&self) -> LengthFieldCodec<B, O> {
+        self.codec()
+    }
+}
+
+/// Encoding of the value of a length field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthEncoding {
+    /// Unsigned binary integer in the byte order of the codec.
+    Binary,
+    /// ASCII decimal digits, padded with leading zeros, such as `"0042"`.
+    Decimal,
+    /// ASCII hexadecimal digits, padded with leading zeros. Upper- and lowercase digits are
+    /// accepted, and uppercase ones are written.
+    Hex,
+    /// Packed binary-coded decimal, two digits per byte, most significant digit first.
+    Bcd,
+}
+
+impl LengthEncoding {
+    /// The maximum length of a field in this encoding whose value fits in a `u64`.
+    fn max_field_size(&self) -> usize {
+        match *self {
+            LengthEncoding::Binary => 8,
+            LengthEncoding::Decimal => 20,
+            LengthEncoding::Hex => 16,
+            LengthEncoding::Bcd => 10,
+        }
+    }
+}
+
+/// Handling of negative values of a [`signed`](./struct.LengthFieldCodec.html#method.signed)
+/// length field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NegativeLength {
+    /// Fails the decode with `Error::InvalidLength`.
+    Reject,
+    /// Yields a frame consisting of the header and the length field only.
+    NoBody,
+    /// Discards the header and the length field, and yields nothing.
+    Skip,
+}
+
 /// Protocol codec used by [`LengthFieldProto`](./struct.LengthFieldProto.html).
-#[derive(Debug, Clone)]
-pub struct LengthFieldCodec<B> {
+///
+/// A frame consists of `field_offset` bytes of header, the length field, and the bytes counted
+/// by the length field:
+///
+/// ```text
+/// +--------------------+--------------+-------------------------------------+
+/// | header             | length field | body                                |
+/// | field_offset bytes | field_size   | length + length_adjustment bytes    |
+/// +--------------------+--------------+-------------------------------------+
+/// ```
+///
+/// When the codec is `inclusive`, the length field counts the whole frame, including the header
+/// and the length field itself. The first `strip` bytes of a frame are removed before it is
+/// yielded; by default, everything up to the end of the length field is stripped.
+///
+/// On encoding, the length field is inserted into the item at `field_offset`.
+///
+/// The length field is a binary integer by default. Codecs created with
+/// [`with_encoding`](#method.with_encoding) read and write it as ASCII digits or BCD instead, in
+/// which case the byte order `B` is unused. A field containing an invalid digit fails the decode
+/// with `Error::InvalidLength`.
+///
+/// A binary length field may be [`signed`](#method.signed), with negative values handled as set
+/// by [`negative_length`](#method.negative_length). The length may also occupy only some bits of
+/// the field, selected by [`length_mask`](#method.length_mask), such as a 24-bit length next to an
+/// 8-bit type in a 32-bit word. In that case the field is kept in decoded frames by default, the
+/// other bits being read with [`header_bits`](#method.header_bits), and items to be encoded must
+/// contain the field, whose length bits are filled in by the codec.
+///
+/// If a frame exceeds `max_frame_length`, decoding fails with
+/// [`Error::FrameTooLarge`](../error/enum.Error.html), or the frame is skipped if the codec is set
+/// to `discard_oversized`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct LengthFieldCodec<B, O = Vec<u8>> {
     field_size: usize,
+    encoding: LengthEncoding,
+    field_offset: usize,
+    length_adjustment: isize,
+    inclusive: bool,
+    strip: Option<usize>,
+    max_frame_length: Option<usize>,
+    discard_oversized: bool,
+    signed: bool,
+    negative_length: NegativeLength,
+    length_mask: Option<u64>,
     current_len: Option<usize>,
+    discarding: usize,
     _byteorder: PhantomData<B>,
+    _out: PhantomData<fn(O)>,
+}
+
+impl<B, O> Clone for LengthFieldCodec<B, O> {
+    fn clone(&self) -> Self {
+        *self
+    }
 }
 
+impl<B, O> Copy for LengthFieldCodec<B, O> {}
+
 impl<B> LengthFieldCodec<B> {
     pub fn new(field_size: usize) -> LengthFieldCodec<B> {
-        assert!(field_size <= ::std::mem::size_of::<usize>());
+        LengthFieldCodec::with_encoding(field_size, LengthEncoding::Binary)
+    }
+
+    /// Creates a codec whose length field is `field_size` bytes in `encoding`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value of a field of `field_size` bytes may not fit in `u64`.
+    pub fn with_encoding(field_size: usize, encoding: LengthEncoding) -> LengthFieldCodec<B> {
+        assert!(field_size <= encoding.max_field_size());
 
         LengthFieldCodec {
-            field_size: field_size,
+            field_size,
+            encoding,
+            field_offset: 0,
+            length_adjustment: 0,
+            inclusive: false,
+            strip: None,
+            max_frame_length: None,
+            discard_oversized: false,
+            signed: false,
+            negative_length: NegativeLength::Reject,
+            length_mask: None,
             current_len: None,
+            discarding: 0,
             _byteorder: PhantomData,
+            _out: PhantomData,
         }
     }
 }
 
-impl<B: ByteOrder> Codec for LengthFieldCodec<B> {
-    type In = Vec<u8>;
-    type Out = Vec<u8>;
+impl<B, O> LengthFieldCodec<B, O> {
+    /// Changes the type of items to be encoded.
+    pub fn with_out<U: AsRef<[u8]>>(self) -> LengthFieldCodec<B, U> {
+        LengthFieldCodec {
+            field_size: self.field_size,
+            encoding: self.encoding,
+            field_offset: self.field_offset,
+            length_adjustment: self.length_adjustment,
+            inclusive: self.inclusive,
+            strip: self.strip,
+            max_frame_length: self.max_frame_length,
+            discard_oversized: self.discard_oversized,
+            signed: self.signed,
+            negative_length: self.negative_length,
+            length_mask: self.length_mask,
+            current_len: self.current_len,
+            discarding: self.discarding,
+            _byteorder: PhantomData,
+            _out: PhantomData,
+        }
+    }
 
-    #[inline]
-    fn decode(&mut self, buf: &mut EasyBuf) -> io::Result<Option<Vec<u8>>> {
-        if self.current_len.is_none() && buf.len() >= self.field_size {
-            let bs = buf.drain_to(self.field_size);
-            self.current_len = Some(B::read_uint(bs.as_slice(), self.field_size) as usize);
+    /// Sets the number of bytes preceding the length field.
+    pub fn field_offset(mut self, field_offset: usize) -> Self {
+        self.field_offset = field_offset;
+        self
+    }
+
+    /// Sets the value added to the length field to obtain the number of bytes following it.
+    pub fn length_adjustment(mut self, length_adjustment: isize) -> Self {
+        self.length_adjustment = length_adjustment;
+        self
+    }
+
+    /// Sets whether the length field counts the whole frame, including the header.
+    pub fn inclusive(mut self, inclusive: bool) -> Self {
+        self.inclusive = inclusive;
+        self
+    }
+
+    /// Sets the number of bytes removed from the beginning of each decoded frame.
+    pub fn strip(mut self, strip: usize) -> Self {
+        self.strip = Some(strip);
+        self
+    }
+
+    /// Sets the maximum length of a frame, including the header and the length field.
+    pub fn max_frame_length(mut self, max_frame_length: usize) -> Self {
+        self.max_frame_length = Some(max_frame_length);
+        self
+    }
+
+    /// Sets whether oversized frames are discarded instead of failing the decode.
+    pub fn discard_oversized(mut self, discard_oversized: bool) -> Self {
+        self.discard_oversized = discard_oversized;
+        self
+    }
+
+    /// Sets whether the length field is a signed integer, whose sign bit is the most significant
+    /// bit of the field or of the length mask. Meant for binary length fields.
+    pub fn signed(mut self, signed: bool) -> Self {
+        self.signed = signed;
+        self
+    }
+
+    /// Sets how frames with a negative length are handled. Defaults to `NegativeLength::Reject`.
+    pub fn negative_length(mut self, negative_length: NegativeLength) -> Self {
+        self.negative_length = negative_length;
+        self
+    }
+
+    /// Sets the bits of the length field holding the length. The length is shifted right by the
+    /// number of trailing zeros of `length_mask`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `length_mask` is zero.
+    pub fn length_mask(mut self, length_mask: u64) -> Self {
+        assert!(length_mask != 0);
+        self.length_mask = Some(length_mask);
+        self
+    }
+
+    /// Returns the bits of the length field outside the length mask, from a decoded frame
+    /// beginning with the length field.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frame` is shorter than the length field.
+    pub fn header_bits(&self, frame: &[u8]) -> u64
+        where B: ByteOrder
+    {
+        let raw = B::read_uint(&frame[..self.field_size], self.field_size);
+        raw & !self.length_mask.unwrap_or(!0)
+    }
+
+    fn header_len(&self) -> usize {
+        self.field_offset + self.field_size
+    }
+
+    fn strip_len(&self) -> usize {
+        let default = if self.length_mask.is_some() {
+            self.field_offset
+        } else {
+            self.header_len()
+        };
+        self.strip.unwrap_or(default)
+    }
+
+    /// The shift and the width in bits of the length within the length field.
+    fn length_bits(&self) -> (u32, u32) {
+        match self.length_mask {
+            Some(mask) => {
+                let shift = mask.trailing_zeros();
+                (shift, 64 - (mask >> shift).leading_zeros())
+            }
+            None => (0, self.field_size as u32 * 8),
+        }
+    }
+
+    /// The largest length which can be written into the length field.
+    fn max_length(&self) -> u64 {
+        let (_, width) = self.length_bits();
+        let width = if self.signed { width.saturating_sub(1) } else { width };
+        if width >= 64 { !0 } else { (1 << width) - 1 }
+    }
+
+    /// The value to be added to the length field to obtain the number of bytes following it.
+    fn effective_adjustment(&self) -> i64 {
+        if self.inclusive {
+            self.length_adjustment as i64 - self.header_len() as i64
+        } else {
+            self.length_adjustment as i64
+        }
+    }
+
+    /// Computes the length of a whole frame from the value of its length field.
+    fn frame_len(&self, len: u64) -> io::Result<usize> {
+        let body = adjust_length(len, self.effective_adjustment());
+
+        match body.and_then(|b| b.checked_add(self.header_len() as u64)) {
+            Some(l) if l <= usize::MAX as u64 && l as usize >= self.strip_len() => {
+                Ok(l as usize)
+            }
+            _ => Err(Error::InvalidLength.into()),
         }
+    }
+}
 
-        if let Some(cl) = self.current_len {
-            if buf.len() >= cl {
-                let bs = buf.drain_to(cl);
-                self.current_len = None;
-                return Ok(Some(bs.as_slice().to_vec()));
+/// Adds `adjustment` to the value `len` of a length field, or returns `None` on overflow.
+pub(crate) fn adjust_length(len: u64, adjustment: i64) -> Option<u64> {
+    if adjustment >= 0 {
+        len.checked_add(adjustment as u64)
+    } else {
+        len.checked_sub((adjustment as u64).wrapping_neg())
+    }
+}
+
+impl<B: ByteOrder, O> LengthFieldCodec<B, O> {
+    fn read_len(&self, field: &[u8]) -> io::Result<u64> {
+        let radix = match self.encoding {
+            LengthEncoding::Binary => return Ok(B::read_uint(field, self.field_size)),
+            LengthEncoding::Decimal => 10,
+            LengthEncoding::Hex => 16,
+            LengthEncoding::Bcd => {
+                let mut len: u64 = 0;
+                for &b in field {
+                    let (hi, lo) = (b >> 4, b & 0x0F);
+                    if hi > 9 || lo > 9 {
+                        return Err(Error::InvalidLength.into());
+                    }
+                    let digits = (hi * 10 + lo) as u64;
+                    len = match len.checked_mul(100).and_then(|l| l.checked_add(digits)) {
+                        Some(l) => l,
+                        None => return Err(Error::InvalidLength.into()),
+                    };
+                }
+                return Ok(len);
             }
+        };
+
+        let mut len: u64 = 0;
+        for &b in field {
+            let digit = match (b as char).to_digit(radix) {
+                Some(d) => d as u64,
+                None => return Err(Error::InvalidLength.into()),
+            };
+            len = match len.checked_mul(radix as u64).and_then(|l| l.checked_add(digit)) {
+                Some(l) => l,
+                None => return Err(Error::InvalidLength.into()),
+            };
         }
+        Ok(len)
+    }
 
-        Ok(None)
+    /// Reads the length from the length field, or returns `None` if it is negative.
+    fn read_length(&self, field: &[u8]) -> io::Result<Option<u64>> {
+        let raw = self.read_len(field)?;
+        let (shift, width) = self.length_bits();
+        let len = match self.length_mask {
+            Some(mask) => (raw & mask) >> shift,
+            None => raw,
+        };
+
+        if self.signed && width > 0 && (len >> (width - 1)) & 1 == 1 {
+            return Ok(None);
+        }
+        Ok(Some(len))
+    }
+
+    /// Writes `len` into `field`, or returns `false` if it does not fit.
+    fn write_len(&self, mut len: u64, field: &mut [u8]) -> bool {
+        let radix = match self.encoding {
+            LengthEncoding::Binary => {
+                if self.field_size < 8 && len >= 1 << (self.field_size * 8) {
+                    return false;
+                }
+                B::write_uint(field, len, self.field_size);
+                return true;
+            }
+            LengthEncoding::Decimal => 10,
+            LengthEncoding::Hex => 16,
+            LengthEncoding::Bcd => {
+                for b in field.iter_mut().rev() {
+                    *b = (((len / 10 % 10) << 4) | (len % 10)) as u8;
+                    len /= 100;
+                }
+                return len == 0;
+            }
+        };
+
+        for b in field.iter_mut().rev() {
+            *b = b"0123456789ABCDEF"[(len % radix) as usize];
+            len /= radix;
+        }
+        len == 0
     }
 
     #[inline]
-    fn encode(&mut self, item: Vec<u8>, buf: &mut Vec<u8>) -> io::Result<()> {
-        let mut s = [0; 8];
-        B::write_uint(&mut s[..], item.len() as u64, self.field_size);
-        buf.extend_from_slice(&s[..self.field_size]);
-        buf.extend_from_slice(&item);
+    fn decode_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<Option<F>> {
+        loop {
+            if self.discarding > 0 {
+                let n = cmp::min(self.discarding, buf.len());
+                buf.split_to(n);
+                self.discarding -= n;
+                if self.discarding > 0 {
+                    return Ok(None);
+                }
+            }
+
+            if self.current_len.is_none() && buf.len() >= self.header_len() {
+                let frame_len = match self.read_length(&buf.as_ref()[self.field_offset..
+                                                                       self.header_len()])? {
+                    Some(len) => self.frame_len(len)?,
+                    None => {
+                        match self.negative_length {
+                            NegativeLength::Reject => return Err(Error::InvalidLength.into()),
+                            NegativeLength::NoBody if self.strip_len() <= self.header_len() => {
+                                self.header_len()
+                            }
+                            NegativeLength::NoBody => return Err(Error::InvalidLength.into()),
+                            NegativeLength::Skip => {
+                                self.discarding = self.header_len();
+                                continue;
+                            }
+                        }
+                    }
+                };
+
+                if self.max_frame_length.is_some_and(|max| frame_len > max) {
+                    if !self.discard_oversized {
+                        return Err(Error::FrameTooLarge.into());
+                    }
+                    self.discarding = frame_len;
+                    continue;
+                }
+
+                self.current_len = Some(frame_len);
+            }
+
+            if let Some(cl) = self.current_len {
+                if buf.len() >= cl {
+                    let mut bs = buf.split_to(cl);
+                    bs.split_to(self.strip_len());
+                    self.current_len = None;
+                    return Ok(Some(bs));
+                }
+            }
+
+            return Ok(None);
+        }
+    }
+
+    fn decode_eof_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<F> {
+        match self.decode_buf(buf)? {
+            Some(frame) => Ok(frame),
+            None => Err(Error::UnexpectedEof.into()),
+        }
+    }
+
+    #[inline]
+    fn encode_buf<W: FrameBufMut>(&mut self, item: &[u8], buf: &mut W) -> io::Result<()> {
+        // with a length mask, the item contains the length field to be filled in
+        let body_start = if self.length_mask.is_some() {
+            self.header_len()
+        } else {
+            self.field_offset
+        };
+        if item.len() < body_start {
+            return Err(Error::WrongFrameLength.into());
+        }
+
+        let len = (item.len() - body_start) as i64 - self.effective_adjustment();
+        if len < 0 {
+            return Err(Error::WrongFrameLength.into());
+        }
+        if len as u64 > self.max_length() {
+            return Err(Error::FrameTooLarge.into());
+        }
+
+        let raw = match self.length_mask {
+            Some(mask) => {
+                let (shift, _) = self.length_bits();
+                let raw = self.read_len(&item[self.field_offset..body_start])?;
+                raw & !mask | (len as u64) << shift & mask
+            }
+            None => len as u64,
+        };
+
+        let mut s = [0; 20];
+        if !self.write_len(raw, &mut s[..self.field_size]) {
+            return Err(Error::FrameTooLarge.into());
+        }
+        buf.put_slice(&item[..self.field_offset]);
+        buf.put_slice(&s[..self.field_size]);
+        buf.put_slice(&item[body_start..]);
         Ok(())
     }
 }
 
+impl<B: ByteOrder, O: AsRef<[u8]>> Codec for LengthFieldCodec<B, O> {
+    type In = EasyBuf;
+    type Out = O;
+
+    #[inline]
+    fn decode(&mut self, buf: &mut EasyBuf) -> io::Result<Option<EasyBuf>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut EasyBuf) -> io::Result<EasyBuf> {
+        self.decode_eof_buf(buf)
+    }
+
+    #[inline]
+    fn encode(&mut self, item: O, buf: &mut Vec<u8>) -> io::Result<()> {
+        self.encode_buf(item.as_ref(), buf)
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl<B: ByteOrder, O> ::tokio_util::codec::Decoder for LengthFieldCodec<B, O> {
+    type Item = BytesMut;
+    type Error = io::Error;
+
+    #[inline]
+    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+        if buf.is_empty() {
+            Ok(None)
+        } else {
+            self.decode_eof_buf(buf).map(Some)
+        }
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl<B: ByteOrder, O: AsRef<[u8]>> ::tokio_util::codec::Encoder<O> for LengthFieldCodec<B, O> {
+    type Error = io::Error;
+
+    #[inline]
+    fn encode(&mut self, item: O, buf: &mut BytesMut) -> io::Result<()> {
+        self.encode_buf(item.as_ref(), buf)
+    }
+}
+
 #[test]
 fn test_length_field() {
     use byteorder::BigEndian;
@@ -125,8 +712,280 @@ fn test_length_field() {
 
     let mut p: LengthFieldCodec<BigEndian> = LengthFieldCodec::new(mem::size_of::<u16>());
 
-    assert_eq!(p.decode(&mut buf).unwrap(), Some(b"abc".to_vec()));
-    assert_eq!(p.decode(&mut buf).unwrap(), Some(b"def".to_vec()));
-    assert_eq!(p.decode(&mut buf).unwrap(), Some(vec![]));
+    assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), b"abc");
+    assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), b"def");
+    assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), b"");
     assert!(p.decode(&mut buf).unwrap().is_none());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::BigEndian;
+
+    fn test_layout(mut p: LengthFieldCodec<BigEndian>, frame: &[u8], decoded: &[u8]) {
+        let mut buf = EasyBuf::new();
+        for &b in frame {
+            assert!(p.decode(&mut buf).unwrap().is_none());
+            buf.get_mut().push(b);
+        }
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), decoded);
+        assert_eq!(buf.len(), 0);
+    }
+
+    #[test]
+    fn test_length_field_proto_field_size() {
+        let mut p = LengthFieldProto::<BigEndian>::new(2).length_adjustment(1);
+        assert_eq!(p.field_size, 2);
+        p.field_size = 1;
+        test_layout(p.codec(), b"\x02abc", b"abc");
+    }
+
+    #[test]
+    fn test_length_field_no_strip() {
+        let p = LengthFieldCodec::new(2).strip(0);
+        test_layout(p, b"\x00\x03abc", b"\x00\x03abc");
+    }
+
+    #[test]
+    fn test_length_field_inclusive() {
+        let p = LengthFieldCodec::new(2).inclusive(true);
+        test_layout(p, b"\x00\x05abc", b"abc");
+
+        let p = LengthFieldCodec::new(2).length_adjustment(-2);
+        test_layout(p, b"\x00\x05abc", b"abc");
+    }
+
+    #[test]
+    fn test_length_field_offset() {
+        let p = LengthFieldCodec::new(2).field_offset(2);
+        test_layout(p, b"\xCA\xFE\x00\x03abc", b"abc");
+
+        let p = LengthFieldCodec::new(2).field_offset(2).strip(0);
+        test_layout(p, b"\xCA\xFE\x00\x03abc", b"\xCA\xFE\x00\x03abc");
+
+        let p = LengthFieldCodec::new(2).field_offset(2).strip(2);
+        test_layout(p, b"\xCA\xFE\x00\x03abc", b"\x00\x03abc");
+
+        let p = LengthFieldCodec::new(2).field_offset(2).inclusive(true).strip(0);
+        test_layout(p, b"\xCA\xFE\x00\x07abc", b"\xCA\xFE\x00\x07abc");
+    }
+
+    #[test]
+    fn test_length_field_trailing_header() {
+        // length field is followed by a 2-byte header which is not counted
+        let p = LengthFieldCodec::new(2).length_adjustment(2).strip(2);
+        test_layout(p, b"\x00\x03\xCA\xFEabc", b"\xCA\xFEabc");
+
+        // header before and after the length field, the length counts the whole frame
+        let p = LengthFieldCodec::new(2).field_offset(1).inclusive(true).strip(3);
+        test_layout(p, b"\x01\x00\x07\x02abc", b"\x02abc");
+    }
+
+    #[test]
+    fn test_length_field_invalid() {
+        let mut p = LengthFieldCodec::<BigEndian>::new(2).inclusive(true);
+        let mut buf = EasyBuf::from(b"\x00\x01".to_vec());
+        let err = p.decode(&mut buf).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::InvalidLength));
+
+        let mut p = LengthFieldCodec::<BigEndian>::new(1);
+        let err = p.encode(vec![0; 256], &mut vec![]).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::FrameTooLarge));
+
+        let mut p = LengthFieldCodec::<BigEndian>::new(1).field_offset(2);
+        let err = p.encode(vec![0; 1], &mut vec![]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_length_field_max_frame_length() {
+        let mut p = LengthFieldCodec::<BigEndian>::new(2).max_frame_length(5);
+        let mut buf = EasyBuf::from(b"\x00\x03abc\x00\x04abcd".to_vec());
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), b"abc");
+        let err = p.decode(&mut buf).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::FrameTooLarge));
+
+        let mut p = LengthFieldCodec::<BigEndian>::new(2).max_frame_length(5).discard_oversized(true);
+        let mut buf = EasyBuf::from(b"\x00\x04ab".to_vec());
+        assert!(p.decode(&mut buf).unwrap().is_none());
+        assert_eq!(buf.len(), 0);
+        buf.get_mut().extend_from_slice(b"cd\x00\x03abc");
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), b"abc");
+
+        // discarding a long run of oversized frames does not recurse
+        let mut v = b"\x00\x06abcdef".repeat(300_000);
+        v.extend_from_slice(b"\x00\x03abc");
+        let mut buf = EasyBuf::from(v);
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), b"abc");
+    }
+
+    #[test]
+    fn test_length_field_eof() {
+        let mut p = LengthFieldCodec::<BigEndian>::new(2);
+        let mut buf = EasyBuf::from(b"\x00\x03abc\x00\x03ab".to_vec());
+        assert_eq!(p.decode_eof(&mut buf).unwrap().as_slice(), b"abc");
+        let err = p.decode_eof(&mut buf).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::UnexpectedEof));
+
+        let mut p = LengthFieldCodec::<BigEndian>::new(2);
+        let mut buf = EasyBuf::from(b"\x00".to_vec());
+        assert_eq!(p.decode_eof(&mut buf).unwrap_err().kind(),
+                   io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_length_field_encode() {
+        let mut v = vec![];
+        let mut p = LengthFieldCodec::<BigEndian>::new(2);
+        p.encode(b"abc".to_vec(), &mut v).unwrap();
+        assert_eq!(v, b"\x00\x03abc");
+
+        let mut v = vec![];
+        let mut p = LengthFieldCodec::<BigEndian>::new(2).field_offset(2).inclusive(true);
+        p.encode(b"\xCA\xFEabc".to_vec(), &mut v).unwrap();
+        assert_eq!(v, b"\xCA\xFE\x00\x07abc");
+        assert_eq!(p.decode(&mut EasyBuf::from(v)).unwrap().unwrap().as_slice(), b"abc");
+
+        let mut v = vec![];
+        let mut p = LengthFieldCodec::<BigEndian>::new(2).length_adjustment(2);
+        p.encode(b"\xCA\xFEabc".to_vec(), &mut v).unwrap();
+        assert_eq!(v, b"\x00\x03\xCA\xFEabc");
+    }
+
+    #[test]
+    fn test_length_field_encoding() {
+        let p = LengthFieldCodec::with_encoding(4, LengthEncoding::Decimal);
+        test_layout(p, b"0003abc", b"abc");
+
+        let p = LengthFieldCodec::with_encoding(2, LengthEncoding::Hex);
+        test_layout(p, b"0aabcdefghij", b"abcdefghij");
+
+        let p = LengthFieldCodec::with_encoding(2, LengthEncoding::Bcd);
+        test_layout(p, b"\x00\x12abcdefghijkl", b"abcdefghijkl");
+
+        let mut v = vec![];
+        let mut p = LengthFieldCodec::<BigEndian>::with_encoding(4, LengthEncoding::Decimal);
+        p.encode(vec![0; 42], &mut v).unwrap();
+        assert_eq!(&v[..4], b"0042");
+
+        let mut v = vec![];
+        let mut p = LengthFieldCodec::<BigEndian>::with_encoding(4, LengthEncoding::Hex);
+        p.encode(vec![0; 0xBEE], &mut v).unwrap();
+        assert_eq!(&v[..4], b"0BEE");
+
+        let mut v = vec![];
+        let mut p = LengthFieldCodec::<BigEndian>::with_encoding(2, LengthEncoding::Bcd);
+        p.encode(vec![0; 1234], &mut v).unwrap();
+        assert_eq!(&v[..2], b"\x12\x34");
+    }
+
+    #[test]
+    fn test_length_field_encoding_invalid() {
+        for &(encoding, field) in &[(LengthEncoding::Decimal, &b"0x03"[..]),
+                                    (LengthEncoding::Hex, &b"000g"[..]),
+                                    (LengthEncoding::Bcd, &b"\x00\x0A"[..])] {
+            let mut p = LengthFieldCodec::<BigEndian>::with_encoding(field.len(), encoding);
+            let err = p.decode(&mut EasyBuf::from(field.to_vec())).unwrap_err();
+            assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                       Some(&Error::InvalidLength));
+        }
+
+        let mut p = LengthFieldCodec::<BigEndian>::with_encoding(20, LengthEncoding::Decimal);
+        let err = p.decode(&mut EasyBuf::from(b"99999999999999999999".to_vec())).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::InvalidLength));
+
+        let mut p = LengthFieldCodec::<BigEndian>::with_encoding(2, LengthEncoding::Decimal);
+        let err = p.encode(vec![0; 100], &mut vec![]).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::FrameTooLarge));
+    }
+
+    #[test]
+    fn test_length_field_signed() {
+        let p = LengthFieldCodec::new(2).signed(true);
+        test_layout(p, b"\x00\x03abc", b"abc");
+
+        let mut p = LengthFieldCodec::<BigEndian>::new(2).signed(true);
+        let err = p.decode(&mut EasyBuf::from(b"\xFF\xFF".to_vec())).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::InvalidLength));
+
+        let mut p = LengthFieldCodec::<BigEndian>::new(2)
+            .signed(true)
+            .negative_length(NegativeLength::NoBody)
+            .strip(0);
+        let mut buf = EasyBuf::from(b"\xFF\xFE\x00\x01a".to_vec());
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), b"\xFF\xFE");
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), b"\x00\x01a");
+
+        let mut p = LengthFieldCodec::<BigEndian>::new(2)
+            .signed(true)
+            .negative_length(NegativeLength::Skip);
+        let mut buf = EasyBuf::from(b"\xFF\xFE\x80\x00\x00\x01a".to_vec());
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), b"a");
+
+        // skipping a long run of headers does not recurse
+        let mut v = b"\xFF\xFF".repeat(300_000);
+        v.extend_from_slice(b"\x00\x01a");
+        let mut buf = EasyBuf::from(v);
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), b"a");
+
+        let mut p = LengthFieldCodec::<BigEndian>::new(1).signed(true);
+        p.encode(vec![0; 127], &mut vec![]).unwrap();
+        let err = p.encode(vec![0; 128], &mut vec![]).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::FrameTooLarge));
+    }
+
+    #[test]
+    fn test_length_field_mask() {
+        // 8-bit type followed by a 24-bit length
+        let p = LengthFieldCodec::new(4).length_mask(0x00FF_FFFF);
+        assert_eq!(p.header_bits(b"\x07\x00\x00\x03abc"), 0x0700_0000);
+        test_layout(p, b"\x07\x00\x00\x03abc", b"\x07\x00\x00\x03abc");
+
+        // 12-bit length followed by 4 bits of flags
+        let p = LengthFieldCodec::new(2).length_mask(0xFFF0).strip(2);
+        test_layout(p, b"\x00\x3Aabc", b"abc");
+
+        let p = LengthFieldCodec::new(2).length_mask(0xFFF0).signed(true);
+        test_layout(p, b"\x00\x3Aabc", b"\x00\x3Aabc");
+
+        let mut v = vec![];
+        let mut p = LengthFieldCodec::<BigEndian>::new(4).length_mask(0x00FF_FFFF);
+        p.encode(b"\x07\xFF\xFF\xFFabc".to_vec(), &mut v).unwrap();
+        assert_eq!(v, b"\x07\x00\x00\x03abc");
+
+        let mut p = LengthFieldCodec::<BigEndian>::new(2).length_mask(0x00F0);
+        let err = p.encode(vec![0; 2 + 16], &mut vec![]).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::FrameTooLarge));
+        let err = p.encode(vec![0; 1], &mut vec![]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[cfg(feature = "tokio-util-codec")]
+    #[test]
+    fn test_length_field_tokio_util() {
+        use tokio_util::codec::{Decoder, Encoder};
+
+        let mut p = LengthFieldCodec::<BigEndian>::new(2).field_offset(1);
+
+        let mut buf = BytesMut::new();
+        Encoder::encode(&mut p, b"\xFFabc".to_vec(), &mut buf).unwrap();
+        assert_eq!(&buf[..], b"\xFF\x00\x03abc");
+        buf.extend_from_slice(b"\xFF\x00");
+
+        assert_eq!(&Decoder::decode(&mut p, &mut buf).unwrap().unwrap()[..], b"abc");
+        assert!(Decoder::decode(&mut p, &mut buf).unwrap().is_none());
+        let err = Decoder::decode_eof(&mut p, &mut buf).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::UnexpectedEof));
+    }
+}
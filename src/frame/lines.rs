@@ -0,0 +1,332 @@
+use tokio_core::io::{Codec, Io, EasyBuf, Framed};
+use tokio_proto::pipeline::{ServerProto, ClientProto};
+#[cfg(feature = "tokio-util-codec")]
+use bytes::BytesMut;
+use buf::FrameBufMut;
+use super::delimiter::{Delimiter, DelimiterCodec};
+use error::Error;
+use std::marker::PhantomData;
+use std::io;
+
+/// Handling of frames that are not valid UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Utf8Policy {
+    /// Fails the decode with [`Error::InvalidUtf8`](../error/enum.Error.html).
+    #[default]
+    Strict,
+    /// Replaces invalid sequences with U+FFFD REPLACEMENT CHARACTER.
+    Lossy,
+    /// Skips the frame.
+    Skip,
+}
+
+/// Delimitered protocol of UTF-8 strings.
+///
+/// The same as [`DelimiterProto`](./struct.DelimiterProto.html), except that frames are decoded
+/// into `String`s. Outgoing frames are `String` by default, and can be any `AsRef<str>` type
+/// selected with [`with_out`](#method.with_out).
+#[derive(Debug, PartialEq, Eq)]
+pub struct LinesProto<D, O = String> {
+    codec: LinesCodec<D, O>,
+}
+
+impl<D: Clone, O> Clone for LinesProto<D, O> {
+    fn clone(&self) -> Self {
+        LinesProto { codec: self.codec.clone() }
+    }
+}
+
+impl<D: Delimiter> LinesProto<D> {
+    /// Creates a `LinesProto` from the specified delimiter.
+    pub fn new(delimiter: D) -> Self {
+        LinesProto { codec: LinesCodec::new(delimiter) }
+    }
+}
+
+impl<D: Delimiter, O> LinesProto<D, O> {
+    /// Changes the type of outgoing frames.
+    pub fn with_out<U: AsRef<str>>(self) -> LinesProto<D, U> {
+        LinesProto { codec: self.codec.with_out() }
+    }
+
+    /// Sets the maximum length of a frame in bytes, excluding the delimiter.
+    pub fn max_frame_length(self, max_frame_length: usize) -> Self {
+        LinesProto { codec: self.codec.max_frame_length(max_frame_length) }
+    }
+
+    /// Sets whether oversized frames are discarded instead of failing the decode.
+    pub fn discard_oversized(self, discard_oversized: bool) -> Self {
+        LinesProto { codec: self.codec.discard_oversized(discard_oversized) }
+    }
+
+    /// Sets whether unterminated data at the end of the stream is yielded as the last frame.
+    pub fn yield_unterminated(self, yield_unterminated: bool) -> Self {
+        LinesProto { codec: self.codec.yield_unterminated(yield_unterminated) }
+    }
+
+    /// Sets how frames that are not valid UTF-8 are handled.
+    pub fn utf8_policy(self, utf8_policy: Utf8Policy) -> Self {
+        LinesProto { codec: self.codec.utf8_policy(utf8_policy) }
+    }
+}
+
+impl<T, D, O> ServerProto<T> for LinesProto<D, O>
+    where T: Io + 'static,
+          D: Delimiter + Clone + 'static,
+          O: AsRef<str> + 'static
+{
+    type Request = String;
+    type Response = O;
+    type Transport = Framed<T, LinesCodec<D, O>>;
+    type BindTransport = io::Result<Self::Transport>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(io.framed(self.codec.clone()))
+    }
+}
+
+impl<T, D, O> ClientProto<T> for LinesProto<D, O>
+    where T: Io + 'static,
+          D: Delimiter + Clone + 'static,
+          O: AsRef<str> + 'static
+{
+    type Request = O;
+    type Response = String;
+    type Transport = Framed<T, LinesCodec<D, O>>;
+    type BindTransport = io::Result<Self::Transport>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(io.framed(self.codec.clone()))
+    }
+}
+
+#[cfg(feature = "async-service")]
+impl<D: Clone, O> ::service::NewCodec for LinesProto<D, O> {
+    type Codec = LinesCodec<D, O>;
+
+    fn new_codec(&self) -> LinesCodec<D, O> {
+        self.codec.clone()
+    }
+}
+
+/// Protocol codec used by [`LinesProto`](./struct.LinesProto.html).
+///
+/// Frames are split in the same way as [`DelimiterCodec`](./struct.DelimiterCodec.html), and then
+/// converted to `String`s according to the [`Utf8Policy`](./enum.Utf8Policy.html).
+///
+/// Since tokio-core's `Codec::decode_eof` must yield a frame, a frame skipped with
+/// `Utf8Policy::Skip` at the very end of the stream is reported as `Error::InvalidUtf8` there.
+#[derive(Debug, PartialEq, Eq)]
+pub struct LinesCodec<D, O = String> {
+    inner: DelimiterCodec<D>,
+    utf8_policy: Utf8Policy,
+    _out: PhantomData<fn(O)>,
+}
+
+impl<D: Clone, O> Clone for LinesCodec<D, O> {
+    fn clone(&self) -> Self {
+        LinesCodec {
+            inner: self.inner.clone(),
+            utf8_policy: self.utf8_policy,
+            _out: PhantomData,
+        }
+    }
+}
+
+impl<D: Delimiter> LinesCodec<D> {
+    pub fn new(delimiter: D) -> Self {
+        LinesCodec {
+            inner: DelimiterCodec::new(delimiter),
+            utf8_policy: Utf8Policy::Strict,
+            _out: PhantomData,
+        }
+    }
+}
+
+impl<D: Delimiter, O> LinesCodec<D, O> {
+    /// Changes the type of items to be encoded.
+    pub fn with_out<U: AsRef<str>>(self) -> LinesCodec<D, U> {
+        LinesCodec {
+            inner: self.inner,
+            utf8_policy: self.utf8_policy,
+            _out: PhantomData,
+        }
+    }
+
+    /// Sets the maximum length of a frame in bytes, excluding the delimiter.
+    pub fn max_frame_length(self, max_frame_length: usize) -> Self {
+        LinesCodec { inner: self.inner.max_frame_length(max_frame_length), ..self }
+    }
+
+    /// Sets whether oversized frames are discarded instead of failing the decode.
+    pub fn discard_oversized(self, discard_oversized: bool) -> Self {
+        LinesCodec { inner: self.inner.discard_oversized(discard_oversized), ..self }
+    }
+
+    /// Sets whether unterminated data at the end of the stream is yielded as the last frame.
+    pub fn yield_unterminated(self, yield_unterminated: bool) -> Self {
+        LinesCodec { inner: self.inner.yield_unterminated(yield_unterminated), ..self }
+    }
+
+    /// Sets how frames that are not valid UTF-8 are handled.
+    pub fn utf8_policy(mut self, utf8_policy: Utf8Policy) -> Self {
+        self.utf8_policy = utf8_policy;
+        self
+    }
+
+    /// Converts a frame into a `String`, or returns `None` if it is to be skipped.
+    fn to_string(&self, frame: Vec<u8>) -> io::Result<Option<String>> {
+        match String::from_utf8(frame) {
+            Ok(s) => Ok(Some(s)),
+            Err(e) => {
+                match self.utf8_policy {
+                    Utf8Policy::Strict => Err(Error::InvalidUtf8.into()),
+                    Utf8Policy::Lossy => Ok(Some(String::from_utf8_lossy(e.as_bytes()).into_owned())),
+                    Utf8Policy::Skip => Ok(None),
+                }
+            }
+        }
+    }
+
+    fn encode_buf<W: FrameBufMut>(&mut self, item: &str, buf: &mut W) -> io::Result<()> {
+        buf.reserve(item.len());
+        buf.put_slice(item.as_bytes());
+        self.inner.delimiter().write_delimiter(buf);
+        Ok(())
+    }
+}
+
+impl<D, O> Codec for LinesCodec<D, O>
+    where D: Delimiter + Clone,
+          O: AsRef<str>
+{
+    type In = String;
+    type Out = O;
+
+    fn decode(&mut self, buf: &mut EasyBuf) -> io::Result<Option<String>> {
+        while let Some(frame) = self.inner.decode(buf)? {
+            if let Some(s) = self.to_string(frame.as_slice().to_vec())? {
+                return Ok(Some(s));
+            }
+        }
+        Ok(None)
+    }
+
+    fn decode_eof(&mut self, buf: &mut EasyBuf) -> io::Result<String> {
+        loop {
+            let frame = self.inner.decode_eof(buf)?;
+            if let Some(s) = self.to_string(frame.as_slice().to_vec())? {
+                return Ok(s);
+            }
+            if buf.len() == 0 {
+                return Err(Error::InvalidUtf8.into());
+            }
+        }
+    }
+
+    fn encode(&mut self, item: O, buf: &mut Vec<u8>) -> io::Result<()> {
+        self.encode_buf(item.as_ref(), buf)
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl<D: Delimiter, O> ::tokio_util::codec::Decoder for LinesCodec<D, O> {
+    type Item = String;
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<String>> {
+        while let Some(frame) = ::tokio_util::codec::Decoder::decode(&mut self.inner, buf)? {
+            if let Some(s) = self.to_string(frame.to_vec())? {
+                return Ok(Some(s));
+            }
+        }
+        Ok(None)
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> io::Result<Option<String>> {
+        while let Some(frame) = ::tokio_util::codec::Decoder::decode_eof(&mut self.inner, buf)? {
+            if let Some(s) = self.to_string(frame.to_vec())? {
+                return Ok(Some(s));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl<D: Delimiter, O: AsRef<str>> ::tokio_util::codec::Encoder<O> for LinesCodec<D, O> {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: O, buf: &mut BytesMut) -> io::Result<()> {
+        self.encode_buf(item.as_ref(), buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use frame::LineDelimiter;
+
+    #[test]
+    fn test_lines() {
+        let mut p = LinesCodec::new(LineDelimiter::Lf).with_out::<&'static str>();
+
+        let mut v = vec![];
+        p.encode("あめ", &mut v).unwrap();
+        p.encode("つち", &mut v).unwrap();
+        assert_eq!(v, "あめ\nつち\n".as_bytes());
+
+        let mut buf = EasyBuf::from(v);
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap(), "あめ");
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap(), "つち");
+        assert!(p.decode(&mut buf).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_lines_utf8_policy() {
+        let input = b"a\n\xFFb\nc\n".to_vec();
+
+        let mut p = LinesCodec::new(b'\n');
+        let mut buf = EasyBuf::from(input.clone());
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap(), "a");
+        let err = p.decode(&mut buf).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::InvalidUtf8));
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap(), "c");
+
+        let mut p = LinesCodec::new(b'\n').utf8_policy(Utf8Policy::Lossy);
+        let mut buf = EasyBuf::from(input.clone());
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap(), "a");
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap(), "\u{FFFD}b");
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap(), "c");
+
+        let mut p = LinesCodec::new(b'\n').utf8_policy(Utf8Policy::Skip);
+        let mut buf = EasyBuf::from(input);
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap(), "a");
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap(), "c");
+        assert!(p.decode(&mut buf).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_lines_eof() {
+        let mut p = LinesCodec::new(b'\n').yield_unterminated(true).utf8_policy(Utf8Policy::Skip);
+        let mut buf = EasyBuf::from(b"\xFF\nabc".to_vec());
+        assert_eq!(p.decode_eof(&mut buf).unwrap(), "abc");
+        assert_eq!(buf.len(), 0);
+    }
+
+    #[cfg(feature = "tokio-util-codec")]
+    #[test]
+    fn test_lines_tokio_util() {
+        use tokio_util::codec::{Decoder, Encoder};
+
+        let mut p = LinesCodec::new(LineDelimiter::CrLf);
+
+        let mut buf = BytesMut::new();
+        Encoder::encode(&mut p, "あめ".to_string(), &mut buf).unwrap();
+        assert_eq!(&buf[..], "あめ\r\n".as_bytes());
+
+        assert_eq!(Decoder::decode(&mut p, &mut buf).unwrap().unwrap(), "あめ");
+        assert!(Decoder::decode_eof(&mut p, &mut buf).unwrap().is_none());
+    }
+}
@@ -1,8 +1,29 @@
 mod fixed_length;
 pub use self::fixed_length::{FixedLengthProto, FixedLengthCodec};
 mod delimiter;
-pub use self::delimiter::{DelimiterProto, DelimiterCodec, Delimiter, LineDelimiter};
+pub use self::delimiter::{DelimiterProto, DelimiterCodec, Delimiter, PopBuf, PopBufDelimiter,
+                          LineDelimiter, AnyLineEnding, AnyOf};
+mod lines;
+pub use self::lines::{LinesProto, LinesCodec, Utf8Policy};
+#[cfg(feature = "regex")]
+mod regex_delimiter;
+#[cfg(feature = "regex")]
+pub use self::regex_delimiter::RegexDelimiter;
+mod stuffing;
+mod slip;
+pub use self::slip::{SlipProto, SlipCodec};
+mod cobs;
+pub use self::cobs::{CobsProto, CobsCodec};
+mod hdlc;
+pub use self::hdlc::{HdlcProto, HdlcCodec, Fcs};
 mod length_field;
-pub use self::length_field::{LengthFieldProto, LengthFieldCodec};
+pub use self::length_field::{LengthFieldProto, LengthFieldCodec, LengthEncoding,
+                             NegativeLength};
 mod varint;
-pub use self::varint::{VarIntLengthFieldProto, VarIntLengthFieldCodec};
\ No newline at end of file
+pub use self::varint::{VarIntLengthFieldProto, VarIntLengthFieldCodec};
+mod header_then_body;
+pub use self::header_then_body::{HeaderThenBodyProto, HeaderThenBodyCodec};
+mod netstring;
+pub use self::netstring::{NetstringProto, NetstringCodec};
+mod chunked;
+pub use self::chunked::{ChunkedProto, ChunkedCodec, Chunk};
@@ -0,0 +1,315 @@
+use tokio_core::io::{Codec, Io, EasyBuf, Framed};
+use tokio_proto::pipeline::{ServerProto, ClientProto};
+#[cfg(feature = "tokio-util-codec")]
+use bytes::BytesMut;
+use buf::{FrameBuf, FrameBufMut};
+use error::Error;
+use std::marker::PhantomData;
+use std::io;
+
+/// Netstring protocol.
+///
+/// A protocol such that every frame is encoded as `<length>:<payload>,`, where `<length>` is the
+/// length of the payload in ASCII decimal digits.
+///
+/// Decoded frames are `EasyBuf`s sharing the read buffer. Wrap this protocol in
+/// [`DecodeToVecProto`](../decode_to_vec/struct.DecodeToVecProto.html) to receive `Vec<u8>`s.
+/// Outgoing frames are `Vec<u8>` by default, and can be any `AsRef<[u8]>` type selected with
+/// [`with_out`](#method.with_out).
+#[derive(Debug, PartialEq, Eq)]
+pub struct NetstringProto<O = Vec<u8>> {
+    codec: NetstringCodec<O>,
+}
+
+impl<O> Clone for NetstringProto<O> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<O> Copy for NetstringProto<O> {}
+
+impl NetstringProto {
+    pub fn new() -> Self {
+        NetstringProto { codec: NetstringCodec::new() }
+    }
+}
+
+impl Default for NetstringProto {
+    fn default() -> Self {
+        NetstringProto::new()
+    }
+}
+
+impl<O> NetstringProto<O> {
+    /// Changes the type of outgoing frames.
+    pub fn with_out<U: AsRef<[u8]>>(self) -> NetstringProto<U> {
+        NetstringProto { codec: self.codec.with_out() }
+    }
+
+    /// Sets the maximum length of a payload.
+    pub fn max_frame_length(self, max_frame_length: usize) -> Self {
+        NetstringProto { codec: self.codec.max_frame_length(max_frame_length) }
+    }
+}
+
+impl<T: Io + 'static, O: AsRef<[u8]> + 'static> ServerProto<T> for NetstringProto<O> {
+    type Request = EasyBuf;
+    type Response = O;
+    type Transport = Framed<T, NetstringCodec<O>>;
+    type BindTransport = io::Result<Self::Transport>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(io.framed(self.codec))
+    }
+}
+
+impl<T: Io + 'static, O: AsRef<[u8]> + 'static> ClientProto<T> for NetstringProto<O> {
+    type Request = O;
+    type Response = EasyBuf;
+    type Transport = Framed<T, NetstringCodec<O>>;
+    type BindTransport = io::Result<Self::Transport>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(io.framed(self.codec))
+    }
+}
+
+#[cfg(feature = "async-service")]
+impl<O> ::service::NewCodec for NetstringProto<O> {
+    type Codec = NetstringCodec<O>;
+
+    fn new_codec(&self) -> NetstringCodec<O> {
+        self.codec
+    }
+}
+
+/// Protocol codec used by [`NetstringProto`](./struct.NetstringProto.html).
+///
+/// A length with a leading zero (other than `0` itself) or which does not fit in `usize` fails
+/// the decode with [`Error::InvalidLength`](../error/enum.Error.html), and a missing `:` or `,`
+/// with `Error::InvalidEncoding`. A payload longer than the maximum frame length fails with
+/// `Error::FrameTooLarge` as soon as the length is read, both when decoding and encoding.
+#[derive(Debug, PartialEq, Eq)]
+pub struct NetstringCodec<O = Vec<u8>> {
+    max_frame_length: Option<usize>,
+    _out: PhantomData<fn(O)>,
+}
+
+impl<O> Clone for NetstringCodec<O> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<O> Copy for NetstringCodec<O> {}
+
+impl NetstringCodec {
+    pub fn new() -> Self {
+        NetstringCodec {
+            max_frame_length: None,
+            _out: PhantomData,
+        }
+    }
+}
+
+impl Default for NetstringCodec {
+    fn default() -> Self {
+        NetstringCodec::new()
+    }
+}
+
+impl<O> NetstringCodec<O> {
+    /// Changes the type of items to be encoded.
+    pub fn with_out<U: AsRef<[u8]>>(self) -> NetstringCodec<U> {
+        NetstringCodec {
+            max_frame_length: self.max_frame_length,
+            _out: PhantomData,
+        }
+    }
+
+    /// Sets the maximum length of a payload.
+    pub fn max_frame_length(mut self, max_frame_length: usize) -> Self {
+        self.max_frame_length = Some(max_frame_length);
+        self
+    }
+
+    fn is_too_large(&self, len: usize) -> bool {
+        self.max_frame_length.is_some_and(|max| len > max)
+    }
+
+    /// Parses the length prefix, returning the payload length and the length of the prefix
+    /// including `:`.
+    fn parse_header(&self, buf: &[u8]) -> io::Result<Option<(usize, usize)>> {
+        let mut len: usize = 0;
+        for (i, &b) in buf.iter().enumerate() {
+            match b {
+                _ if b.is_ascii_digit() => {
+                    if i == 1 && buf[0] == b'0' {
+                        return Err(Error::InvalidLength.into());
+                    }
+                    len = match len.checked_mul(10).and_then(|l| l.checked_add((b - b'0') as usize)) {
+                        Some(len) => len,
+                        None => return Err(Error::InvalidLength.into()),
+                    };
+                    if self.is_too_large(len) {
+                        return Err(Error::FrameTooLarge.into());
+                    }
+                }
+                b':' if i > 0 => return Ok(Some((len, i + 1))),
+                _ => return Err(Error::InvalidEncoding.into()),
+            }
+        }
+        Ok(None)
+    }
+
+    #[inline]
+    fn decode_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<Option<F>> {
+        let (len, header_len) = match self.parse_header(buf.as_ref())? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+
+        let frame_len = match header_len.checked_add(len).and_then(|l| l.checked_add(1)) {
+            Some(l) => l,
+            None => return Err(Error::InvalidLength.into()),
+        };
+        if buf.len() < frame_len {
+            return Ok(None);
+        }
+        if buf.as_ref()[frame_len - 1] != b',' {
+            return Err(Error::InvalidEncoding.into());
+        }
+
+        buf.split_to(header_len);
+        let frame = buf.split_to(len);
+        buf.split_to(1);
+        Ok(Some(frame))
+    }
+
+    fn decode_eof_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<F> {
+        match self.decode_buf(buf)? {
+            Some(frame) => Ok(frame),
+            None => Err(Error::UnexpectedEof.into()),
+        }
+    }
+
+    #[inline]
+    fn encode_buf<W: FrameBufMut>(&mut self, item: &[u8], buf: &mut W) -> io::Result<()> {
+        if self.is_too_large(item.len()) {
+            return Err(Error::FrameTooLarge.into());
+        }
+
+        let header = format!("{}:", item.len());
+        buf.reserve(header.len() + item.len() + 1);
+        buf.put_slice(header.as_bytes());
+        buf.put_slice(item);
+        buf.put_slice(b",");
+        Ok(())
+    }
+}
+
+impl<O: AsRef<[u8]>> Codec for NetstringCodec<O> {
+    type In = EasyBuf;
+    type Out = O;
+
+    #[inline]
+    fn decode(&mut self, buf: &mut EasyBuf) -> io::Result<Option<EasyBuf>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut EasyBuf) -> io::Result<EasyBuf> {
+        self.decode_eof_buf(buf)
+    }
+
+    #[inline]
+    fn encode(&mut self, item: O, buf: &mut Vec<u8>) -> io::Result<()> {
+        self.encode_buf(item.as_ref(), buf)
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl<O> ::tokio_util::codec::Decoder for NetstringCodec<O> {
+    type Item = BytesMut;
+    type Error = io::Error;
+
+    #[inline]
+    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+        if buf.is_empty() {
+            Ok(None)
+        } else {
+            self.decode_eof_buf(buf).map(Some)
+        }
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl<O: AsRef<[u8]>> ::tokio_util::codec::Encoder<O> for NetstringCodec<O> {
+    type Error = io::Error;
+
+    #[inline]
+    fn encode(&mut self, item: O, buf: &mut BytesMut) -> io::Result<()> {
+        self.encode_buf(item.as_ref(), buf)
+    }
+}
+
+#[test]
+fn test_netstring() {
+    let mut p = NetstringCodec::new();
+
+    let mut v = vec![];
+    p.encode(b"hello world!".to_vec(), &mut v).unwrap();
+    p.encode(vec![], &mut v).unwrap();
+    assert_eq!(v, b"12:hello world!,0:,");
+
+    let mut buf = EasyBuf::new();
+    buf.get_mut().extend_from_slice(&v[..5]);
+    assert!(p.decode(&mut buf).unwrap().is_none());
+    buf.get_mut().extend_from_slice(&v[5..]);
+    assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), b"hello world!");
+    assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), b"");
+    assert_eq!(buf.len(), 0);
+}
+
+#[test]
+fn test_netstring_invalid() {
+    use test_util::{assert_err, codec_error};
+
+    fn decode_err(p: &mut NetstringCodec, input: &[u8]) -> Error {
+        let mut buf = EasyBuf::from(input.to_vec());
+        codec_error(&p.decode(&mut buf).unwrap_err()).unwrap()
+    }
+
+    let mut p = NetstringCodec::new();
+    assert_eq!(decode_err(&mut p, b"01:a,"), Error::InvalidLength);
+    assert_eq!(decode_err(&mut p, b"00"), Error::InvalidLength);
+    assert_eq!(decode_err(&mut p, b"99999999999999999999999"), Error::InvalidLength);
+    assert_eq!(decode_err(&mut p, b":a,"), Error::InvalidEncoding);
+    assert_eq!(decode_err(&mut p, b"1;a,"), Error::InvalidEncoding);
+    assert_eq!(decode_err(&mut p, b"1:ab"), Error::InvalidEncoding);
+
+    let mut p = NetstringCodec::new().max_frame_length(10);
+    assert_eq!(decode_err(&mut p, b"11"), Error::FrameTooLarge);
+    assert_err(p.encode(vec![0; 11], &mut vec![]), Error::FrameTooLarge);
+
+    let mut p = NetstringCodec::new();
+    let mut buf = EasyBuf::from(b"3:ab".to_vec());
+    assert_err(p.decode_eof(&mut buf), Error::UnexpectedEof);
+}
+
+#[test]
+fn test_netstring_roundtrip() {
+    use quickcheck::quickcheck;
+    use test_util::roundtrip;
+
+    fn prop(frames: Vec<Vec<u8>>, chunk: usize) -> bool {
+        let decoded = roundtrip(&mut NetstringCodec::new(), frames.clone(), chunk % 16 + 1);
+        decoded.iter().map(|f| f.as_slice()).eq(frames.iter().map(|f| &f[..]))
+    }
+
+    quickcheck(prop as fn(Vec<Vec<u8>>, usize) -> bool);
+}
@@ -0,0 +1,219 @@
+use regex::bytes::Regex;
+use buf::FrameBufMut;
+use super::delimiter::Delimiter;
+use std::io;
+
+/// A delimiter matching a regular expression. Available with the `regex` feature.
+///
+/// The regex crate does not support lookaround. To match a delimiter only when followed by
+/// something, such as a newline followed by a timestamp, name the part to be removed `delimiter`:
+/// the rest of the match then stays at the head of the next frame.
+///
+/// ```rust
+/// # extern crate regex;
+/// # extern crate framecodecs;
+/// # use regex::bytes::Regex;
+/// # use framecodecs::frame::{DelimiterCodec, RegexDelimiter};
+/// # fn main() {
+/// let re = Regex::new(r"(?P<delimiter>\n)\d{4}-\d{2}-\d{2} ").unwrap();
+/// let codec = DelimiterCodec::new(RegexDelimiter::new(re, 12));
+/// # }
+/// ```
+///
+/// A match reaching the end of the received data is not taken until more data arrives or the
+/// stream ends, as it may be extended, unless it already has the maximum length. Only complete
+/// matches are seen, though: a match found in the received data is taken even if an alternative
+/// starting earlier could have matched once more data arrived.
+///
+/// The maximum length of a match lets the codec search only the newly received data. A delimiter
+/// created with [`unbounded`](#method.unbounded) has none, so the whole buffer is searched again
+/// on every read, and a `max_frame_length` of the codec applies to the whole unterminated data,
+/// since a match could span all of it.
+///
+/// Frames are encoded with the delimiter set by [`encode_with`](#method.encode_with), which is
+/// empty by default.
+#[derive(Debug, Clone)]
+pub struct RegexDelimiter {
+    regex: Regex,
+    group: Option<usize>,
+    max_len: Option<usize>,
+    encode: Vec<u8>,
+}
+
+impl RegexDelimiter {
+    /// Creates a `RegexDelimiter` from `regex`, whose matches are at most `max_len` bytes long,
+    /// including the part not taken as the delimiter.
+    pub fn new(regex: Regex, max_len: usize) -> Self {
+        RegexDelimiter { max_len: Some(max_len), ..RegexDelimiter::unbounded(regex) }
+    }
+
+    /// Creates a `RegexDelimiter` from `regex`, whose matches may have any length.
+    ///
+    /// The whole buffer is searched on every read, which takes time quadratic in the frame length.
+    /// Bound frames with the codec's `max_frame_length`.
+    pub fn unbounded(regex: Regex) -> Self {
+        let group = regex.capture_names().position(|name| name == Some("delimiter"));
+
+        RegexDelimiter {
+            regex,
+            group,
+            max_len: None,
+            encode: Vec::new(),
+        }
+    }
+
+    /// Sets the delimiter appended to encoded frames.
+    pub fn encode_with<B: Into<Vec<u8>>>(mut self, delimiter: B) -> Self {
+        self.encode = delimiter.into();
+        self
+    }
+
+    fn find_match(&self, buf: &[u8], eof: bool) -> Option<(usize, usize)> {
+        let (whole, delimiter) = match self.group {
+            Some(group) => {
+                let caps = self.regex.captures(buf)?;
+                let whole = caps.get(0).unwrap();
+                match caps.get(group) {
+                    Some(m) => (whole.range(), (m.start(), m.end())),
+                    None => (whole.range(), (whole.start(), whole.end())),
+                }
+            }
+            None => {
+                match self.regex.find(buf) {
+                    Some(m) => (m.range(), (m.start(), m.end())),
+                    None => return None,
+                }
+            }
+        };
+
+        let is_full = self.max_len.is_some_and(|max_len| whole.len() >= max_len);
+        if whole.end == buf.len() && !eof && !is_full {
+            // the match may be extended by more data
+            return None;
+        }
+
+        Some(delimiter)
+    }
+}
+
+impl Delimiter for RegexDelimiter {
+    fn find(&self, buf: &[u8]) -> io::Result<Option<(usize, usize)>> {
+        Ok(self.find_match(buf, false))
+    }
+
+    fn find_eof(&self, buf: &[u8]) -> io::Result<Option<(usize, usize)>> {
+        Ok(self.find_match(buf, true))
+    }
+
+    fn write_delimiter<W: FrameBufMut>(&self, buf: &mut W) {
+        buf.put_slice(&self.encode);
+    }
+
+    fn max_len(&self) -> usize {
+        self.max_len.unwrap_or(usize::MAX)
+    }
+
+    fn resume_at(&self, buf: &[u8]) -> usize {
+        match self.max_len {
+            // a match ending at the end of `buf` may not be taken, so it must be searched again
+            Some(max_len) => buf.len().saturating_sub(max_len),
+            None => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use frame::DelimiterCodec;
+    use tokio_core::io::{Codec, EasyBuf};
+
+    #[test]
+    fn test_regex_delimiter() {
+        let re = Regex::new(r"(?P<delimiter>\n)\d{4}-\d{2}-\d{2} ").unwrap();
+        let mut p = DelimiterCodec::new(RegexDelimiter::new(re, 12))
+            .yield_unterminated(true);
+
+        let input = "2017-01-01 a\n  b\n2017-01-02 c\n2017-01-03 d";
+        let mut buf = EasyBuf::new();
+        let mut frames = vec![];
+        for chunk in input.as_bytes().chunks(3) {
+            buf.get_mut().extend_from_slice(chunk);
+            while let Some(frame) = p.decode(&mut buf).unwrap() {
+                frames.push(String::from_utf8(frame.as_slice().to_vec()).unwrap());
+            }
+        }
+        frames.push(String::from_utf8(p.decode_eof(&mut buf).unwrap().as_slice().to_vec()).unwrap());
+
+        assert_eq!(frames, vec!["2017-01-01 a\n  b", "2017-01-02 c", "2017-01-03 d"]);
+    }
+
+    #[test]
+    fn test_regex_delimiter_read_boundary() {
+        let re = Regex::new(r"(?P<delimiter>\n)\d{4}-\d{2}-\d{2} ").unwrap();
+        let input = b"2017-01-01 a\n2017-01-02 b\n2017-01-03 c";
+
+        // including a read ending exactly at the end of a match
+        for at in 0..input.len() {
+            let mut p = DelimiterCodec::new(RegexDelimiter::new(re.clone(), 12))
+                .yield_unterminated(true);
+            let mut buf = EasyBuf::new();
+            let mut frames = vec![];
+            for chunk in &[&input[..at], &input[at..]] {
+                buf.get_mut().extend_from_slice(chunk);
+                while let Some(frame) = p.decode(&mut buf).unwrap() {
+                    frames.push(frame.as_slice().to_vec());
+                }
+            }
+            frames.push(p.decode_eof(&mut buf).unwrap().as_slice().to_vec());
+
+            assert_eq!(frames,
+                       vec![b"2017-01-01 a".to_vec(),
+                            b"2017-01-02 b".to_vec(),
+                            b"2017-01-03 c".to_vec()],
+                       "split at {}",
+                       at);
+        }
+    }
+
+    #[test]
+    fn test_regex_delimiter_max_frame_length() {
+        use error::Error;
+
+        let d = RegexDelimiter::unbounded(Regex::new(r"\n").unwrap());
+        let mut p = DelimiterCodec::new(d).max_frame_length(4);
+        let mut buf = EasyBuf::from(vec![b'a'; 100]);
+        let err = p.decode(&mut buf).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::FrameTooLarge));
+    }
+
+    #[test]
+    fn test_regex_delimiter_extend() {
+        let d = RegexDelimiter::unbounded(Regex::new(r"\n+").unwrap()).encode_with("\n");
+
+        assert_eq!(d.find(b"a\n\n").unwrap(), None);
+        assert_eq!(d.find(b"a\n\nb").unwrap(), Some((1, 3)));
+        assert_eq!(d.find_eof(b"a\n\n").unwrap(), Some((1, 3)));
+        assert_eq!(d.resume_at(b"a\n\n"), 0);
+
+        let mut v = vec![];
+        d.write_delimiter(&mut v);
+        assert_eq!(v, b"\n");
+    }
+
+    #[test]
+    fn test_regex_delimiter_full_match_at_end() {
+        let d = RegexDelimiter::new(Regex::new(r"\n+").unwrap(), 2);
+
+        assert_eq!(d.find(b"a\n").unwrap(), None);
+        assert_eq!(d.find(b"a\n\n").unwrap(), Some((1, 3)));
+
+        // a reply ending the received data is decoded without waiting for more
+        let d = RegexDelimiter::new(Regex::new(r"\r\n").unwrap(), 2);
+        let mut p = DelimiterCodec::new(d);
+        let mut buf = EasyBuf::from(b"+OK\r\n".to_vec());
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), b"+OK");
+        assert_eq!(buf.len(), 0);
+    }
+}
@@ -0,0 +1,321 @@
+use tokio_core::io::{Codec, Io, EasyBuf, Framed};
+use tokio_proto::pipeline::{ServerProto, ClientProto};
+#[cfg(feature = "tokio-util-codec")]
+use bytes::BytesMut;
+use buf::{FrameBuf, FrameBufMut};
+use super::stuffing::split_frame;
+use error::Error;
+use std::marker::PhantomData;
+use std::io;
+
+const END: u8 = 0xC0;
+const ESC: u8 = 0xDB;
+const ESC_END: u8 = 0xDC;
+const ESC_ESC: u8 = 0xDD;
+
+/// SLIP protocol (RFC 1055).
+///
+/// A protocol such that frames are terminated with `END` (`0xC0`), and `END` and `ESC` (`0xDB`)
+/// bytes in the payload are escaped.
+///
+/// Decoded frames are unescaped `Vec<u8>`s. Outgoing frames are `Vec<u8>` by default, and can be
+/// any `AsRef<[u8]>` type selected with [`with_out`](#method.with_out).
+#[derive(Debug, PartialEq, Eq)]
+pub struct SlipProto<O = Vec<u8>> {
+    codec: SlipCodec<O>,
+}
+
+impl<O> Clone for SlipProto<O> {
+    fn clone(&self) -> Self {
+        SlipProto { codec: self.codec.clone() }
+    }
+}
+
+impl SlipProto {
+    pub fn new() -> Self {
+        SlipProto { codec: SlipCodec::new() }
+    }
+}
+
+impl Default for SlipProto {
+    fn default() -> Self {
+        SlipProto::new()
+    }
+}
+
+impl<O> SlipProto<O> {
+    /// Changes the type of outgoing frames.
+    pub fn with_out<U: AsRef<[u8]>>(self) -> SlipProto<U> {
+        SlipProto { codec: self.codec.with_out() }
+    }
+
+    /// Sets the maximum length of an encoded frame, excluding the `END` byte.
+    pub fn max_frame_length(self, max_frame_length: usize) -> Self {
+        SlipProto { codec: self.codec.max_frame_length(max_frame_length) }
+    }
+
+    /// Sets whether an `END` byte is also sent before each frame.
+    pub fn leading_end(self, leading_end: bool) -> Self {
+        SlipProto { codec: self.codec.leading_end(leading_end) }
+    }
+}
+
+impl<T: Io + 'static, O: AsRef<[u8]> + 'static> ServerProto<T> for SlipProto<O> {
+    type Request = Vec<u8>;
+    type Response = O;
+    type Transport = Framed<T, SlipCodec<O>>;
+    type BindTransport = io::Result<Self::Transport>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(io.framed(self.codec.clone()))
+    }
+}
+
+impl<T: Io + 'static, O: AsRef<[u8]> + 'static> ClientProto<T> for SlipProto<O> {
+    type Request = O;
+    type Response = Vec<u8>;
+    type Transport = Framed<T, SlipCodec<O>>;
+    type BindTransport = io::Result<Self::Transport>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(io.framed(self.codec.clone()))
+    }
+}
+
+#[cfg(feature = "async-service")]
+impl<O> ::service::NewCodec for SlipProto<O> {
+    type Codec = SlipCodec<O>;
+
+    fn new_codec(&self) -> SlipCodec<O> {
+        self.codec.clone()
+    }
+}
+
+/// Protocol codec used by [`SlipProto`](./struct.SlipProto.html).
+///
+/// Empty frames between `END` bytes are ignored, as recommended by RFC 1055. An `ESC` byte
+/// followed by anything other than `ESC_END` or `ESC_ESC` fails the decode with
+/// [`Error::InvalidEncoding`](../error/enum.Error.html).
+#[derive(Debug, PartialEq, Eq)]
+pub struct SlipCodec<O = Vec<u8>> {
+    max_frame_length: Option<usize>,
+    leading_end: bool,
+    searched: usize,
+    _out: PhantomData<fn(O)>,
+}
+
+impl<O> Clone for SlipCodec<O> {
+    fn clone(&self) -> Self {
+        SlipCodec {
+            max_frame_length: self.max_frame_length,
+            leading_end: self.leading_end,
+            searched: self.searched,
+            _out: PhantomData,
+        }
+    }
+}
+
+impl SlipCodec {
+    pub fn new() -> Self {
+        SlipCodec {
+            max_frame_length: None,
+            leading_end: false,
+            searched: 0,
+            _out: PhantomData,
+        }
+    }
+}
+
+impl Default for SlipCodec {
+    fn default() -> Self {
+        SlipCodec::new()
+    }
+}
+
+impl<O> SlipCodec<O> {
+    /// Changes the type of items to be encoded.
+    pub fn with_out<U: AsRef<[u8]>>(self) -> SlipCodec<U> {
+        SlipCodec {
+            max_frame_length: self.max_frame_length,
+            leading_end: self.leading_end,
+            searched: self.searched,
+            _out: PhantomData,
+        }
+    }
+
+    /// Sets the maximum length of an encoded frame, excluding the `END` byte.
+    pub fn max_frame_length(mut self, max_frame_length: usize) -> Self {
+        self.max_frame_length = Some(max_frame_length);
+        self
+    }
+
+    /// Sets whether an `END` byte is also sent before each frame, which flushes any noise
+    /// received by the peer.
+    pub fn leading_end(mut self, leading_end: bool) -> Self {
+        self.leading_end = leading_end;
+        self
+    }
+
+    fn decode_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<Option<Vec<u8>>> {
+        match split_frame(buf, END, &mut self.searched, self.max_frame_length)? {
+            Some(frame) => unescape(frame.as_ref()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn decode_eof_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<Option<Vec<u8>>> {
+        match self.decode_buf(buf)? {
+            Some(frame) => Ok(Some(frame)),
+            None if buf.is_empty() => Ok(None),
+            None => Err(Error::UnexpectedEof.into()),
+        }
+    }
+
+    fn encode_buf<W: FrameBufMut>(&mut self, item: &[u8], buf: &mut W) -> io::Result<()> {
+        buf.reserve(item.len() + 2);
+        if self.leading_end {
+            buf.put_slice(&[END]);
+        }
+        let mut rest = item;
+        while let Some(pos) = rest.iter().position(|&b| b == END || b == ESC) {
+            buf.put_slice(&rest[..pos]);
+            buf.put_slice(if rest[pos] == END { &[ESC, ESC_END] } else { &[ESC, ESC_ESC] });
+            rest = &rest[pos + 1..];
+        }
+        buf.put_slice(rest);
+        buf.put_slice(&[END]);
+        Ok(())
+    }
+}
+
+fn unescape(frame: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(frame.len());
+    let mut iter = frame.iter();
+    while let Some(&b) = iter.next() {
+        if b == ESC {
+            match iter.next() {
+                Some(&ESC_END) => out.push(END),
+                Some(&ESC_ESC) => out.push(ESC),
+                _ => return Err(Error::InvalidEncoding.into()),
+            }
+        } else {
+            out.push(b);
+        }
+    }
+    Ok(out)
+}
+
+impl<O: AsRef<[u8]>> Codec for SlipCodec<O> {
+    type In = Vec<u8>;
+    type Out = O;
+
+    fn decode(&mut self, buf: &mut EasyBuf) -> io::Result<Option<Vec<u8>>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut EasyBuf) -> io::Result<Vec<u8>> {
+        match self.decode_eof_buf(buf)? {
+            Some(frame) => Ok(frame),
+            None => Err(Error::UnexpectedEof.into()),
+        }
+    }
+
+    fn encode(&mut self, item: O, buf: &mut Vec<u8>) -> io::Result<()> {
+        self.encode_buf(item.as_ref(), buf)
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl<O> ::tokio_util::codec::Decoder for SlipCodec<O> {
+    type Item = Vec<u8>;
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<Vec<u8>>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> io::Result<Option<Vec<u8>>> {
+        self.decode_eof_buf(buf)
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl<O: AsRef<[u8]>> ::tokio_util::codec::Encoder<O> for SlipCodec<O> {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: O, buf: &mut BytesMut) -> io::Result<()> {
+        self.encode_buf(item.as_ref(), buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::{assert_err, roundtrip};
+
+    #[test]
+    fn test_slip() {
+        let mut p = SlipCodec::new();
+
+        let mut v = vec![];
+        p.encode(vec![1, END, 2, ESC, 3], &mut v).unwrap();
+        assert_eq!(v, [1, ESC, ESC_END, 2, ESC, ESC_ESC, 3, END]);
+
+        let mut buf = EasyBuf::from(vec![END, END]);
+        buf.get_mut().extend_from_slice(&v[..3]);
+        assert!(p.decode(&mut buf).unwrap().is_none());
+        buf.get_mut().extend_from_slice(&v[3..]);
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap(), [1, END, 2, ESC, 3]);
+        assert_eq!(buf.len(), 0);
+
+        let mut p = SlipCodec::new().leading_end(true).with_out::<&'static [u8]>();
+        let mut v = vec![];
+        p.encode(b"a", &mut v).unwrap();
+        assert_eq!(v, [END, b'a', END]);
+    }
+
+    #[test]
+    fn test_slip_invalid() {
+        let mut p = SlipCodec::new();
+        let mut buf = EasyBuf::from(vec![1, ESC, 2, END, 3, END]);
+        assert_err(p.decode(&mut buf), Error::InvalidEncoding);
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap(), [3]);
+
+        let mut p = SlipCodec::new().max_frame_length(2);
+        let mut buf = EasyBuf::from(vec![1, 2, 3]);
+        assert_err(p.decode(&mut buf), Error::FrameTooLarge);
+
+        let mut p = SlipCodec::new();
+        let mut buf = EasyBuf::from(vec![1, 2]);
+        assert_err(p.decode_eof(&mut buf), Error::UnexpectedEof);
+    }
+
+    #[cfg(feature = "tokio-util-codec")]
+    #[test]
+    fn test_slip_tokio_util() {
+        use tokio_util::codec::{Decoder, Encoder};
+
+        let mut p = SlipCodec::new().leading_end(true);
+
+        let mut buf = BytesMut::new();
+        Encoder::encode(&mut p, vec![1, END, 2, ESC], &mut buf).unwrap();
+        assert_eq!(&buf[..], [END, 1, ESC, ESC_END, 2, ESC, ESC_ESC, END]);
+
+        assert_eq!(Decoder::decode(&mut p, &mut buf).unwrap().unwrap(), [1, END, 2, ESC]);
+        assert!(Decoder::decode_eof(&mut p, &mut buf).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_slip_roundtrip() {
+        use quickcheck::quickcheck;
+
+        fn prop(frames: Vec<Vec<u8>>, chunk: usize) -> bool {
+            // empty frames are ignored by the decoder
+            let frames: Vec<_> = frames.into_iter().filter(|f| !f.is_empty()).collect();
+
+            roundtrip(&mut SlipCodec::new(), frames.clone(), chunk % 16 + 1) == frames
+        }
+
+        quickcheck(prop as fn(Vec<Vec<u8>>, usize) -> bool);
+    }
+}
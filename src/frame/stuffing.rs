@@ -0,0 +1,42 @@
+//! Scanning shared by the byte-stuffing codecs, whose frames are terminated with a byte that never
+//! appears in the encoded payload.
+
+use buf::FrameBuf;
+use error::Error;
+use std::io;
+
+/// Splits the next non-empty frame off `buf`, and returns it without the terminator.
+///
+/// `searched` is the length of the head of `buf` known not to contain the terminator, and is
+/// updated for the next call. Empty frames are skipped.
+pub(crate) fn split_frame<F: FrameBuf>(buf: &mut F,
+                                       terminator: u8,
+                                       searched: &mut usize,
+                                       max_frame_length: Option<usize>)
+                                       -> io::Result<Option<F>> {
+    let is_too_large = |len| max_frame_length.is_some_and(|max| len > max);
+
+    loop {
+        let pos = match ::memchr::memchr(terminator, &buf.as_ref()[*searched..]) {
+            Some(pos) => *searched + pos,
+            None => {
+                *searched = buf.len();
+                if is_too_large(buf.len()) {
+                    return Err(Error::FrameTooLarge.into());
+                }
+                return Ok(None);
+            }
+        };
+
+        *searched = 0;
+        let frame = buf.split_to(pos);
+        buf.split_to(1);
+        if pos == 0 {
+            continue;
+        }
+        if is_too_large(pos) {
+            return Err(Error::FrameTooLarge.into());
+        }
+        return Ok(Some(frame));
+    }
+}
@@ -1,32 +1,81 @@
 use tokio_core::io::{Codec, Io, EasyBuf, Framed};
 use tokio_proto::pipeline;
-use std::io;
-use std::mem;
+#[cfg(feature = "tokio-util-codec")]
+use bytes::BytesMut;
+use buf::{FrameBuf, FrameBufMut};
+use error::Error;
+use std::{cmp, io};
+use std::marker::PhantomData;
+
+/// The maximum length of a varint representing a 64-bit integer.
+const MAX_VARINT_LENGTH: usize = 10;
 
 /// Length field based protocol using Protobuf's base 128 varint.
 ///
 /// A protocol such that every frame has length field prepended in
 /// [Protobuf's base 128 varint](https://developers.google.com/protocol-buffers/docs/encoding#varints)
 /// format.
-#[derive(Debug, Clone, Default)]
-pub struct VarIntLengthFieldProto;
+///
+/// Decoded frames are `EasyBuf`s sharing the read buffer. Wrap this protocol in
+/// [`DecodeToVecProto`](../decode_to_vec/struct.DecodeToVecProto.html) to receive `Vec<u8>`s.
+/// Outgoing frames are `Vec<u8>` by default, and can be any `AsRef<[u8]>` type selected with
+/// [`with_out`](#method.with_out).
+#[derive(Debug)]
+pub struct VarIntLengthFieldProto<O = Vec<u8>> {
+    codec: VarIntLengthFieldCodec<O>,
+}
+
+impl<O> Clone for VarIntLengthFieldProto<O> {
+    fn clone(&self) -> Self {
+        VarIntLengthFieldProto { codec: self.codec.clone() }
+    }
+}
+
+impl Default for VarIntLengthFieldProto {
+    fn default() -> VarIntLengthFieldProto {
+        VarIntLengthFieldProto { codec: Default::default() }
+    }
+}
 
 impl VarIntLengthFieldProto {
     pub fn new() -> VarIntLengthFieldProto {
-        VarIntLengthFieldProto
+        Default::default()
     }
+}
 
-    fn codec(&self) -> VarIntLengthFieldCodec {
-        VarIntLengthFieldCodec::default()
+impl<O> VarIntLengthFieldProto<O> {
+    /// Changes the type of outgoing frames.
+    pub fn with_out<U: AsRef<[u8]>>(self) -> VarIntLengthFieldProto<U> {
+        VarIntLengthFieldProto { codec: self.codec.with_out() }
+    }
+
+    /// Sets the maximum length of a frame, excluding the length field.
+    pub fn max_frame_length(self, max_frame_length: usize) -> Self {
+        VarIntLengthFieldProto { codec: self.codec.max_frame_length(max_frame_length) }
+    }
+
+    /// Sets whether oversized frames are discarded instead of failing the decode.
+    pub fn discard_oversized(self, discard_oversized: bool) -> Self {
+        VarIntLengthFieldProto { codec: self.codec.discard_oversized(discard_oversized) }
+    }
+
+    /// Sets the maximum number of bytes of a length field.
+    pub fn max_varint_length(self, max_varint_length: usize) -> Self {
+        VarIntLengthFieldProto { codec: self.codec.max_varint_length(max_varint_length) }
+    }
+
+    fn codec(&self) -> VarIntLengthFieldCodec<O> {
+        self.codec.clone()
     }
 }
 
-impl<T> pipeline::ClientProto<T> for VarIntLengthFieldProto
-    where T: Io + 'static
+impl<T, O> pipeline::ClientProto<T> for VarIntLengthFieldProto<O>
+    where T: Io + 'static,
+          O: AsRef<[u8]> + 'static
 {
-    type Request = Vec<u8>;
-    type Response = Vec<u8>;
-    type Transport = Framed<T, VarIntLengthFieldCodec>;
+    type Request = O;
+    type Response = EasyBuf;
+    type Transport = Framed<T, VarIntLengthFieldCodec<O>>;
     type BindTransport = Result<Self::Transport, io::Error>;
 
     fn bind_transport(&self, io: T) -> Self::BindTransport {
@@ -34,12 +83,13 @@ impl<T> pipeline::ClientProto<T> for VarIntLengthFieldProto
     }
 }
 
-impl<T> pipeline::ServerProto<T> for VarIntLengthFieldProto
-    where T: Io + 'static
+impl<T, O> pipeline::ServerProto<T> for VarIntLengthFieldProto<O>
+    where T: Io + 'static,
+          O: AsRef<[u8]> + 'static
 {
-    type Request = Vec<u8>;
-    type Response = Vec<u8>;
-    type Transport = Framed<T, VarIntLengthFieldCodec>;
+    type Request = EasyBuf;
+    type Response = O;
+    type Transport = Framed<T, VarIntLengthFieldCodec<O>>;
     type BindTransport = Result<Self::Transport, io::Error>;
 
     fn bind_transport(&self, io: T) -> Self::BindTransport {
@@ -47,11 +97,62 @@ impl<T> pipeline::ServerProto<T> for VarIntLengthFieldProto
     }
 }
 
+#[cfg(feature = "async-service")]
+impl<O> ::service::NewCodec for VarIntLengthFieldProto<O> {
+    type Codec = VarIntLengthFieldCodec<O>;
+
+    fn new_codec(&self) -> VarIntLengthFieldCodec<O> {
+        self.codec()
+    }
+}
+
 /// Protocol codec used by [`VarIntLengthFieldProto`](./struct.VarIntLengthFieldProto.html).
-#[derive(Debug, Clone, Default)]
-pub struct VarIntLengthFieldCodec {
+///
+/// If a frame exceeds `max_frame_length`, decoding fails with
+/// [`Error::FrameTooLarge`](../error/enum.Error.html), or the frame is skipped if the codec is set
+/// to `discard_oversized`.
+///
+/// A length field longer than `max_varint_length` bytes (10 by default), or a length that does not
+/// fit in `usize`, fails the decode with [`Error::VarIntOverflow`](../error/enum.Error.html).
+/// Non-canonical encodings with redundant trailing zero groups, such as `0x80 0x00` for 0, fail
+/// with [`Error::InvalidEncoding`](../error/enum.Error.html).
+#[derive(Debug)]
+pub struct VarIntLengthFieldCodec<O = Vec<u8>> {
     pos: usize,
     size: Option<usize>,
+    max_varint_length: usize,
+    max_frame_length: Option<usize>,
+    discard_oversized: bool,
+    discarding: usize,
+    _out: PhantomData<fn(O)>,
+}
+
+impl<O> Clone for VarIntLengthFieldCodec<O> {
+    fn clone(&self) -> Self {
+        VarIntLengthFieldCodec {
+            pos: self.pos,
+            size: self.size,
+            max_varint_length: self.max_varint_length,
+            max_frame_length: self.max_frame_length,
+            discard_oversized: self.discard_oversized,
+            discarding: self.discarding,
+            _out: PhantomData,
+        }
+    }
+}
+
+impl Default for VarIntLengthFieldCodec {
+    fn default() -> VarIntLengthFieldCodec {
+        VarIntLengthFieldCodec {
+            pos: 0,
+            size: None,
+            max_varint_length: MAX_VARINT_LENGTH,
+            max_frame_length: None,
+            discard_oversized: false,
+            discarding: 0,
+            _out: PhantomData,
+        }
+    }
 }
 
 impl VarIntLengthFieldCodec {
@@ -60,51 +161,192 @@ impl VarIntLengthFieldCodec {
     }
 }
 
-impl Codec for VarIntLengthFieldCodec {
-    type In = Vec<u8>;
-    type Out = Vec<u8>;
+impl<O> VarIntLengthFieldCodec<O> {
+    /// Changes the type of items to be encoded.
+    pub fn with_out<U: AsRef<[u8]>>(self) -> VarIntLengthFieldCodec<U> {
+        VarIntLengthFieldCodec {
+            pos: self.pos,
+            size: self.size,
+            max_varint_length: self.max_varint_length,
+            max_frame_length: self.max_frame_length,
+            discard_oversized: self.discard_oversized,
+            discarding: self.discarding,
+            _out: PhantomData,
+        }
+    }
+
+    /// Sets the maximum number of bytes of a length field.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_varint_length` is zero or greater than 10.
+    pub fn max_varint_length(mut self, max_varint_length: usize) -> Self {
+        assert!(max_varint_length > 0 && max_varint_length <= MAX_VARINT_LENGTH);
+        self.max_varint_length = max_varint_length;
+        self
+    }
 
-    fn decode(&mut self, buf: &mut EasyBuf) -> io::Result<Option<Vec<u8>>> {
-        if let Some(size) = self.size {
-            if buf.len() >= size {
-                self.size = None;
-                let b = buf.drain_to(size);
-                Ok(Some(b.as_slice().to_vec()))
-            } else {
-                Ok(None)
+    /// Sets the maximum length of a frame, excluding the length field.
+    pub fn max_frame_length(mut self, max_frame_length: usize) -> Self {
+        self.max_frame_length = Some(max_frame_length);
+        self
+    }
+
+    /// Sets whether oversized frames are discarded instead of failing the decode.
+    pub fn discard_oversized(mut self, discard_oversized: bool) -> Self {
+        self.discard_oversized = discard_oversized;
+        self
+    }
+}
+
+impl<O> VarIntLengthFieldCodec<O> {
+    fn decode_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<Option<F>> {
+        'frame: loop {
+            if self.discarding > 0 {
+                let n = cmp::min(self.discarding, buf.len());
+                buf.split_to(n);
+                self.discarding -= n;
+                if self.discarding > 0 {
+                    return Ok(None);
+                }
             }
-        } else {
-            for pos in self.pos..buf.len() {
-                if &buf.as_slice()[pos] & 0x80 != 0 {
-                    self.pos += 1;
-                } else {
-                    let varint = buf.drain_to(self.pos + 1);
-                    let mut size: usize = 0;
-                    for (i, e) in varint.as_slice().iter().enumerate() {
-                        size += ((e & 0x7F) as usize) << (i * 7);
+
+            if self.size.is_none() {
+                for pos in self.pos..buf.len() {
+                    if pos >= self.max_varint_length {
+                        return Err(Error::VarIntOverflow.into());
+                    }
+
+                    if &buf.as_ref()[pos] & 0x80 != 0 {
+                        self.pos += 1;
+                    } else {
+                        let size = read_varint(&buf.as_ref()[..pos + 1])?;
+                        self.pos = pos + 1;
+
+                        if self.max_frame_length.is_some_and(|max| size > max) {
+                            if !self.discard_oversized {
+                                return Err(Error::FrameTooLarge.into());
+                            }
+                            buf.split_to(self.pos);
+                            self.pos = 0;
+                            self.discarding = size;
+                            continue 'frame;
+                        }
+
+                        self.size = Some(size);
+                        break;
                     }
-                    self.size = Some(size);
+                }
+            }
+
+            // the varint is kept in `buf` until the whole frame arrives
+            if let Some(size) = self.size {
+                if buf.len() - self.pos >= size {
+                    buf.split_to(self.pos);
                     self.pos = 0;
-                    return self.decode(buf);
+                    self.size = None;
+                    return Ok(Some(buf.split_to(size)));
                 }
             }
-            Ok(None)
+
+            return Ok(None);
         }
     }
 
-    fn encode(&mut self, item: Vec<u8>, buf: &mut Vec<u8>) -> io::Result<()> {
+    fn decode_eof_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<F> {
+        match self.decode_buf(buf)? {
+            Some(frame) => Ok(frame),
+            None => Err(Error::UnexpectedEof.into()),
+        }
+    }
+
+    fn encode_buf<W: FrameBufMut>(&mut self, item: &[u8], buf: &mut W) -> io::Result<()> {
+        let mut varint = [0; MAX_VARINT_LENGTH];
+        let mut len = 0;
         let mut size = item.len();
-        buf.reserve_exact(size +
-                          (mem::size_of::<usize>() * 8 - (size.leading_zeros() as usize) + 6) / 7);
-        while size > 0 {
-            buf.push(((size & 0x7F) as u8) | if size >= 0x80 { 0x80 } else { 0 });
+        // an empty frame still needs a single zero byte as its length field
+        loop {
+            varint[len] = ((size & 0x7F) as u8) | if size >= 0x80 { 0x80 } else { 0 };
+            len += 1;
             size >>= 7;
+            if size == 0 {
+                break;
+            }
         }
-        buf.extend(item);
+
+        buf.reserve(len + item.len());
+        buf.put_slice(&varint[..len]);
+        buf.put_slice(item);
         Ok(())
     }
 }
 
+impl<O: AsRef<[u8]>> Codec for VarIntLengthFieldCodec<O> {
+    type In = EasyBuf;
+    type Out = O;
+
+    fn decode(&mut self, buf: &mut EasyBuf) -> io::Result<Option<EasyBuf>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut EasyBuf) -> io::Result<EasyBuf> {
+        self.decode_eof_buf(buf)
+    }
+
+    fn encode(&mut self, item: O, buf: &mut Vec<u8>) -> io::Result<()> {
+        self.encode_buf(item.as_ref(), buf)
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl<O> ::tokio_util::codec::Decoder for VarIntLengthFieldCodec<O> {
+    type Item = BytesMut;
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+        if buf.is_empty() {
+            Ok(None)
+        } else {
+            self.decode_eof_buf(buf).map(Some)
+        }
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl<O: AsRef<[u8]>> ::tokio_util::codec::Encoder<O> for VarIntLengthFieldCodec<O> {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: O, buf: &mut BytesMut) -> io::Result<()> {
+        self.encode_buf(item.as_ref(), buf)
+    }
+}
+
+/// Reads a complete varint from `bytes`.
+fn read_varint(bytes: &[u8]) -> io::Result<usize> {
+    if bytes.len() > 1 && bytes[bytes.len() - 1] == 0 {
+        return Err(Error::InvalidEncoding.into());
+    }
+
+    let mut value: u64 = 0;
+    for (i, e) in bytes.iter().enumerate() {
+        let bits = (e & 0x7F) as u64;
+        let shift = i * 7;
+        if shift >= 64 || (bits << shift) >> shift != bits {
+            return Err(Error::VarIntOverflow.into());
+        }
+        value |= bits << shift;
+    }
+
+    if value > usize::MAX as u64 {
+        return Err(Error::VarIntOverflow.into());
+    }
+    Ok(value as usize)
+}
+
 #[test]
 fn test_varintlengthfield() {
     let mut p = VarIntLengthFieldCodec::new();
@@ -112,22 +354,157 @@ fn test_varintlengthfield() {
     let mut buf = EasyBuf::new();
     buf.get_mut().extend(&[1, 65, 2, 65, 66]);
 
-    assert_eq!(p.decode(&mut buf).unwrap().unwrap(), vec![65]);
-    assert_eq!(p.decode(&mut buf).unwrap().unwrap(), vec![65, 66]);
+    assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), &[65]);
+    assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), &[65, 66]);
     assert!(buf.as_slice().is_empty());
     assert!(p.decode(&mut buf).unwrap().is_none());
 
     p.encode(vec![0, 1, 2], &mut buf.get_mut()).unwrap();
-    assert_eq!(p.decode(&mut buf).unwrap().unwrap(), vec![0, 1, 2]);
+    assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), &[0, 1, 2]);
     assert!(p.decode(&mut buf).unwrap().is_none());
 
     let mut test_large = |len| {
         let data: Vec<_> = (0..10).cycle().take(len).collect();
         p.encode(data.clone(), &mut buf.get_mut()).unwrap();
-        assert_eq!(p.decode(&mut buf).unwrap().unwrap(), data);
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), &data[..]);
     };
 
     test_large(128);
     test_large(300);
     test_large(1000);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_varintlengthfield_max_frame_length() {
+    let mut p = VarIntLengthFieldCodec::new().max_frame_length(2);
+
+    let mut buf = EasyBuf::new();
+    buf.get_mut().extend(&[2, 65, 66, 3, 65, 66, 67]);
+
+    assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), &[65, 66]);
+    let err = p.decode(&mut buf).unwrap_err();
+    assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+               Some(&Error::FrameTooLarge));
+
+    let mut p = VarIntLengthFieldCodec::new().max_frame_length(2).discard_oversized(true);
+
+    let mut buf = EasyBuf::new();
+    buf.get_mut().extend(&[3, 65, 66]);
+    assert!(p.decode(&mut buf).unwrap().is_none());
+    buf.get_mut().extend(&[67, 1, 68]);
+    assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), &[68]);
+
+    // discarding a long run of oversized frames does not recurse
+    let mut v = [3, 65, 66, 67].repeat(300_000);
+    v.extend(&[1, 68]);
+    let mut buf = EasyBuf::from(v);
+    assert_eq!(p.decode(&mut buf).unwrap().unwrap().as_slice(), &[68]);
+}
+
+#[test]
+fn test_varintlengthfield_eof() {
+    let mut p = VarIntLengthFieldCodec::new();
+
+    let mut buf = EasyBuf::new();
+    buf.get_mut().extend(&[1, 65, 2, 65]);
+
+    assert_eq!(p.decode_eof(&mut buf).unwrap().as_slice(), &[65]);
+    let err = p.decode_eof(&mut buf).unwrap_err();
+    assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+               Some(&Error::UnexpectedEof));
+    assert_eq!(buf.len(), 2);
+
+    let mut buf = EasyBuf::new();
+    buf.get_mut().extend(&[0x80]);
+    assert!(VarIntLengthFieldCodec::new().decode_eof(&mut buf).is_err());
+}
+
+#[test]
+fn test_varintlengthfield_overflow() {
+    fn decode(bytes: &[u8]) -> io::Result<Option<usize>> {
+        let mut p = VarIntLengthFieldCodec::new();
+        let mut buf = EasyBuf::from(bytes.to_vec());
+        p.decode(&mut buf).map(|_| p.size)
+    }
+
+    fn assert_overflow(res: io::Result<Option<usize>>) {
+        assert_eq!(res.unwrap_err().get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::VarIntOverflow));
+    }
+
+    if cfg!(target_pointer_width = "64") {
+        // 2^63
+        assert_eq!(decode(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01]).unwrap(),
+                   Some(1 << 63));
+        // 2^64 - 1
+        assert_eq!(decode(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]).unwrap(),
+                   Some(usize::MAX));
+    }
+
+    // 2^64
+    assert_overflow(decode(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x02]));
+    // 11 bytes
+    assert_overflow(decode(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00]));
+    // continuation bytes never end
+    assert_overflow(decode(&[0xFF; 16]));
+
+    // non-canonical encodings
+    for bytes in &[&[0x80, 0x00][..], &[0x81, 0x80, 0x80, 0x00, 65][..]] {
+        assert_eq!(decode(bytes).unwrap_err().get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::InvalidEncoding));
+    }
+    let mut buf = EasyBuf::from(vec![0x00]);
+    assert_eq!(VarIntLengthFieldCodec::new().decode(&mut buf).unwrap().unwrap().as_slice(), b"");
+
+    let mut p = VarIntLengthFieldCodec::new().max_varint_length(2);
+    let mut buf = EasyBuf::from(vec![0x80, 0x80, 0x01]);
+    assert!(p.decode(&mut buf).is_err());
+}
+
+#[test]
+fn test_varintlengthfield_roundtrip() {
+    use quickcheck::quickcheck;
+
+    fn roundtrip(data: Vec<u8>) -> bool {
+        let mut p = VarIntLengthFieldCodec::new();
+        let mut buf = EasyBuf::new();
+        p.encode(data.clone(), &mut buf.get_mut()).unwrap();
+        p.encode(data.clone(), &mut buf.get_mut()).unwrap();
+        p.decode(&mut buf).unwrap().map(|b| b.as_slice().to_vec()) == Some(data.clone()) &&
+        p.decode(&mut buf).unwrap().map(|b| b.as_slice().to_vec()) == Some(data) &&
+        buf.len() == 0
+    }
+
+    for &(len, varint) in &[(0, &[0x00][..]),
+                            (1, &[0x01][..]),
+                            (127, &[0x7F][..]),
+                            (128, &[0x80, 0x01][..]),
+                            (16383, &[0xFF, 0x7F][..]),
+                            (16384, &[0x80, 0x80, 0x01][..])] {
+        let mut buf = vec![];
+        VarIntLengthFieldCodec::new().encode(vec![0; len], &mut buf).unwrap();
+        assert_eq!(&buf[..varint.len()], varint);
+        assert_eq!(buf.len(), varint.len() + len);
+
+        assert!(roundtrip((0..10).cycle().take(len).collect()));
+    }
+
+    quickcheck(roundtrip as fn(Vec<u8>) -> bool);
+}
+
+#[cfg(feature = "tokio-util-codec")]
+#[test]
+fn test_varintlengthfield_tokio_util() {
+    use tokio_util::codec::{Decoder, Encoder};
+
+    let mut p = VarIntLengthFieldCodec::new();
+
+    let mut buf = BytesMut::new();
+    Encoder::encode(&mut p, vec![0; 300], &mut buf).unwrap();
+    Encoder::encode(&mut p, vec![], &mut buf).unwrap();
+    assert_eq!(&buf[..2], &[0xAC, 0x02]);
+
+    assert_eq!(Decoder::decode(&mut p, &mut buf).unwrap().unwrap().len(), 300);
+    assert_eq!(Decoder::decode(&mut p, &mut buf).unwrap().unwrap().len(), 0);
+    assert!(Decoder::decode_eof(&mut p, &mut buf).unwrap().is_none());
+}
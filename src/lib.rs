@@ -1,4 +1,17 @@
 //! `framecodecs` provides simple protocol implementations to be used with tokio-proto.
+//!
+//! With the `tokio-util-codec` feature enabled, the codecs also implement tokio-util's `Decoder`
+//! and `Encoder` over `BytesMut`, yielding `BytesMut` frames where tokio-core's `Codec` yields
+//! `EasyBuf`.
+//!
+//! The `async-service` feature additionally provides [`service`](service/index.html), a set of
+//! tokio-based clients and servers working with `tower::Service`, in place of tokio-proto.
+//!
+//! The `regex` feature enables `frame::RegexDelimiter`, splitting frames at matches of a regular
+//! expression.
+//!
+//! The `websocket` feature enables [`websocket`](websocket/index.html), which masks the frames
+//! sent by clients with keys from the operating system's random number generator.
 
 #[macro_use]
 extern crate futures;
@@ -7,8 +20,37 @@ extern crate tokio_proto;
 extern crate byteorder;
 extern crate memchr;
 extern crate twoway;
+#[cfg(feature = "tokio-util-codec")]
+extern crate bytes;
+#[cfg(feature = "tokio-util-codec")]
+extern crate tokio_util;
+#[cfg(feature = "regex")]
+extern crate regex;
+#[cfg(feature = "websocket")]
+extern crate getrandom;
+#[cfg(feature = "async-service")]
+extern crate tokio;
+#[cfg(feature = "async-service")]
+extern crate tower_service;
+#[cfg(feature = "async-service")]
+extern crate futures_util;
 
+#[cfg(test)]
+extern crate quickcheck;
+
+pub mod buf;
+pub mod error;
 pub mod frame;
 pub mod request_id_field;
 pub mod remote_addr;
-pub mod decode_to_vec;
\ No newline at end of file
+pub mod decode_to_vec;
+pub mod resp;
+pub mod memcache;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+#[cfg(feature = "async-service")]
+pub mod service;
+#[cfg(test)]
+mod test_util;
+
+pub use error::Error;
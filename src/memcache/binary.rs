@@ -0,0 +1,352 @@
+use tokio_core::io::{Codec, Io, EasyBuf, Framed};
+use tokio_proto::multiplex::{ServerProto, ClientProto, RequestId};
+use byteorder::{BigEndian, ByteOrder};
+#[cfg(feature = "tokio-util-codec")]
+use bytes::BytesMut;
+use buf::{FrameBuf, FrameBufMut};
+use error::Error;
+use std::io;
+
+const HEADER_LEN: usize = 24;
+
+/// Magic byte of request packets.
+pub const REQUEST_MAGIC: u8 = 0x80;
+/// Magic byte of response packets.
+pub const RESPONSE_MAGIC: u8 = 0x81;
+
+/// A packet of the memcached binary protocol, except for the `opaque` field, which is carried as
+/// the `RequestId`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinaryPacket {
+    pub magic: u8,
+    pub opcode: u8,
+    pub data_type: u8,
+    /// The vbucket id of a request, or the status of a response.
+    pub status: u16,
+    pub cas: u64,
+    pub extras: Vec<u8>,
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+}
+
+impl BinaryPacket {
+    /// Creates an empty request packet with `opcode`.
+    pub fn request(opcode: u8) -> Self {
+        BinaryPacket {
+            magic: REQUEST_MAGIC,
+            opcode,
+            data_type: 0,
+            status: 0,
+            cas: 0,
+            extras: Vec::new(),
+            key: Vec::new(),
+            value: Vec::new(),
+        }
+    }
+
+    /// Creates an empty response packet with `opcode` and `status`.
+    pub fn response(opcode: u8, status: u16) -> Self {
+        BinaryPacket {
+            magic: RESPONSE_MAGIC,
+            status,
+            ..BinaryPacket::request(opcode)
+        }
+    }
+}
+
+/// Memcached binary protocol.
+///
+/// A multiplexed protocol of [`BinaryPacket`](./struct.BinaryPacket.html)s, whose `opaque` field
+/// holds the `RequestId`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinaryProto {
+    codec: BinaryCodec,
+}
+
+impl BinaryProto {
+    pub fn new() -> Self {
+        BinaryProto { codec: BinaryCodec::new() }
+    }
+
+    /// Sets the maximum length of the body of a packet.
+    pub fn max_frame_length(self, max_frame_length: usize) -> Self {
+        BinaryProto { codec: self.codec.max_frame_length(max_frame_length) }
+    }
+}
+
+impl Default for BinaryProto {
+    fn default() -> Self {
+        BinaryProto::new()
+    }
+}
+
+impl<T: Io + 'static> ServerProto<T> for BinaryProto {
+    type Request = BinaryPacket;
+    type Response = BinaryPacket;
+    type Transport = Framed<T, BinaryCodec>;
+    type BindTransport = io::Result<Self::Transport>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(io.framed(self.codec.clone()))
+    }
+}
+
+impl<T: Io + 'static> ClientProto<T> for BinaryProto {
+    type Request = BinaryPacket;
+    type Response = BinaryPacket;
+    type Transport = Framed<T, BinaryCodec>;
+    type BindTransport = io::Result<Self::Transport>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(io.framed(self.codec.clone()))
+    }
+}
+
+#[cfg(feature = "async-service")]
+impl ::service::NewCodec for BinaryProto {
+    type Codec = BinaryCodec;
+
+    fn new_codec(&self) -> BinaryCodec {
+        self.codec.clone()
+    }
+}
+
+/// Protocol codec used by [`BinaryProto`](./struct.BinaryProto.html).
+///
+/// A packet with an unknown magic byte fails the decode with
+/// [`Error::InvalidEncoding`](../error/enum.Error.html), and one whose extras and key exceed the
+/// body with `Error::InvalidLength`. Encoding a packet whose key, extras or body is too long for
+/// its length field fails with `Error::FrameTooLarge`, and one whose `RequestId` does not fit in
+/// the 32-bit `opaque` field with `Error::InvalidEncoding`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinaryCodec {
+    max_frame_length: Option<usize>,
+}
+
+impl BinaryCodec {
+    pub fn new() -> Self {
+        BinaryCodec {
+            max_frame_length: None,
+        }
+    }
+
+    /// Sets the maximum length of the body of a packet.
+    pub fn max_frame_length(mut self, max_frame_length: usize) -> Self {
+        self.max_frame_length = Some(max_frame_length);
+        self
+    }
+
+    fn decode_buf<F: FrameBuf>(&mut self, buf: &mut F)
+                               -> io::Result<Option<(RequestId, BinaryPacket)>> {
+        if buf.len() < HEADER_LEN {
+            return Ok(None);
+        }
+
+        let (key_len, extras_len, body_len) = {
+            let header = &buf.as_ref()[..HEADER_LEN];
+            if header[0] != REQUEST_MAGIC && header[0] != RESPONSE_MAGIC {
+                return Err(Error::InvalidEncoding.into());
+            }
+            (BigEndian::read_u16(&header[2..4]) as usize,
+             header[4] as usize,
+             BigEndian::read_u32(&header[8..12]) as usize)
+        };
+
+        if self.max_frame_length.is_some_and(|max| body_len > max) {
+            return Err(Error::FrameTooLarge.into());
+        }
+        if key_len + extras_len > body_len {
+            return Err(Error::InvalidLength.into());
+        }
+        if buf.len() - HEADER_LEN < body_len {
+            return Ok(None);
+        }
+
+        let frame = buf.split_to(HEADER_LEN + body_len);
+        let frame = frame.as_ref();
+        let opaque = BigEndian::read_u32(&frame[12..16]);
+
+        let body = &frame[HEADER_LEN..];
+        let packet = BinaryPacket {
+            magic: frame[0],
+            opcode: frame[1],
+            data_type: frame[5],
+            status: BigEndian::read_u16(&frame[6..8]),
+            cas: BigEndian::read_u64(&frame[16..24]),
+            extras: body[..extras_len].to_vec(),
+            key: body[extras_len..extras_len + key_len].to_vec(),
+            value: body[extras_len + key_len..].to_vec(),
+        };
+        Ok(Some((opaque as RequestId, packet)))
+    }
+
+    fn decode_eof_buf<F: FrameBuf>(&mut self, buf: &mut F)
+                                   -> io::Result<Option<(RequestId, BinaryPacket)>> {
+        match self.decode_buf(buf)? {
+            Some(frame) => Ok(Some(frame)),
+            None if buf.is_empty() => Ok(None),
+            None => Err(Error::UnexpectedEof.into()),
+        }
+    }
+
+    fn encode_buf<W: FrameBufMut>(&mut self, id: RequestId, packet: &BinaryPacket, buf: &mut W)
+                                  -> io::Result<()> {
+        let body_len = packet.extras.len() + packet.key.len() + packet.value.len();
+        if packet.key.len() > u16::MAX as usize || packet.extras.len() > 0xFF ||
+           body_len > u32::MAX as usize {
+            return Err(Error::FrameTooLarge.into());
+        }
+
+        let opaque = id as u32;
+        if opaque as RequestId != id {
+            return Err(Error::InvalidEncoding.into());
+        }
+
+        let mut header = [0; HEADER_LEN];
+        header[0] = packet.magic;
+        header[1] = packet.opcode;
+        BigEndian::write_u16(&mut header[2..4], packet.key.len() as u16);
+        header[4] = packet.extras.len() as u8;
+        header[5] = packet.data_type;
+        BigEndian::write_u16(&mut header[6..8], packet.status);
+        BigEndian::write_u32(&mut header[8..12], body_len as u32);
+        BigEndian::write_u32(&mut header[12..16], opaque);
+        BigEndian::write_u64(&mut header[16..24], packet.cas);
+
+        buf.reserve(HEADER_LEN + body_len);
+        buf.put_slice(&header);
+        buf.put_slice(&packet.extras);
+        buf.put_slice(&packet.key);
+        buf.put_slice(&packet.value);
+        Ok(())
+    }
+}
+
+impl Default for BinaryCodec {
+    fn default() -> Self {
+        BinaryCodec::new()
+    }
+}
+
+impl Codec for BinaryCodec {
+    type In = (RequestId, BinaryPacket);
+    type Out = (RequestId, BinaryPacket);
+
+    fn decode(&mut self, buf: &mut EasyBuf) -> io::Result<Option<(RequestId, BinaryPacket)>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut EasyBuf) -> io::Result<(RequestId, BinaryPacket)> {
+        match self.decode_eof_buf(buf)? {
+            Some(frame) => Ok(frame),
+            None => Err(Error::UnexpectedEof.into()),
+        }
+    }
+
+    fn encode(&mut self, (id, packet): (RequestId, BinaryPacket), buf: &mut Vec<u8>)
+              -> io::Result<()> {
+        self.encode_buf(id, &packet, buf)
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl ::tokio_util::codec::Decoder for BinaryCodec {
+    type Item = (RequestId, BinaryPacket);
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<(RequestId, BinaryPacket)>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> io::Result<Option<(RequestId, BinaryPacket)>> {
+        self.decode_eof_buf(buf)
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl ::tokio_util::codec::Encoder<(RequestId, BinaryPacket)> for BinaryCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, (id, packet): (RequestId, BinaryPacket), buf: &mut BytesMut)
+              -> io::Result<()> {
+        self.encode_buf(id, &packet, buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memcache_binary() {
+        // GET "Hello" from the protocol specification
+        let get = b"\x80\x00\x00\x05\x00\x00\x00\x00\x00\x00\x00\x05\x00\x00\x00\x07\
+                    \x00\x00\x00\x00\x00\x00\x00\x00Hello";
+
+        let mut p = BinaryCodec::new();
+        let mut buf = EasyBuf::new();
+        buf.get_mut().extend_from_slice(&get[..26]);
+        assert!(p.decode(&mut buf).unwrap().is_none());
+        buf.get_mut().extend_from_slice(&get[26..]);
+
+        let mut request = BinaryPacket::request(0x00);
+        request.key = b"Hello".to_vec();
+        assert_eq!(p.decode(&mut buf).unwrap().unwrap(), (7, request.clone()));
+        assert_eq!(buf.len(), 0);
+
+        let mut v = vec![];
+        p.encode((7, request), &mut v).unwrap();
+        assert_eq!(&v[..], &get[..]);
+
+        let mut response = BinaryPacket::response(0x00, 0);
+        response.extras = vec![0xDE, 0xAD, 0xBE, 0xEF];
+        response.value = b"World".to_vec();
+        response.cas = 1;
+        let mut v = vec![];
+        p.encode((7, response.clone()), &mut v).unwrap();
+        assert_eq!(&v[8..12], &[0, 0, 0, 9]);
+        assert_eq!(p.decode(&mut EasyBuf::from(v)).unwrap().unwrap(), (7, response));
+    }
+
+    #[test]
+    fn test_memcache_binary_opaque() {
+        let mut p = BinaryCodec::new();
+        let id = (1 << 32) + 7;
+
+        let mut v = vec![];
+        let err = p.encode((id, BinaryPacket::request(0x0A)), &mut v).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::InvalidEncoding));
+        assert!(v.is_empty());
+
+        p.encode((0xFFFF_FFFF, BinaryPacket::request(0x0A)), &mut v).unwrap();
+        assert_eq!(&v[12..16], &[0xFF; 4]);
+        assert_eq!(p.decode(&mut EasyBuf::from(v)).unwrap().unwrap().0, 0xFFFF_FFFF);
+    }
+
+    #[test]
+    fn test_memcache_binary_invalid() {
+        fn decode_err(p: &mut BinaryCodec, header: &[u8]) -> Error {
+            let err = p.decode(&mut EasyBuf::from(header.to_vec())).unwrap_err();
+            *err.get_ref().and_then(|e| e.downcast_ref::<Error>()).unwrap()
+        }
+
+        let mut header = [0; HEADER_LEN];
+        let mut p = BinaryCodec::new().max_frame_length(4);
+        assert_eq!(decode_err(&mut p, &header), Error::InvalidEncoding);
+
+        header[0] = REQUEST_MAGIC;
+        header[3] = 3;
+        header[11] = 2;
+        assert_eq!(decode_err(&mut p, &header), Error::InvalidLength);
+
+        header[11] = 5;
+        assert_eq!(decode_err(&mut p, &header), Error::FrameTooLarge);
+
+        let mut packet = BinaryPacket::request(0x01);
+        packet.extras = vec![0; 256];
+        let err = p.encode((0, packet), &mut vec![]).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::FrameTooLarge));
+    }
+}
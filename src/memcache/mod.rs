@@ -0,0 +1,11 @@
+//! Memcached protocols.
+//!
+//! [`TextProto`](./struct.TextProto.html) is the pipelined text protocol, whose frames are command
+//! or response lines optionally followed by a data block.
+//! [`BinaryProto`](./struct.BinaryProto.html) is the binary protocol, multiplexed by its `opaque`
+//! field.
+
+mod text;
+pub use self::text::{TextProto, TextCodec, TextFrame};
+mod binary;
+pub use self::binary::{BinaryProto, BinaryCodec, BinaryPacket, REQUEST_MAGIC, RESPONSE_MAGIC};
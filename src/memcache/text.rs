@@ -0,0 +1,352 @@
+use tokio_core::io::{Codec, Io, EasyBuf, Framed};
+use tokio_proto::pipeline::{ServerProto, ClientProto};
+#[cfg(feature = "tokio-util-codec")]
+use bytes::BytesMut;
+use buf::{FrameBuf, FrameBufMut};
+use error::Error;
+use std::{io, str};
+
+/// A frame of the memcached text protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextFrame {
+    /// The command or response line, without the line ending.
+    pub line: Vec<u8>,
+    /// The data block following the line, without the line ending.
+    pub data: Option<Vec<u8>>,
+}
+
+impl TextFrame {
+    /// Creates a frame without a data block.
+    pub fn new<L: Into<Vec<u8>>>(line: L) -> Self {
+        TextFrame {
+            line: line.into(),
+            data: None,
+        }
+    }
+
+    /// Creates a frame followed by the data block `data`.
+    pub fn with_data<L: Into<Vec<u8>>, D: Into<Vec<u8>>>(line: L, data: D) -> Self {
+        TextFrame {
+            line: line.into(),
+            data: Some(data.into()),
+        }
+    }
+
+    /// Returns the space-separated words of the line.
+    pub fn args(&self) -> Vec<&[u8]> {
+        args(&self.line)
+    }
+}
+
+fn args(line: &[u8]) -> Vec<&[u8]> {
+    line.split(|&b| b == b' ').filter(|a| !a.is_empty()).collect()
+}
+
+/// Returns the length of the data block announced by `line`, if any.
+fn data_len(line: &[u8]) -> io::Result<Option<usize>> {
+    let args = args(line);
+    let index = match args.first() {
+        Some(&b"set") | Some(&b"add") | Some(&b"replace") | Some(&b"append") |
+        Some(&b"prepend") | Some(&b"cas") => 4,
+        Some(&b"VALUE") => 3,
+        Some(&b"ms") => 2,
+        Some(&b"VA") => 1,
+        _ => return Ok(None),
+    };
+
+    match args.get(index).and_then(|a| str::from_utf8(a).ok()).and_then(|a| a.parse().ok()) {
+        Some(len) => Ok(Some(len)),
+        None => Err(Error::InvalidLength.into()),
+    }
+}
+
+/// Memcached text protocol.
+///
+/// Requests and responses are [`TextFrame`](./struct.TextFrame.html)s, so that the protocol can
+/// be used both by servers and by clients.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextProto {
+    codec: TextCodec,
+}
+
+impl TextProto {
+    pub fn new() -> Self {
+        TextProto { codec: TextCodec::new() }
+    }
+
+    /// Sets the maximum length of a line, excluding the line ending.
+    pub fn max_line_length(self, max_line_length: usize) -> Self {
+        TextProto { codec: self.codec.max_line_length(max_line_length) }
+    }
+
+    /// Sets the maximum length of a data block, excluding the line ending.
+    pub fn max_data_length(self, max_data_length: usize) -> Self {
+        TextProto { codec: self.codec.max_data_length(max_data_length) }
+    }
+}
+
+impl Default for TextProto {
+    fn default() -> Self {
+        TextProto::new()
+    }
+}
+
+impl<T: Io + 'static> ServerProto<T> for TextProto {
+    type Request = TextFrame;
+    type Response = TextFrame;
+    type Transport = Framed<T, TextCodec>;
+    type BindTransport = io::Result<Self::Transport>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(io.framed(self.codec.clone()))
+    }
+}
+
+impl<T: Io + 'static> ClientProto<T> for TextProto {
+    type Request = TextFrame;
+    type Response = TextFrame;
+    type Transport = Framed<T, TextCodec>;
+    type BindTransport = io::Result<Self::Transport>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(io.framed(self.codec.clone()))
+    }
+}
+
+#[cfg(feature = "async-service")]
+impl ::service::NewCodec for TextProto {
+    type Codec = TextCodec;
+
+    fn new_codec(&self) -> TextCodec {
+        self.codec.clone()
+    }
+}
+
+/// Protocol codec used by [`TextProto`](./struct.TextProto.html).
+///
+/// Lines are terminated by CRLF, or LF alone when decoding. After a line announcing a data block
+/// (`set`, `add`, `replace`, `append`, `prepend`, `cas` and `ms` commands, and `VALUE` and `VA`
+/// responses), the codec reads the announced number of bytes followed by CRLF.
+///
+/// A missing or invalid byte count fails the decode with
+/// [`Error::InvalidLength`](../error/enum.Error.html), and a data block not followed by CRLF
+/// with `Error::InvalidEncoding`. Encoding a frame whose data block does not match its line fails
+/// with `Error::WrongFrameLength`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextCodec {
+    max_line_length: Option<usize>,
+    max_data_length: Option<usize>,
+    searched: usize,
+    line: Option<(Vec<u8>, usize)>,
+}
+
+impl TextCodec {
+    pub fn new() -> Self {
+        TextCodec {
+            max_line_length: None,
+            max_data_length: None,
+            searched: 0,
+            line: None,
+        }
+    }
+
+    /// Sets the maximum length of a line, excluding the line ending.
+    pub fn max_line_length(mut self, max_line_length: usize) -> Self {
+        self.max_line_length = Some(max_line_length);
+        self
+    }
+
+    /// Sets the maximum length of a data block, excluding the line ending.
+    pub fn max_data_length(mut self, max_data_length: usize) -> Self {
+        self.max_data_length = Some(max_data_length);
+        self
+    }
+
+    fn decode_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<Option<TextFrame>> {
+        if self.line.is_none() {
+            let end = match ::memchr::memchr(b'\n', &buf.as_ref()[self.searched..]) {
+                Some(pos) => self.searched + pos,
+                None => {
+                    self.searched = buf.len();
+                    if self.max_line_length.is_some_and(|max| buf.len() > max + 1) {
+                        return Err(Error::FrameTooLarge.into());
+                    }
+                    return Ok(None);
+                }
+            };
+            self.searched = 0;
+
+            let line = buf.split_to(end + 1);
+            let line = &line.as_ref()[..end];
+            let line = if line.last() == Some(&b'\r') { &line[..end - 1] } else { line };
+            if self.max_line_length.is_some_and(|max| line.len() > max) {
+                return Err(Error::FrameTooLarge.into());
+            }
+
+            match data_len(line)? {
+                Some(len) => {
+                    if self.max_data_length.is_some_and(|max| len > max) {
+                        return Err(Error::FrameTooLarge.into());
+                    }
+                    self.line = Some((line.to_vec(), len));
+                }
+                None => return Ok(Some(TextFrame::new(line))),
+            }
+        }
+
+        let len = self.line.as_ref().unwrap().1;
+        if buf.len() < len || buf.len() - len < 2 {
+            return Ok(None);
+        }
+        if &buf.as_ref()[len..len + 2] != b"\r\n" {
+            self.line = None;
+            return Err(Error::InvalidEncoding.into());
+        }
+
+        let data = buf.split_to(len);
+        buf.split_to(2);
+        let (line, _) = self.line.take().unwrap();
+        Ok(Some(TextFrame::with_data(line, data.as_ref())))
+    }
+
+    fn decode_eof_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<Option<TextFrame>> {
+        match self.decode_buf(buf)? {
+            Some(frame) => Ok(Some(frame)),
+            None if buf.is_empty() && self.line.is_none() => Ok(None),
+            None => Err(Error::UnexpectedEof.into()),
+        }
+    }
+
+    fn encode_buf<W: FrameBufMut>(&mut self, item: &TextFrame, buf: &mut W) -> io::Result<()> {
+        if item.line.iter().any(|&b| b == b'\r' || b == b'\n') {
+            return Err(Error::InvalidEncoding.into());
+        }
+        match (data_len(&item.line)?, item.data.as_ref().map(|d| d.len())) {
+            (Some(expected), Some(len)) if expected == len => {}
+            (None, None) => {}
+            _ => return Err(Error::WrongFrameLength.into()),
+        }
+
+        buf.put_slice(&item.line);
+        buf.put_slice(b"\r\n");
+        if let Some(ref data) = item.data {
+            buf.reserve(data.len() + 2);
+            buf.put_slice(data);
+            buf.put_slice(b"\r\n");
+        }
+        Ok(())
+    }
+}
+
+impl Default for TextCodec {
+    fn default() -> Self {
+        TextCodec::new()
+    }
+}
+
+impl Codec for TextCodec {
+    type In = TextFrame;
+    type Out = TextFrame;
+
+    fn decode(&mut self, buf: &mut EasyBuf) -> io::Result<Option<TextFrame>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut EasyBuf) -> io::Result<TextFrame> {
+        match self.decode_eof_buf(buf)? {
+            Some(frame) => Ok(frame),
+            None => Err(Error::UnexpectedEof.into()),
+        }
+    }
+
+    fn encode(&mut self, item: TextFrame, buf: &mut Vec<u8>) -> io::Result<()> {
+        self.encode_buf(&item, buf)
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl ::tokio_util::codec::Decoder for TextCodec {
+    type Item = TextFrame;
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<TextFrame>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> io::Result<Option<TextFrame>> {
+        self.decode_eof_buf(buf)
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl ::tokio_util::codec::Encoder<TextFrame> for TextCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: TextFrame, buf: &mut BytesMut) -> io::Result<()> {
+        self.encode_buf(&item, buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(p: &mut TextCodec, input: &[u8]) -> Vec<TextFrame> {
+        let mut buf = EasyBuf::new();
+        let mut frames = vec![];
+        for &b in input {
+            buf.get_mut().push(b);
+            while let Some(f) = p.decode(&mut buf).unwrap() {
+                frames.push(f);
+            }
+        }
+        assert_eq!(buf.len(), 0);
+        frames
+    }
+
+    #[test]
+    fn test_memcache_text() {
+        let mut p = TextCodec::new();
+        let frames = decode_all(&mut p,
+                                b"set k 0 0 7\r\nhel\r\nlo\r\nget k\nVALUE k 0 2 5\r\nhi\r\nEND\r\n\
+                                  ms k 1 T0\r\nx\r\n");
+        assert_eq!(frames,
+                   vec![TextFrame::with_data("set k 0 0 7", "hel\r\nlo"),
+                        TextFrame::new("get k"),
+                        TextFrame::with_data("VALUE k 0 2 5", "hi"),
+                        TextFrame::new("END"),
+                        TextFrame::with_data("ms k 1 T0", "x")]);
+        assert_eq!(frames[0].args(), vec![&b"set"[..], b"k", b"0", b"0", b"7"]);
+
+        let mut v = vec![];
+        p.encode(TextFrame::with_data("cas k 0 0 1 42 noreply", "a"), &mut v).unwrap();
+        p.encode(TextFrame::new("STORED"), &mut v).unwrap();
+        assert_eq!(v, b"cas k 0 0 1 42 noreply\r\na\r\nSTORED\r\n");
+    }
+
+    #[test]
+    fn test_memcache_text_invalid() {
+        fn decode_err(p: &mut TextCodec, input: &[u8]) -> Error {
+            let err = p.decode(&mut EasyBuf::from(input.to_vec())).unwrap_err();
+            *err.get_ref().and_then(|e| e.downcast_ref::<Error>()).unwrap()
+        }
+
+        let mut p = TextCodec::new();
+        assert_eq!(decode_err(&mut p, b"set k 0 0\r\n"), Error::InvalidLength);
+        assert_eq!(decode_err(&mut p, b"set k 0 0 x\r\n"), Error::InvalidLength);
+        assert_eq!(decode_err(&mut p, b"set k 0 0 1\r\nab\r\n"), Error::InvalidEncoding);
+
+        let mut p = TextCodec::new().max_line_length(8).max_data_length(4);
+        assert_eq!(decode_err(&mut p, b"get abcdefgh"), Error::FrameTooLarge);
+        assert_eq!(decode_err(&mut p, b"set k 0 0 5\r\n"), Error::FrameTooLarge);
+
+        let err = p.encode(TextFrame::with_data("set k 0 0 2", "a"), &mut vec![]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = p.encode(TextFrame::new("set k 0 0 2"), &mut vec![]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let mut p = TextCodec::new();
+        let err = p.decode_eof(&mut EasyBuf::from(b"set k 0 0 1\r\n".to_vec())).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
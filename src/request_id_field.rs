@@ -3,6 +3,8 @@
 use tokio_core::io::{EasyBuf, Codec, Io, Framed};
 use tokio_proto::multiplex::{self, RequestId};
 use byteorder::{self, ByteOrder};
+#[cfg(feature = "tokio-util-codec")]
+use bytes::BytesMut;
 use std::marker::PhantomData;
 use std::io;
 
@@ -57,6 +59,15 @@ impl<B, C, T> multiplex::ServerProto<T> for RequestIdFieldProto<B, C>
     }
 }
 
+#[cfg(feature = "async-service")]
+impl<B, C: Clone> ::service::NewCodec for RequestIdFieldProto<B, C> {
+    type Codec = RequestIdFieldCodec<B, C>;
+
+    fn new_codec(&self) -> RequestIdFieldCodec<B, C> {
+        RequestIdFieldCodec::new(self.inner.clone())
+    }
+}
+
 /// Protocol codec used by [`RequestIdFieldProto`](./struct.RequestIdFieldProto.html).
 #[derive(Debug, Clone, Default)]
 pub struct RequestIdFieldCodec<B, C> {
@@ -109,3 +120,78 @@ impl<B, C> Codec for RequestIdFieldCodec<B, C>
         self.inner.encode(msg, buf)
     }
 }
+
+#[cfg(feature = "tokio-util-codec")]
+impl<B, C> ::tokio_util::codec::Decoder for RequestIdFieldCodec<B, C>
+    where B: ByteOrder, C: ::tokio_util::codec::Decoder
+{
+    type Item = (RequestId, C::Item);
+    type Error = C::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<(RequestId, C::Item)>, C::Error> {
+        let reqid = if let Some(id) = self.reqid.take() {
+            id
+        } else {
+            if buf.len() < SIZE_OF_REQID {
+                return Ok(None);
+            }
+            B::read_u64(&buf.split_to(SIZE_OF_REQID))
+        };
+
+        match self.inner.decode(buf) {
+            Ok(Some(msg)) => Ok(Some((reqid, msg))),
+            Ok(None) => {
+                self.reqid = Some(reqid);
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl<B, C, T> ::tokio_util::codec::Encoder<(RequestId, T)> for RequestIdFieldCodec<B, C>
+    where B: ByteOrder, C: ::tokio_util::codec::Encoder<T>
+{
+    type Error = C::Error;
+
+    fn encode(&mut self, (reqid, msg): (RequestId, T), buf: &mut BytesMut) -> Result<(), C::Error> {
+        let mut arr = [0u8; SIZE_OF_REQID];
+        B::write_u64(&mut arr, reqid);
+        buf.extend_from_slice(&arr[..]);
+        self.inner.encode(msg, buf)
+    }
+}
+
+#[cfg(all(test, feature = "tokio-util-codec"))]
+mod tests {
+    use super::*;
+    use frame::{DelimiterCodec, LineDelimiter};
+    use byteorder::BigEndian;
+
+    #[test]
+    fn test_request_id_field_tokio_util() {
+        use tokio_util::codec::{Decoder, Encoder};
+
+        let inner = DelimiterCodec::new(LineDelimiter::Lf);
+        let mut p = RequestIdFieldCodec::<BigEndian, _>::new(inner);
+
+        let mut encoded = BytesMut::new();
+        Encoder::encode(&mut p, (1, b"abc".to_vec()), &mut encoded).unwrap();
+        assert_eq!(&encoded[..], b"\0\0\0\0\0\0\0\x01abc\n");
+        Encoder::encode(&mut p, (0x0102, b"de".to_vec()), &mut encoded).unwrap();
+
+        // the request id is kept while the inner frame is incomplete
+        let mut buf = encoded.split_to(10);
+        assert_eq!(Decoder::decode(&mut p, &mut buf).unwrap(), None);
+        assert_eq!(p.reqid, Some(1));
+        buf.extend_from_slice(&encoded);
+
+        let (id, frame) = Decoder::decode(&mut p, &mut buf).unwrap().unwrap();
+        assert_eq!((id, &frame[..]), (1, &b"abc"[..]));
+        let (id, frame) = Decoder::decode(&mut p, &mut buf).unwrap().unwrap();
+        assert_eq!((id, &frame[..]), (0x0102, &b"de"[..]));
+        assert!(Decoder::decode(&mut p, &mut buf).unwrap().is_none());
+        assert!(buf.is_empty());
+    }
+}
@@ -0,0 +1,831 @@
+//! Redis serialization protocol (RESP2 and RESP3).
+//!
+//! [`RespProto`](./struct.RespProto.html) is a pipelined protocol of
+//! [`RespValue`](./enum.RespValue.html)s, which can be used both by Redis-compatible servers and
+//! by clients.
+
+use tokio_core::io::{Codec, Io, EasyBuf, Framed};
+use tokio_proto::pipeline::{ServerProto, ClientProto};
+#[cfg(feature = "tokio-util-codec")]
+use bytes::BytesMut;
+use buf::{FrameBuf, FrameBufMut};
+use error::Error;
+use std::{cmp, io, str};
+
+/// The maximum nesting depth of aggregate values, to bound the recursion of the parser.
+const MAX_DEPTH: usize = 128;
+
+/// A value of the Redis serialization protocol.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RespValue {
+    /// Simple string, `+OK\r\n`.
+    SimpleString(Vec<u8>),
+    /// Simple error, `-ERR message\r\n`.
+    Error(Vec<u8>),
+    /// Integer, `:42\r\n`.
+    Integer(i64),
+    /// Bulk string, `$3\r\nfoo\r\n`, or `None` for the null bulk string `$-1\r\n`.
+    BulkString(Option<Vec<u8>>),
+    /// Array, `*2\r\n` followed by the elements, or `None` for the null array `*-1\r\n`.
+    Array(Option<Vec<RespValue>>),
+    /// Inline command such as `PING\r\n`, split at spaces.
+    Inline(Vec<Vec<u8>>),
+    /// RESP3 null, `_\r\n`.
+    Null,
+    /// RESP3 boolean, `#t\r\n` or `#f\r\n`.
+    Boolean(bool),
+    /// RESP3 double, `,1.5\r\n`.
+    Double(f64),
+    /// RESP3 big number, `(12345678901234567890\r\n`, as its digits.
+    BigNumber(Vec<u8>),
+    /// RESP3 blob error, `!len\r\n` followed by the message.
+    BlobError(Vec<u8>),
+    /// RESP3 verbatim string, `=len\r\n` followed by the data, including its `txt:` style prefix.
+    VerbatimString(Vec<u8>),
+    /// RESP3 map, `%count\r\n` followed by the keys and values.
+    Map(Vec<(RespValue, RespValue)>),
+    /// RESP3 set, `~count\r\n` followed by the elements.
+    Set(Vec<RespValue>),
+    /// RESP3 push, `>count\r\n` followed by the elements.
+    Push(Vec<RespValue>),
+}
+
+impl RespValue {
+    /// Creates a command to be sent to a server, as an array of bulk strings.
+    pub fn command<I>(args: I) -> RespValue
+        where I: IntoIterator,
+              I::Item: Into<Vec<u8>>
+    {
+        let args = args.into_iter().map(|a| RespValue::BulkString(Some(a.into()))).collect();
+        RespValue::Array(Some(args))
+    }
+}
+
+/// Redis serialization protocol.
+///
+/// Requests and responses are both [`RespValue`](./enum.RespValue.html)s. The layout of values can
+/// be customized in the same way as the codec (see [`RespCodec`](./struct.RespCodec.html)).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RespProto {
+    codec: RespCodec,
+}
+
+impl RespProto {
+    pub fn new() -> Self {
+        RespProto { codec: RespCodec::new() }
+    }
+
+    /// Sets the maximum length of an encoded value.
+    pub fn max_frame_length(self, max_frame_length: usize) -> Self {
+        RespProto { codec: self.codec.max_frame_length(max_frame_length) }
+    }
+
+    /// Sets whether inline commands are decoded.
+    pub fn inline(self, inline: bool) -> Self {
+        RespProto { codec: self.codec.inline(inline) }
+    }
+}
+
+impl Default for RespProto {
+    fn default() -> Self {
+        RespProto::new()
+    }
+}
+
+impl<T: Io + 'static> ServerProto<T> for RespProto {
+    type Request = RespValue;
+    type Response = RespValue;
+    type Transport = Framed<T, RespCodec>;
+    type BindTransport = io::Result<Self::Transport>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(io.framed(self.codec))
+    }
+}
+
+impl<T: Io + 'static> ClientProto<T> for RespProto {
+    type Request = RespValue;
+    type Response = RespValue;
+    type Transport = Framed<T, RespCodec>;
+    type BindTransport = io::Result<Self::Transport>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(io.framed(self.codec))
+    }
+}
+
+#[cfg(feature = "async-service")]
+impl ::service::NewCodec for RespProto {
+    type Codec = RespCodec;
+
+    fn new_codec(&self) -> RespCodec {
+        self.codec
+    }
+}
+
+/// Protocol codec used by [`RespProto`](./struct.RespProto.html).
+///
+/// A line which does not begin with a type byte is decoded as an inline command, unless disabled
+/// with [`inline`](#method.inline); empty inline commands are skipped. Malformed values fail the
+/// decode with [`Error::InvalidEncoding`](../error/enum.Error.html), invalid lengths of bulk
+/// strings and aggregates with `Error::InvalidLength`, and values longer than `max_frame_length`
+/// with `Error::FrameTooLarge`.
+///
+/// Encoding a simple string, simple error or inline command containing a line break fails with
+/// `Error::InvalidEncoding`, and writes nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RespCodec {
+    max_frame_length: Option<usize>,
+    inline: bool,
+    scan: Scan,
+}
+
+impl RespCodec {
+    pub fn new() -> Self {
+        RespCodec {
+            max_frame_length: None,
+            inline: true,
+            scan: Scan::new(),
+        }
+    }
+
+    /// Sets the maximum length of an encoded value.
+    pub fn max_frame_length(mut self, max_frame_length: usize) -> Self {
+        self.max_frame_length = Some(max_frame_length);
+        self
+    }
+
+    /// Sets whether inline commands are decoded. Enabled by default.
+    pub fn inline(mut self, inline: bool) -> Self {
+        self.inline = inline;
+        self
+    }
+
+    fn is_too_large(&self, len: usize) -> bool {
+        self.max_frame_length.is_some_and(|max| len > max)
+    }
+
+    fn decode_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<Option<RespValue>> {
+        loop {
+            let (value, end) = {
+                let bytes = buf.as_ref();
+                if bytes.is_empty() {
+                    return Ok(None);
+                }
+
+                // the value is built only once it has been received whole, so that a large value
+                // arriving in many reads is scanned once
+                let inline = self.inline && !is_type(bytes[0]);
+                let end = if inline {
+                    self.scan.line(bytes, 0)
+                } else {
+                    self.scan.value(bytes, self.max_frame_length)?
+                };
+                let end = match end {
+                    Some(end) => end,
+                    None => {
+                        if self.is_too_large(bytes.len()) {
+                            return Err(Error::FrameTooLarge.into());
+                        }
+                        return Ok(None);
+                    }
+                };
+                self.scan = Scan::new();
+
+                if inline {
+                    (parse_inline(&bytes[..end]), end)
+                } else {
+                    let mut parser = Parser {
+                        buf: &bytes[..end],
+                        pos: 0,
+                        max_frame_length: self.max_frame_length,
+                    };
+                    match parser.value(0)? {
+                        Some(value) => (Some(value), end),
+                        None => return Err(Error::InvalidEncoding.into()),
+                    }
+                }
+            };
+
+            if self.is_too_large(end) {
+                return Err(Error::FrameTooLarge.into());
+            }
+            buf.split_to(end);
+            if let Some(value) = value {
+                return Ok(Some(value));
+            }
+        }
+    }
+
+    fn decode_eof_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<Option<RespValue>> {
+        match self.decode_buf(buf)? {
+            Some(value) => Ok(Some(value)),
+            None if buf.is_empty() => Ok(None),
+            None => Err(Error::UnexpectedEof.into()),
+        }
+    }
+}
+
+impl Default for RespCodec {
+    fn default() -> Self {
+        RespCodec::new()
+    }
+}
+
+fn is_type(b: u8) -> bool {
+    matches!(b,
+             b'+' | b'-' | b':' | b'$' | b'*' | b'_' | b'#' | b',' | b'(' | b'!' | b'=' | b'%' |
+             b'~' | b'>')
+}
+
+/// Parses an inline command terminated by LF, returning `None` if it is empty.
+fn parse_inline(line: &[u8]) -> Option<RespValue> {
+    let line = &line[..line.len() - 1];
+    let line = if line.last() == Some(&b'\r') { &line[..line.len() - 1] } else { line };
+    let args: Vec<Vec<u8>> = line.split(|&b| b == b' ' || b == b'\t')
+        .filter(|a| !a.is_empty())
+        .map(|a| a.to_vec())
+        .collect();
+
+    if args.is_empty() { None } else { Some(RespValue::Inline(args)) }
+}
+
+/// Progress in finding the end of a value received over several reads, without building it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Scan {
+    /// Offset of the next item to scan
+    pos: usize,
+    /// Number of items left to scan, including the elements of aggregates
+    remaining: usize,
+    /// Offset up to which the buffer has been searched for the end of the line of the next item
+    searched: usize,
+}
+
+impl Scan {
+    fn new() -> Scan {
+        Scan {
+            pos: 0,
+            remaining: 1,
+            searched: 0,
+        }
+    }
+
+    /// Returns the offset following the LF which ends the line at `pos`.
+    fn line(&mut self, buf: &[u8], pos: usize) -> Option<usize> {
+        let from = cmp::max(self.searched, pos);
+        match ::memchr::memchr(b'\n', &buf[from..]) {
+            Some(i) => Some(from + i + 1),
+            None => {
+                self.searched = buf.len();
+                None
+            }
+        }
+    }
+
+    /// Resumes scanning `buf`, and returns the end of the value once it is complete.
+    ///
+    /// Only the extent of items is checked here; their contents are validated by `Parser`.
+    fn value(&mut self, buf: &[u8], max_frame_length: Option<usize>) -> io::Result<Option<usize>> {
+        while self.remaining > 0 {
+            let pos = self.pos;
+            if pos >= buf.len() {
+                return Ok(None);
+            }
+            let tag = buf[pos];
+            if !is_type(tag) {
+                return Err(Error::InvalidEncoding.into());
+            }
+            let line_end = match self.line(buf, pos + 1) {
+                Some(end) => end,
+                None => return Ok(None),
+            };
+
+            let header = &buf[pos + 1..line_end];
+            let (end, elements) = match tag {
+                b'$' | b'!' | b'=' => {
+                    match scan_length(header)? {
+                        Some(len) => {
+                            if max_frame_length.is_some_and(|max| len > max) {
+                                return Err(Error::FrameTooLarge.into());
+                            }
+                            match line_end.checked_add(len).and_then(|e| e.checked_add(2)) {
+                                Some(end) => (end, 0),
+                                None => return Err(Error::InvalidLength.into()),
+                            }
+                        }
+                        None => (line_end, 0),
+                    }
+                }
+                b'*' | b'~' | b'>' => (line_end, scan_length(header)?.unwrap_or(0)),
+                b'%' => {
+                    let count = scan_length(header)?.unwrap_or(0);
+                    match count.checked_mul(2) {
+                        Some(elements) => (line_end, elements),
+                        None => return Err(Error::InvalidLength.into()),
+                    }
+                }
+                _ => (line_end, 0),
+            };
+            if end > buf.len() {
+                return Ok(None);
+            }
+
+            self.remaining = match (self.remaining - 1).checked_add(elements) {
+                Some(remaining) => remaining,
+                None => return Err(Error::InvalidLength.into()),
+            };
+            self.pos = end;
+            self.searched = 0;
+        }
+        Ok(Some(self.pos))
+    }
+}
+
+/// Reads the length in the header line of a bulk string or an aggregate, including its line
+/// ending, which is `None` if negative.
+fn scan_length(line: &[u8]) -> io::Result<Option<usize>> {
+    if line.len() < 2 || line[line.len() - 2] != b'\r' {
+        return Err(Error::InvalidEncoding.into());
+    }
+    match str::from_utf8(&line[..line.len() - 2]).ok().and_then(|s| s.parse::<i64>().ok()) {
+        Some(len) if len < 0 => Ok(None),
+        Some(len) if len as u64 <= usize::MAX as u64 => Ok(Some(len as usize)),
+        _ => Err(Error::InvalidLength.into()),
+    }
+}
+
+/// Returns `Ok(None)` from the enclosing function if more data is needed.
+macro_rules! try_complete {
+    ($e:expr) => (match $e? {
+        Some(v) => v,
+        None => return Ok(None),
+    })
+}
+
+struct Parser<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    max_frame_length: Option<usize>,
+}
+
+impl<'a> Parser<'a> {
+    fn value(&mut self, depth: usize) -> io::Result<Option<RespValue>> {
+        if self.pos >= self.buf.len() {
+            return Ok(None);
+        }
+        if depth > MAX_DEPTH {
+            return Err(Error::InvalidEncoding.into());
+        }
+
+        let tag = self.buf[self.pos];
+        self.pos += 1;
+
+        let value = match tag {
+            b'+' => RespValue::SimpleString(try_complete!(self.line()).to_vec()),
+            b'-' => RespValue::Error(try_complete!(self.line()).to_vec()),
+            b':' => RespValue::Integer(parse_int(try_complete!(self.line()))?),
+            b'$' => {
+                match try_complete!(self.length()) {
+                    Some(len) => RespValue::BulkString(Some(try_complete!(self.bulk(len)))),
+                    None => RespValue::BulkString(None),
+                }
+            }
+            b'*' => {
+                match try_complete!(self.length()) {
+                    Some(count) => RespValue::Array(Some(try_complete!(self.values(count, depth)))),
+                    None => RespValue::Array(None),
+                }
+            }
+            b'_' => {
+                if !try_complete!(self.line()).is_empty() {
+                    return Err(Error::InvalidEncoding.into());
+                }
+                RespValue::Null
+            }
+            b'#' => {
+                match try_complete!(self.line()) {
+                    b"t" => RespValue::Boolean(true),
+                    b"f" => RespValue::Boolean(false),
+                    _ => return Err(Error::InvalidEncoding.into()),
+                }
+            }
+            b',' => {
+                let line = try_complete!(self.line());
+                match str::from_utf8(line).ok().and_then(|s| s.parse().ok()) {
+                    Some(d) => RespValue::Double(d),
+                    None => return Err(Error::InvalidEncoding.into()),
+                }
+            }
+            b'(' => {
+                let line = try_complete!(self.line());
+                let digits = if line.first() == Some(&b'-') { &line[1..] } else { line };
+                if digits.is_empty() || !digits.iter().all(|&b| b.is_ascii_digit()) {
+                    return Err(Error::InvalidEncoding.into());
+                }
+                RespValue::BigNumber(line.to_vec())
+            }
+            b'!' => RespValue::BlobError(try_complete!(self.bulk_non_null())),
+            b'=' => RespValue::VerbatimString(try_complete!(self.bulk_non_null())),
+            b'%' => {
+                let count = try_complete!(self.count());
+                let mut pairs = Vec::with_capacity(cmp::min(count, self.remaining()));
+                for _ in 0..count {
+                    let k = try_complete!(self.value(depth + 1));
+                    let v = try_complete!(self.value(depth + 1));
+                    pairs.push((k, v));
+                }
+                RespValue::Map(pairs)
+            }
+            b'~' => {
+                let count = try_complete!(self.count());
+                RespValue::Set(try_complete!(self.values(count, depth)))
+            }
+            b'>' => {
+                let count = try_complete!(self.count());
+                RespValue::Push(try_complete!(self.values(count, depth)))
+            }
+            _ => return Err(Error::InvalidEncoding.into()),
+        };
+
+        Ok(Some(value))
+    }
+
+    fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    /// Reads a line terminated by CRLF.
+    fn line(&mut self) -> io::Result<Option<&'a [u8]>> {
+        let end = match ::memchr::memchr(b'\n', &self.buf[self.pos..]) {
+            Some(i) => self.pos + i,
+            None => return Ok(None),
+        };
+        if end == self.pos || self.buf[end - 1] != b'\r' {
+            return Err(Error::InvalidEncoding.into());
+        }
+
+        let line = &self.buf[self.pos..end - 1];
+        self.pos = end + 1;
+        Ok(Some(line))
+    }
+
+    /// Reads the length of a bulk string or an aggregate, which is `None` if `-1`.
+    fn length(&mut self) -> io::Result<Option<Option<usize>>> {
+        let line = try_complete!(self.line());
+        match str::from_utf8(line).ok().and_then(|s| s.parse::<i64>().ok()) {
+            Some(-1) => Ok(Some(None)),
+            Some(len) if len >= 0 && len as u64 <= usize::MAX as u64 => {
+                Ok(Some(Some(len as usize)))
+            }
+            _ => Err(Error::InvalidLength.into()),
+        }
+    }
+
+    /// Reads the length of a value which cannot be null.
+    fn count(&mut self) -> io::Result<Option<usize>> {
+        match try_complete!(self.length()) {
+            Some(count) => Ok(Some(count)),
+            None => Err(Error::InvalidLength.into()),
+        }
+    }
+
+    fn bulk(&mut self, len: usize) -> io::Result<Option<Vec<u8>>> {
+        if self.max_frame_length.is_some_and(|max| len > max) {
+            return Err(Error::FrameTooLarge.into());
+        }
+        if self.remaining() < len || self.remaining() - len < 2 {
+            return Ok(None);
+        }
+
+        let end = self.pos + len;
+        if &self.buf[end..end + 2] != b"\r\n" {
+            return Err(Error::InvalidEncoding.into());
+        }
+
+        let data = self.buf[self.pos..end].to_vec();
+        self.pos = end + 2;
+        Ok(Some(data))
+    }
+
+    fn bulk_non_null(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let len = try_complete!(self.count());
+        self.bulk(len)
+    }
+
+    fn values(&mut self, count: usize, depth: usize) -> io::Result<Option<Vec<RespValue>>> {
+        let mut values = Vec::with_capacity(cmp::min(count, self.remaining()));
+        for _ in 0..count {
+            values.push(try_complete!(self.value(depth + 1)));
+        }
+        Ok(Some(values))
+    }
+}
+
+fn parse_int(line: &[u8]) -> io::Result<i64> {
+    match str::from_utf8(line).ok().and_then(|s| s.parse().ok()) {
+        Some(n) => Ok(n),
+        None => Err(Error::InvalidEncoding.into()),
+    }
+}
+
+/// Fails with `Error::InvalidEncoding` if `value` cannot be encoded, so that nothing is written
+/// for it.
+fn check_value(value: &RespValue) -> io::Result<()> {
+    let is_line = |s: &[u8]| !s.iter().any(|&b| b == b'\r' || b == b'\n');
+    let is_arg = |arg: &Vec<u8>| {
+        !arg.is_empty() && !arg.iter().any(|&b| b == b' ' || b == b'\t' || b == b'\r' || b == b'\n')
+    };
+
+    let valid = match *value {
+        RespValue::SimpleString(ref s) |
+        RespValue::Error(ref s) |
+        RespValue::BigNumber(ref s) => is_line(s),
+        RespValue::Inline(ref args) => !args.is_empty() && args.iter().all(is_arg),
+        RespValue::Array(Some(ref values)) |
+        RespValue::Set(ref values) |
+        RespValue::Push(ref values) => {
+            for v in values {
+                check_value(v)?;
+            }
+            true
+        }
+        RespValue::Map(ref pairs) => {
+            for (k, v) in pairs {
+                check_value(k)?;
+                check_value(v)?;
+            }
+            true
+        }
+        _ => true,
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidEncoding.into())
+    }
+}
+
+/// Writes `value`, which has been checked by `check_value`.
+fn encode_value<W: FrameBufMut>(value: &RespValue, buf: &mut W) {
+    match *value {
+        RespValue::SimpleString(ref s) => encode_line(b'+', s, buf),
+        RespValue::Error(ref s) => encode_line(b'-', s, buf),
+        RespValue::Integer(n) => encode_line(b':', n.to_string().as_bytes(), buf),
+        RespValue::BulkString(None) => buf.put_slice(b"$-1\r\n"),
+        RespValue::BulkString(Some(ref s)) => encode_bulk(b'$', s, buf),
+        RespValue::Array(None) => buf.put_slice(b"*-1\r\n"),
+        RespValue::Array(Some(ref values)) => encode_values(b'*', values, buf),
+        RespValue::Inline(ref args) => {
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    buf.put_slice(b" ");
+                }
+                buf.put_slice(arg);
+            }
+            buf.put_slice(b"\r\n");
+        }
+        RespValue::Null => buf.put_slice(b"_\r\n"),
+        RespValue::Boolean(true) => buf.put_slice(b"#t\r\n"),
+        RespValue::Boolean(false) => buf.put_slice(b"#f\r\n"),
+        RespValue::Double(d) => {
+            let s = if d.is_nan() {
+                "nan".to_owned()
+            } else if d.is_infinite() {
+                if d > 0.0 { "inf" } else { "-inf" }.to_owned()
+            } else {
+                d.to_string()
+            };
+            encode_line(b',', s.as_bytes(), buf);
+        }
+        RespValue::BigNumber(ref s) => encode_line(b'(', s, buf),
+        RespValue::BlobError(ref s) => encode_bulk(b'!', s, buf),
+        RespValue::VerbatimString(ref s) => encode_bulk(b'=', s, buf),
+        RespValue::Map(ref pairs) => {
+            encode_header(b'%', pairs.len(), buf);
+            for (k, v) in pairs {
+                encode_value(k, buf);
+                encode_value(v, buf);
+            }
+        }
+        RespValue::Set(ref values) => encode_values(b'~', values, buf),
+        RespValue::Push(ref values) => encode_values(b'>', values, buf),
+    }
+}
+
+fn encode_header<W: FrameBufMut>(tag: u8, len: usize, buf: &mut W) {
+    buf.put_slice(&[tag]);
+    buf.put_slice(len.to_string().as_bytes());
+    buf.put_slice(b"\r\n");
+}
+
+fn encode_line<W: FrameBufMut>(tag: u8, line: &[u8], buf: &mut W) {
+    buf.reserve(line.len() + 3);
+    buf.put_slice(&[tag]);
+    buf.put_slice(line);
+    buf.put_slice(b"\r\n");
+}
+
+fn encode_bulk<W: FrameBufMut>(tag: u8, data: &[u8], buf: &mut W) {
+    encode_header(tag, data.len(), buf);
+    buf.reserve(data.len() + 2);
+    buf.put_slice(data);
+    buf.put_slice(b"\r\n");
+}
+
+fn encode_values<W: FrameBufMut>(tag: u8, values: &[RespValue], buf: &mut W) {
+    encode_header(tag, values.len(), buf);
+    for v in values {
+        encode_value(v, buf);
+    }
+}
+
+impl Codec for RespCodec {
+    type In = RespValue;
+    type Out = RespValue;
+
+    fn decode(&mut self, buf: &mut EasyBuf) -> io::Result<Option<RespValue>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut EasyBuf) -> io::Result<RespValue> {
+        match self.decode_eof_buf(buf)? {
+            Some(value) => Ok(value),
+            None => Err(Error::UnexpectedEof.into()),
+        }
+    }
+
+    fn encode(&mut self, item: RespValue, buf: &mut Vec<u8>) -> io::Result<()> {
+        check_value(&item)?;
+        encode_value(&item, buf);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl ::tokio_util::codec::Decoder for RespCodec {
+    type Item = RespValue;
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<RespValue>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> io::Result<Option<RespValue>> {
+        self.decode_eof_buf(buf)
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl ::tokio_util::codec::Encoder<RespValue> for RespCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: RespValue, buf: &mut BytesMut) -> io::Result<()> {
+        check_value(&item)?;
+        encode_value(&item, buf);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(p: &mut RespCodec, input: &[u8]) -> Vec<RespValue> {
+        let mut buf = EasyBuf::new();
+        let mut values = vec![];
+        for &b in input {
+            buf.get_mut().push(b);
+            while let Some(v) = p.decode(&mut buf).unwrap() {
+                values.push(v);
+            }
+        }
+        assert_eq!(buf.len(), 0);
+        values
+    }
+
+    fn decode_err(p: &mut RespCodec, input: &[u8]) -> Error {
+        let err = p.decode(&mut EasyBuf::from(input.to_vec())).unwrap_err();
+        *err.get_ref().and_then(|e| e.downcast_ref::<Error>()).unwrap()
+    }
+
+    fn sample() -> Vec<RespValue> {
+        vec![RespValue::SimpleString(b"OK".to_vec()),
+             RespValue::Error(b"ERR unknown command".to_vec()),
+             RespValue::Integer(-42),
+             RespValue::BulkString(Some(b"foo\r\nbar".to_vec())),
+             RespValue::BulkString(Some(vec![])),
+             RespValue::BulkString(None),
+             RespValue::Array(None),
+             RespValue::Array(Some(vec![RespValue::Integer(1),
+                                        RespValue::Array(Some(vec![])),
+                                        RespValue::BulkString(None)])),
+             RespValue::Null,
+             RespValue::Boolean(true),
+             RespValue::Double(1.5),
+             RespValue::Double(f64::NEG_INFINITY),
+             RespValue::BigNumber(b"-3492890328409238509324850943850943825024385".to_vec()),
+             RespValue::BlobError(b"SYNTAX invalid syntax".to_vec()),
+             RespValue::VerbatimString(b"txt:Some string".to_vec()),
+             RespValue::Map(vec![(RespValue::SimpleString(b"first".to_vec()),
+                                  RespValue::Integer(1))]),
+             RespValue::Set(vec![RespValue::Boolean(false)]),
+             RespValue::Push(vec![RespValue::BulkString(Some(b"message".to_vec()))])]
+    }
+
+    #[test]
+    fn test_resp() {
+        let mut p = RespCodec::new();
+        let values = decode_all(&mut p, b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n$-1\r\n:10\r\n");
+        assert_eq!(values,
+                   vec![RespValue::command(vec!["GET", "k"]),
+                        RespValue::BulkString(None),
+                        RespValue::Integer(10)]);
+    }
+
+    #[test]
+    fn test_resp_roundtrip() {
+        let mut p = RespCodec::new();
+        let mut v = vec![];
+        for value in sample() {
+            p.encode(value, &mut v).unwrap();
+        }
+        assert_eq!(decode_all(&mut p, &v), sample());
+    }
+
+    #[test]
+    fn test_resp_partial() {
+        let mut p = RespCodec::new();
+        let mut v = vec![];
+        let values = (0..1000).map(RespValue::Integer).collect::<Vec<_>>();
+        p.encode(RespValue::Array(Some(values.clone())), &mut v).unwrap();
+
+        // elements already received are not scanned again
+        let mut buf = EasyBuf::new();
+        buf.get_mut().extend_from_slice(&v[..v.len() - 3]);
+        assert_eq!(p.decode(&mut buf).unwrap(), None);
+        assert_eq!(p.scan.remaining, 1);
+        assert_eq!(&v[p.scan.pos..], b":999\r\n");
+
+        buf.get_mut().extend_from_slice(&v[v.len() - 3..]);
+        assert_eq!(p.decode(&mut buf).unwrap(), Some(RespValue::Array(Some(values))));
+        assert_eq!(p.scan, Scan::new());
+        assert_eq!(buf.len(), 0);
+    }
+
+    #[test]
+    fn test_resp_inline() {
+        let mut p = RespCodec::new();
+        let values = decode_all(&mut p, b"PING\r\n\r\nSET  k\tv\n");
+        assert_eq!(values,
+                   vec![RespValue::Inline(vec![b"PING".to_vec()]),
+                        RespValue::Inline(vec![b"SET".to_vec(), b"k".to_vec(), b"v".to_vec()])]);
+
+        let mut v = vec![];
+        p.encode(RespValue::Inline(vec![b"PING".to_vec()]), &mut v).unwrap();
+        assert_eq!(v, b"PING\r\n");
+
+        let mut p = RespCodec::new().inline(false);
+        assert_eq!(decode_err(&mut p, b"PING\r\n"), Error::InvalidEncoding);
+    }
+
+    #[test]
+    fn test_resp_invalid() {
+        let mut p = RespCodec::new();
+        assert_eq!(decode_err(&mut p, b"+OK\n"), Error::InvalidEncoding);
+        assert_eq!(decode_err(&mut p, b":abc\r\n"), Error::InvalidEncoding);
+        assert_eq!(decode_err(&mut p, b"$-2\r\n"), Error::InvalidLength);
+        assert_eq!(decode_err(&mut p, b"%-1\r\n"), Error::InvalidLength);
+        assert_eq!(decode_err(&mut p, b"$3\r\nfoobar\r\n"), Error::InvalidEncoding);
+        assert_eq!(decode_err(&mut p, b"#x\r\n"), Error::InvalidEncoding);
+
+        let mut nested = vec![];
+        for _ in 0..MAX_DEPTH + 2 {
+            nested.extend_from_slice(b"*1\r\n");
+        }
+        nested.extend_from_slice(b":1\r\n");
+        assert_eq!(decode_err(&mut p, &nested), Error::InvalidEncoding);
+
+        let mut p = RespCodec::new().max_frame_length(8);
+        assert_eq!(decode_err(&mut p, b"$9\r\n"), Error::FrameTooLarge);
+        assert_eq!(decode_err(&mut p, b"+123456789"), Error::FrameTooLarge);
+
+        let err = p.encode(RespValue::SimpleString(b"a\r\n".to_vec()), &mut vec![]).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::InvalidEncoding));
+
+        // nothing is written for a value failing to encode
+        let mut v = b"+OK\r\n".to_vec();
+        let value = RespValue::Array(Some(vec![RespValue::Integer(1),
+                                               RespValue::Inline(vec![b"a b".to_vec()])]));
+        let err = p.encode(value, &mut v).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::InvalidEncoding));
+        assert_eq!(v, b"+OK\r\n");
+
+        let mut p = RespCodec::new();
+        let err = p.decode_eof(&mut EasyBuf::from(b"*2\r\n:1\r\n".to_vec())).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+                   Some(&Error::UnexpectedEof));
+    }
+}
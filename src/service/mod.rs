@@ -0,0 +1,72 @@
+//! Clients and servers driving the codecs with tokio and `tower::Service`.
+//!
+//! This module is a replacement of tokio-proto for the `tokio-util` based codecs, and is available
+//! with the `async-service` feature. The futures returned by the functions here are plain
+//! `std::future::Future`s which can be `.await`ed or spawned onto a tokio runtime.
+//!
+//! [`pipeline`](./pipeline/index.html) processes requests one after another on a connection, and
+//! [`multiplex`](./multiplex/index.html) correlates requests and responses by `RequestId`, as
+//! framed by [`RequestIdFieldProto`](../request_id_field/struct.RequestIdFieldProto.html).
+
+pub mod pipeline;
+pub mod multiplex;
+
+use tokio::sync::oneshot;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::io;
+
+/// A protocol that creates a codec for each connection.
+///
+/// This is the counterpart of tokio-proto's `bind_transport`: the protocols in this crate hold a
+/// codec template, and `new_codec` returns a fresh copy of it.
+pub trait NewCodec {
+    /// The codec to be used for a connection.
+    type Codec;
+
+    /// Creates a codec for a new connection.
+    fn new_codec(&self) -> Self::Codec;
+}
+
+impl<P: NewCodec + ?Sized> NewCodec for &P {
+    type Codec = P::Codec;
+
+    fn new_codec(&self) -> P::Codec {
+        (**self).new_codec()
+    }
+}
+
+/// Default maximum number of requests processed at once on a server connection, as in
+/// tokio-proto.
+const DEFAULT_MAX_IN_FLIGHT: usize = 32;
+
+fn connection_closed() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "connection closed")
+}
+
+/// `io::Error` is not `Clone`; copies the kind and message of `err` to report it to every pending
+/// request.
+fn copy_error(err: &io::Error) -> io::Error {
+    io::Error::new(err.kind(), err.to_string())
+}
+
+/// Future of a response returned by the clients in this module.
+///
+/// Fails with `ErrorKind::BrokenPipe` if the connection is closed before the response arrives.
+#[derive(Debug)]
+pub struct ResponseFuture<Resp> {
+    rx: oneshot::Receiver<io::Result<Resp>>,
+}
+
+impl<Resp> Future for ResponseFuture<Resp> {
+    type Output = io::Result<Resp>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<Resp>> {
+        match Pin::new(&mut self.rx).poll(cx) {
+            Poll::Ready(Ok(res)) => Poll::Ready(res),
+            Poll::Ready(Err(_)) => Poll::Ready(Err(connection_closed())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,470 @@
+//! Multiplexed servers and clients.
+//!
+//! Every frame carries a `RequestId`, which lets responses be sent as soon as they are ready and
+//! matched to their requests by the client. The codec decodes and encodes `(RequestId, T)` pairs,
+//! such as the one of [`RequestIdFieldProto`](../../request_id_field/struct.RequestIdFieldProto.html).
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::codec::{Decoder, Encoder, Framed};
+use tokio_proto::multiplex::RequestId;
+use futures_util::sink::Sink;
+use futures_util::stream::{FuturesUnordered, Stream};
+use tower_service::Service;
+use super::{DEFAULT_MAX_IN_FLIGHT, NewCodec, ResponseFuture, connection_closed, copy_error};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::io;
+
+/// Serves `service` on every connection accepted by `listener`, framing it with `proto`.
+///
+/// Each connection is handled by a task spawned onto the current tokio runtime, with its own clone
+/// of `service`, as by [`serve_connection`](./fn.serve_connection.html). The returned future
+/// completes only when accepting a connection fails.
+pub fn serve<P, S>(listener: TcpListener, proto: P, service: S) -> Serve<P, S> {
+    Serve {
+        listener,
+        proto,
+        service,
+        max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+    }
+}
+
+/// Future returned by [`serve`](./fn.serve.html).
+#[derive(Debug)]
+pub struct Serve<P, S> {
+    listener: TcpListener,
+    proto: P,
+    service: S,
+    max_in_flight: usize,
+}
+
+impl<P, S> Serve<P, S> {
+    /// Sets the maximum number of requests processed at once on each connection, 32 by default.
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        assert!(max_in_flight > 0, "max_in_flight is zero");
+        self.max_in_flight = max_in_flight;
+        self
+    }
+}
+
+impl<P, S> Unpin for Serve<P, S> {}
+
+impl<P, S, Req> Future for Serve<P, S>
+    where P: NewCodec,
+          P::Codec: Decoder<Item = (RequestId, Req), Error = io::Error> +
+                    Encoder<(RequestId, S::Response), Error = io::Error> + Send + 'static,
+          S: Service<Req, Error = io::Error> + Clone + Send + 'static,
+          S::Response: Send + 'static,
+          S::Future: Send + 'static,
+          Req: 'static
+{
+    type Output = io::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        loop {
+            let (io, _) = match self.listener.poll_accept(cx) {
+                Poll::Ready(Ok(conn)) => conn,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+            let conn: Connection<TcpStream, P::Codec, S, Req> =
+                serve_connection(io, &self.proto, self.service.clone())
+                    .max_in_flight(self.max_in_flight);
+            ::tokio::spawn(conn);
+        }
+    }
+}
+
+/// Serves `service` on a single connection `io`, framing it with `proto`.
+///
+/// The returned future completes when the peer has closed the connection and every response has
+/// been written, or fails with the first error of the codec or the service.
+///
+/// The codec has no way to send an error in place of a response, so a request failing in the
+/// service fails the whole connection, and the responses to the other requests in flight are
+/// dropped. A service that should keep the connection open reports errors in its responses.
+///
+/// At most 32 requests are processed at once by default; see
+/// [`max_in_flight`](./struct.Connection.html#method.max_in_flight).
+pub fn serve_connection<T, P, S, Req>(io: T, proto: P, service: S) -> Connection<T, P::Codec, S, Req>
+    where P: NewCodec,
+          S: Service<Req>
+{
+    Connection {
+        framed: Framed::new(io, proto.new_codec()),
+        service,
+        in_flight: FuturesUnordered::new(),
+        read_closed: false,
+        max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+    }
+}
+
+/// Future returned by [`serve_connection`](./fn.serve_connection.html).
+pub struct Connection<T, C, S: Service<Req>, Req> {
+    framed: Framed<T, C>,
+    service: S,
+    in_flight: FuturesUnordered<Tagged<S::Future>>,
+    read_closed: bool,
+    max_in_flight: usize,
+}
+
+impl<T, C, S: Service<Req>, Req> Connection<T, C, S, Req> {
+    /// Sets the maximum number of requests processed at once, 32 by default.
+    ///
+    /// Once it is reached, no more requests are read until a response is ready.
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        assert!(max_in_flight > 0, "max_in_flight is zero");
+        self.max_in_flight = max_in_flight;
+        self
+    }
+}
+
+impl<T, C, S: Service<Req>, Req> Unpin for Connection<T, C, S, Req> {}
+
+impl<T, C, S, Req> Future for Connection<T, C, S, Req>
+    where T: AsyncRead + AsyncWrite + Unpin,
+          C: Decoder<Item = (RequestId, Req), Error = io::Error> +
+             Encoder<(RequestId, S::Response), Error = io::Error>,
+          S: Service<Req, Error = io::Error>
+{
+    type Output = io::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            let mut progress = false;
+
+            if !this.read_closed && this.in_flight.len() < this.max_in_flight {
+                if let Poll::Ready(()) = this.service.poll_ready(cx)? {
+                    match Pin::new(&mut this.framed).poll_next(cx) {
+                        Poll::Ready(Some(req)) => {
+                            let (id, req) = req?;
+                            this.in_flight.push(Tagged {
+                                id,
+                                fut: Box::pin(this.service.call(req)),
+                            });
+                            progress = true;
+                        }
+                        Poll::Ready(None) => {
+                            this.read_closed = true;
+                            progress = true;
+                        }
+                        Poll::Pending => {}
+                    }
+                }
+            }
+
+            while let Poll::Ready(()) = Pin::new(&mut this.framed).poll_ready(cx)? {
+                match Pin::new(&mut this.in_flight).poll_next(cx) {
+                    Poll::Ready(Some((id, resp))) => {
+                        Pin::new(&mut this.framed).start_send((id, resp?))?;
+                        progress = true;
+                    }
+                    _ => break,
+                }
+            }
+
+            let flushed = Pin::new(&mut this.framed).poll_flush(cx)?.is_ready();
+            if this.read_closed && this.in_flight.is_empty() && flushed {
+                return Poll::Ready(Ok(()));
+            }
+
+            if !progress {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+/// A response future labeled with the id of its request.
+struct Tagged<F> {
+    id: RequestId,
+    fut: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for Tagged<F> {
+    type Output = (RequestId, F::Output);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<(RequestId, F::Output)> {
+        let id = self.id;
+        self.fut.as_mut().poll(cx).map(|res| (id, res))
+    }
+}
+
+type Pending<Resp> = oneshot::Sender<io::Result<Resp>>;
+
+/// A multiplexed client.
+///
+/// Each request is assigned a `RequestId` not used by any other request in flight, and responses
+/// are delivered to the request with the same id in whatever order they arrive. The client can be
+/// cloned to share the connection.
+#[derive(Debug)]
+pub struct Client<Req, Resp> {
+    tx: mpsc::UnboundedSender<(Req, Pending<Resp>)>,
+}
+
+impl<Req, Resp> Clone for Client<Req, Resp> {
+    fn clone(&self) -> Self {
+        Client { tx: self.tx.clone() }
+    }
+}
+
+impl<Req, Resp> Client<Req, Resp>
+    where Req: Send + 'static,
+          Resp: Send + 'static
+{
+    /// Creates a client over `io` framed with `proto`.
+    ///
+    /// The connection is driven by a task spawned onto the current tokio runtime, which exits once
+    /// every clone of the client is dropped and all responses have been received. Must be called
+    /// within a tokio runtime.
+    pub fn new<T, P>(io: T, proto: P) -> Self
+        where T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+              P: NewCodec,
+              P::Codec: Decoder<Item = (RequestId, Resp), Error = io::Error> +
+                        Encoder<(RequestId, Req), Error = io::Error> + Send + 'static
+    {
+        let (tx, rx) = mpsc::unbounded_channel();
+        ::tokio::spawn(Dispatch {
+            framed: Framed::new(io, proto.new_codec()),
+            rx,
+            rx_closed: false,
+            next_id: 0,
+            pending: HashMap::new(),
+        });
+        Client { tx }
+    }
+}
+
+impl<Req, Resp> Service<Req> for Client<Req, Resp> {
+    type Response = Resp;
+    type Error = io::Error;
+    type Future = ResponseFuture<Resp>;
+
+    fn poll_ready(&mut self, _cx: &mut Context) -> Poll<io::Result<()>> {
+        if self.tx.is_closed() {
+            Poll::Ready(Err(connection_closed()))
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn call(&mut self, req: Req) -> ResponseFuture<Resp> {
+        let (tx, rx) = oneshot::channel();
+        // If the connection is gone, `tx` is dropped and the future fails.
+        let _ = self.tx.send((req, tx));
+        ResponseFuture { rx }
+    }
+}
+
+struct Dispatch<T, C, Req, Resp> {
+    framed: Framed<T, C>,
+    rx: mpsc::UnboundedReceiver<(Req, Pending<Resp>)>,
+    rx_closed: bool,
+    next_id: RequestId,
+    pending: HashMap<RequestId, Pending<Resp>>,
+}
+
+impl<T, C, Req, Resp> Unpin for Dispatch<T, C, Req, Resp> {}
+
+impl<T, C, Req, Resp> Dispatch<T, C, Req, Resp>
+    where T: AsyncRead + AsyncWrite + Unpin,
+          C: Decoder<Item = (RequestId, Resp), Error = io::Error> +
+             Encoder<(RequestId, Req), Error = io::Error>
+{
+    fn next_id(&mut self) -> RequestId {
+        while self.pending.contains_key(&self.next_id) {
+            self.next_id = self.next_id.wrapping_add(1);
+        }
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        id
+    }
+
+    fn poll_dispatch(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+        loop {
+            let mut progress = false;
+
+            while !self.rx_closed {
+                if Pin::new(&mut self.framed).poll_ready(cx)?.is_pending() {
+                    break;
+                }
+                match self.rx.poll_recv(cx) {
+                    Poll::Ready(Some((req, tx))) => {
+                        let id = self.next_id();
+                        Pin::new(&mut self.framed).start_send((id, req))?;
+                        self.pending.insert(id, tx);
+                        progress = true;
+                    }
+                    Poll::Ready(None) => self.rx_closed = true,
+                    Poll::Pending => break,
+                }
+            }
+
+            let flushed = Pin::new(&mut self.framed).poll_flush(cx)?.is_ready();
+
+            if !self.pending.is_empty() {
+                match Pin::new(&mut self.framed).poll_next(cx) {
+                    Poll::Ready(Some(resp)) => {
+                        let (id, resp) = resp?;
+                        match self.pending.remove(&id) {
+                            Some(tx) => {
+                                // The request may have been cancelled by dropping its future.
+                                let _ = tx.send(Ok(resp));
+                            }
+                            None => {
+                                return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidData,
+                                                                      "response to unknown request id")));
+                            }
+                        }
+                        progress = true;
+                    }
+                    Poll::Ready(None) => return Poll::Ready(Err(connection_closed())),
+                    Poll::Pending => {}
+                }
+            }
+
+            if self.rx_closed && self.pending.is_empty() && flushed {
+                return Poll::Ready(Ok(()));
+            }
+
+            if !progress {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+impl<T, C, Req, Resp> Future for Dispatch<T, C, Req, Resp>
+    where T: AsyncRead + AsyncWrite + Unpin,
+          C: Decoder<Item = (RequestId, Resp), Error = io::Error> +
+             Encoder<(RequestId, Req), Error = io::Error>
+{
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let this = self.get_mut();
+        match this.poll_dispatch(cx) {
+            Poll::Ready(Ok(())) => Poll::Ready(()),
+            Poll::Ready(Err(e)) => {
+                for (_, tx) in this.pending.drain() {
+                    let _ = tx.send(Err(copy_error(&e)));
+                }
+                Poll::Ready(())
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[test]
+fn test_multiplex() {
+    use frame::DelimiterCodec;
+    use request_id_field::RequestIdFieldProto;
+    use futures_util::future::{self, FutureExt};
+    use bytes::BytesMut;
+    use byteorder::BigEndian;
+
+    /// Holds the response to `first` until `second` has been received.
+    struct Gate {
+        tx: Option<oneshot::Sender<()>>,
+        rx: Option<oneshot::Receiver<()>>,
+    }
+
+    impl Service<BytesMut> for Gate {
+        type Response = Vec<u8>;
+        type Error = io::Error;
+        type Future = Pin<Box<dyn Future<Output = io::Result<Vec<u8>>> + Send>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: BytesMut) -> Self::Future {
+            if &req[..] == b"first" {
+                Box::pin(self.rx.take().unwrap().map(|_| Ok(b"FIRST".to_vec())))
+            } else {
+                let _ = self.tx.take().unwrap().send(());
+                Box::pin(future::ready(Ok(req.to_ascii_uppercase())))
+            }
+        }
+    }
+
+    let rt = ::tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let _guard = rt.enter();
+
+    let (server, client) = ::tokio::io::duplex(16);
+    let proto = RequestIdFieldProto::<BigEndian, _>::new(DelimiterCodec::new(b'\n'));
+    let (tx, rx) = oneshot::channel();
+    let gate = Gate {
+        tx: Some(tx),
+        rx: Some(rx),
+    };
+    let conn = ::tokio::spawn(serve_connection(server, &proto, gate));
+
+    let mut client = Client::new(client, &proto);
+    let first = client.call(b"first".to_vec());
+    let second = client.call(b"second".to_vec());
+    assert_eq!(&rt.block_on(second).unwrap()[..], b"SECOND");
+    assert_eq!(&rt.block_on(first).unwrap()[..], b"FIRST");
+
+    drop(client);
+    rt.block_on(conn).unwrap().unwrap();
+}
+
+#[test]
+fn test_multiplex_max_in_flight() {
+    use frame::DelimiterCodec;
+    use request_id_field::RequestIdFieldProto;
+    use futures_util::future;
+    use bytes::BytesMut;
+    use byteorder::BigEndian;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Counts the requests and never responds.
+    #[derive(Clone)]
+    struct Stall(Arc<AtomicUsize>);
+
+    impl Service<BytesMut> for Stall {
+        type Response = Vec<u8>;
+        type Error = io::Error;
+        type Future = future::Pending<io::Result<Vec<u8>>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _req: BytesMut) -> Self::Future {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            future::pending()
+        }
+    }
+
+    let rt = ::tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let _guard = rt.enter();
+
+    let (server, client) = ::tokio::io::duplex(1024);
+    let proto = RequestIdFieldProto::<BigEndian, _>::new(DelimiterCodec::new(b'\n'));
+    let calls = Arc::new(AtomicUsize::new(0));
+    ::tokio::spawn(serve_connection(server, &proto, Stall(calls.clone())).max_in_flight(2));
+
+    let mut client = Client::new(client, &proto);
+    let _responses: Vec<_> = (0..5).map(|_| client.call(b"x".to_vec())).collect();
+    // let the connection tasks run until they are idle
+    let mut polls = 0;
+    rt.block_on(future::poll_fn(|cx| {
+        polls += 1;
+        if polls == 100 {
+            return Poll::Ready(());
+        }
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }));
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,357 @@
+//! Pipelined servers and clients.
+//!
+//! Responses are sent in the order the corresponding requests were received, so any protocol in
+//! [`frame`](../../frame/index.html) can be used.
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::codec::{Decoder, Encoder, Framed};
+use futures_util::sink::Sink;
+use futures_util::stream::{FuturesOrdered, Stream};
+use tower_service::Service;
+use super::{DEFAULT_MAX_IN_FLIGHT, NewCodec, ResponseFuture, connection_closed, copy_error};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::io;
+
+/// Serves `service` on every connection accepted by `listener`, framing it with `proto`.
+///
+/// Each connection is handled by a task spawned onto the current tokio runtime, with its own clone
+/// of `service`, as by [`serve_connection`](./fn.serve_connection.html). The returned future
+/// completes only when accepting a connection fails.
+pub fn serve<P, S>(listener: TcpListener, proto: P, service: S) -> Serve<P, S> {
+    Serve {
+        listener,
+        proto,
+        service,
+        max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+    }
+}
+
+/// Future returned by [`serve`](./fn.serve.html).
+#[derive(Debug)]
+pub struct Serve<P, S> {
+    listener: TcpListener,
+    proto: P,
+    service: S,
+    max_in_flight: usize,
+}
+
+impl<P, S> Serve<P, S> {
+    /// Sets the maximum number of requests processed at once on each connection, 32 by default.
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        assert!(max_in_flight > 0, "max_in_flight is zero");
+        self.max_in_flight = max_in_flight;
+        self
+    }
+}
+
+impl<P, S> Unpin for Serve<P, S> {}
+
+impl<P, S> Future for Serve<P, S>
+    where P: NewCodec,
+          P::Codec: Decoder<Error = io::Error> + Encoder<S::Response, Error = io::Error> + Send + 'static,
+          S: Service<<P::Codec as Decoder>::Item, Error = io::Error> + Clone + Send + 'static,
+          S::Response: Send + 'static,
+          S::Future: Send + 'static
+{
+    type Output = io::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        loop {
+            let (io, _) = match self.listener.poll_accept(cx) {
+                Poll::Ready(Ok(conn)) => conn,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+            let conn: Connection<TcpStream, P::Codec, S> =
+                serve_connection(io, &self.proto, self.service.clone())
+                    .max_in_flight(self.max_in_flight);
+            ::tokio::spawn(conn);
+        }
+    }
+}
+
+/// Serves `service` on a single connection `io`, framing it with `proto`.
+///
+/// The returned future completes when the peer has closed the connection and every response has
+/// been written, or fails with the first error of the codec or the service.
+///
+/// The codec has no way to send an error in place of a response, so a request failing in the
+/// service fails the whole connection, and the responses to the other requests in flight are
+/// dropped. A service that should keep the connection open reports errors in its responses.
+///
+/// At most 32 requests are processed at once by default; see
+/// [`max_in_flight`](./struct.Connection.html#method.max_in_flight).
+pub fn serve_connection<T, P, S>(io: T, proto: P, service: S) -> Connection<T, P::Codec, S>
+    where P: NewCodec,
+          P::Codec: Decoder,
+          S: Service<<P::Codec as Decoder>::Item>
+{
+    Connection {
+        framed: Framed::new(io, proto.new_codec()),
+        service,
+        in_flight: FuturesOrdered::new(),
+        read_closed: false,
+        max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+    }
+}
+
+/// Future returned by [`serve_connection`](./fn.serve_connection.html).
+pub struct Connection<T, C: Decoder, S: Service<C::Item>> {
+    framed: Framed<T, C>,
+    service: S,
+    in_flight: FuturesOrdered<S::Future>,
+    read_closed: bool,
+    max_in_flight: usize,
+}
+
+impl<T, C: Decoder, S: Service<C::Item>> Connection<T, C, S> {
+    /// Sets the maximum number of requests processed at once, 32 by default.
+    ///
+    /// Once it is reached, no more requests are read until a response is ready.
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        assert!(max_in_flight > 0, "max_in_flight is zero");
+        self.max_in_flight = max_in_flight;
+        self
+    }
+}
+
+impl<T, C: Decoder, S: Service<C::Item>> Unpin for Connection<T, C, S> {}
+
+impl<T, C, S> Future for Connection<T, C, S>
+    where T: AsyncRead + AsyncWrite + Unpin,
+          C: Decoder<Error = io::Error> + Encoder<S::Response, Error = io::Error>,
+          S: Service<C::Item, Error = io::Error>
+{
+    type Output = io::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            let mut progress = false;
+
+            if !this.read_closed && this.in_flight.len() < this.max_in_flight {
+                if let Poll::Ready(()) = this.service.poll_ready(cx)? {
+                    match Pin::new(&mut this.framed).poll_next(cx) {
+                        Poll::Ready(Some(req)) => {
+                            let fut = this.service.call(req?);
+                            this.in_flight.push_back(fut);
+                            progress = true;
+                        }
+                        Poll::Ready(None) => {
+                            this.read_closed = true;
+                            progress = true;
+                        }
+                        Poll::Pending => {}
+                    }
+                }
+            }
+
+            while let Poll::Ready(()) = Pin::new(&mut this.framed).poll_ready(cx)? {
+                match Pin::new(&mut this.in_flight).poll_next(cx) {
+                    Poll::Ready(Some(resp)) => {
+                        Pin::new(&mut this.framed).start_send(resp?)?;
+                        progress = true;
+                    }
+                    _ => break,
+                }
+            }
+
+            let flushed = Pin::new(&mut this.framed).poll_flush(cx)?.is_ready();
+            if this.read_closed && this.in_flight.is_empty() && flushed {
+                return Poll::Ready(Ok(()));
+            }
+
+            if !progress {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+type Pending<Resp> = oneshot::Sender<io::Result<Resp>>;
+
+/// A pipelined client.
+///
+/// Requests are written in the order `call` is invoked, and each response is delivered to the
+/// earliest request still waiting for one. The client can be cloned to share the connection.
+#[derive(Debug)]
+pub struct Client<Req, Resp> {
+    tx: mpsc::UnboundedSender<(Req, Pending<Resp>)>,
+}
+
+impl<Req, Resp> Clone for Client<Req, Resp> {
+    fn clone(&self) -> Self {
+        Client { tx: self.tx.clone() }
+    }
+}
+
+impl<Req, Resp> Client<Req, Resp>
+    where Req: Send + 'static,
+          Resp: Send + 'static
+{
+    /// Creates a client over `io` framed with `proto`.
+    ///
+    /// The connection is driven by a task spawned onto the current tokio runtime, which exits once
+    /// every clone of the client is dropped and all responses have been received. Must be called
+    /// within a tokio runtime.
+    pub fn new<T, P>(io: T, proto: P) -> Self
+        where T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+              P: NewCodec,
+              P::Codec: Decoder<Item = Resp, Error = io::Error> +
+                        Encoder<Req, Error = io::Error> + Send + 'static
+    {
+        let (tx, rx) = mpsc::unbounded_channel();
+        ::tokio::spawn(Dispatch {
+            framed: Framed::new(io, proto.new_codec()),
+            rx,
+            rx_closed: false,
+            pending: VecDeque::new(),
+        });
+        Client { tx }
+    }
+}
+
+impl<Req, Resp> Service<Req> for Client<Req, Resp> {
+    type Response = Resp;
+    type Error = io::Error;
+    type Future = ResponseFuture<Resp>;
+
+    fn poll_ready(&mut self, _cx: &mut Context) -> Poll<io::Result<()>> {
+        if self.tx.is_closed() {
+            Poll::Ready(Err(connection_closed()))
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn call(&mut self, req: Req) -> ResponseFuture<Resp> {
+        let (tx, rx) = oneshot::channel();
+        // If the connection is gone, `tx` is dropped and the future fails.
+        let _ = self.tx.send((req, tx));
+        ResponseFuture { rx }
+    }
+}
+
+struct Dispatch<T, C, Req, Resp> {
+    framed: Framed<T, C>,
+    rx: mpsc::UnboundedReceiver<(Req, Pending<Resp>)>,
+    rx_closed: bool,
+    pending: VecDeque<Pending<Resp>>,
+}
+
+impl<T, C, Req, Resp> Unpin for Dispatch<T, C, Req, Resp> {}
+
+impl<T, C, Req, Resp> Dispatch<T, C, Req, Resp>
+    where T: AsyncRead + AsyncWrite + Unpin,
+          C: Decoder<Item = Resp, Error = io::Error> + Encoder<Req, Error = io::Error>
+{
+    fn poll_dispatch(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+        loop {
+            let mut progress = false;
+
+            while !self.rx_closed {
+                if Pin::new(&mut self.framed).poll_ready(cx)?.is_pending() {
+                    break;
+                }
+                match self.rx.poll_recv(cx) {
+                    Poll::Ready(Some((req, tx))) => {
+                        Pin::new(&mut self.framed).start_send(req)?;
+                        self.pending.push_back(tx);
+                        progress = true;
+                    }
+                    Poll::Ready(None) => self.rx_closed = true,
+                    Poll::Pending => break,
+                }
+            }
+
+            let flushed = Pin::new(&mut self.framed).poll_flush(cx)?.is_ready();
+
+            if !self.pending.is_empty() {
+                match Pin::new(&mut self.framed).poll_next(cx) {
+                    Poll::Ready(Some(resp)) => {
+                        let resp = resp?;
+                        // The request may have been cancelled by dropping its future.
+                        let _ = self.pending.pop_front().unwrap().send(Ok(resp));
+                        progress = true;
+                    }
+                    Poll::Ready(None) => return Poll::Ready(Err(connection_closed())),
+                    Poll::Pending => {}
+                }
+            }
+
+            if self.rx_closed && self.pending.is_empty() && flushed {
+                return Poll::Ready(Ok(()));
+            }
+
+            if !progress {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+impl<T, C, Req, Resp> Future for Dispatch<T, C, Req, Resp>
+    where T: AsyncRead + AsyncWrite + Unpin,
+          C: Decoder<Item = Resp, Error = io::Error> + Encoder<Req, Error = io::Error>
+{
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let this = self.get_mut();
+        match this.poll_dispatch(cx) {
+            Poll::Ready(Ok(())) => Poll::Ready(()),
+            Poll::Ready(Err(e)) => {
+                for tx in this.pending.drain(..) {
+                    let _ = tx.send(Err(copy_error(&e)));
+                }
+                Poll::Ready(())
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[test]
+fn test_pipeline() {
+    use frame::DelimiterProto;
+    use futures_util::future::{self, Ready};
+    use bytes::BytesMut;
+
+    struct Upper;
+
+    impl Service<BytesMut> for Upper {
+        type Response = Vec<u8>;
+        type Error = io::Error;
+        type Future = Ready<io::Result<Vec<u8>>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: BytesMut) -> Self::Future {
+            future::ready(Ok(req.to_ascii_uppercase()))
+        }
+    }
+
+    let rt = ::tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let _guard = rt.enter();
+
+    let (server, client) = ::tokio::io::duplex(16);
+    let proto = DelimiterProto::new(b'\n');
+    let conn = ::tokio::spawn(serve_connection(server, proto, Upper));
+
+    let mut client = Client::new(client, proto);
+    let foo = client.call(b"foo".to_vec());
+    let bar = client.call(b"bar".to_vec());
+    assert_eq!(&rt.block_on(foo).unwrap()[..], b"FOO");
+    assert_eq!(&rt.block_on(bar).unwrap()[..], b"BAR");
+
+    drop(client);
+    rt.block_on(conn).unwrap().unwrap();
+}
@@ -0,0 +1,45 @@
+//! Helpers shared by the codec tests.
+
+use tokio_core::io::{Codec, EasyBuf};
+use error::Error;
+use std::fmt::Debug;
+use std::io;
+
+/// Returns the codec error carried by `err`, if any.
+pub fn codec_error(err: &io::Error) -> Option<Error> {
+    err.get_ref().and_then(|e| e.downcast_ref::<Error>()).cloned()
+}
+
+/// Asserts that `res` failed with the codec error `expected`.
+pub fn assert_err<T: Debug>(res: io::Result<T>, expected: Error) {
+    assert_eq!(codec_error(&res.unwrap_err()), Some(expected));
+}
+
+/// Encodes `items` one after the other.
+pub fn encode_all<C: Codec>(codec: &mut C, items: Vec<C::Out>) -> Vec<u8> {
+    let mut v = vec![];
+    for item in items {
+        codec.encode(item, &mut v).unwrap();
+    }
+    v
+}
+
+/// Decodes `input` received in reads of `read_len` bytes, and asserts that it is consumed.
+pub fn decode_chunks<C: Codec>(codec: &mut C, input: &[u8], read_len: usize) -> Vec<C::In> {
+    let mut buf = EasyBuf::new();
+    let mut decoded = vec![];
+    for c in input.chunks(read_len) {
+        buf.get_mut().extend_from_slice(c);
+        while let Some(item) = codec.decode(&mut buf).unwrap() {
+            decoded.push(item);
+        }
+    }
+    assert_eq!(buf.len(), 0);
+    decoded
+}
+
+/// Encodes `items` and decodes them back with the same codec, in reads of `read_len` bytes.
+pub fn roundtrip<C: Codec>(codec: &mut C, items: Vec<C::Out>, read_len: usize) -> Vec<C::In> {
+    let v = encode_all(codec, items);
+    decode_chunks(codec, &v, read_len)
+}
@@ -0,0 +1,534 @@
+//! WebSocket protocol (RFC 6455).
+//!
+//! [`WebSocketProto`](./struct.WebSocketProto.html) frames the data transfer phase of a WebSocket
+//! connection, after the opening handshake has been completed over the same connection. Data
+//! messages are reassembled from their fragments, and control frames are yielded as they arrive,
+//! including in the middle of a fragmented message.
+//!
+//! The closing handshake is left to the application: a received
+//! [`Message::Close`](./enum.Message.html) should be answered with a close message before the
+//! connection is closed.
+
+use tokio_core::io::{Codec, Io, EasyBuf, Framed};
+use tokio_proto::pipeline::{ServerProto, ClientProto};
+#[cfg(feature = "tokio-util-codec")]
+use bytes::BytesMut;
+use byteorder::{BigEndian, ByteOrder};
+use buf::{FrameBuf, FrameBufMut};
+use error::Error;
+use std::io;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+/// The maximum payload length of a control frame.
+const MAX_CONTROL_LENGTH: usize = 125;
+
+/// The endpoint of a connection, which determines the masking rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Frames sent are not masked, and frames received must be masked.
+    Server,
+    /// Frames sent are masked with a random key, and frames received must not be masked.
+    Client,
+}
+
+/// A WebSocket message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// Text message.
+    Text(String),
+    /// Binary message.
+    Binary(Vec<u8>),
+    /// Close control frame, with its status code and reason if any.
+    Close(Option<(u16, String)>),
+    /// Ping control frame, with its application data.
+    Ping(Vec<u8>),
+    /// Pong control frame, with its application data.
+    Pong(Vec<u8>),
+}
+
+/// WebSocket protocol.
+///
+/// Requests and responses are both [`Message`](./enum.Message.html)s. The role of the codec must
+/// match the side of the connection the protocol is used on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebSocketProto {
+    codec: WebSocketCodec,
+}
+
+impl WebSocketProto {
+    pub fn new(role: Role) -> Self {
+        WebSocketProto { codec: WebSocketCodec::new(role) }
+    }
+
+    /// Sets the maximum length of the payload of a message, once reassembled.
+    pub fn max_message_length(self, max_message_length: usize) -> Self {
+        WebSocketProto { codec: self.codec.max_message_length(max_message_length) }
+    }
+}
+
+impl<T: Io + 'static> ServerProto<T> for WebSocketProto {
+    type Request = Message;
+    type Response = Message;
+    type Transport = Framed<T, WebSocketCodec>;
+    type BindTransport = io::Result<Self::Transport>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(io.framed(self.codec.clone()))
+    }
+}
+
+impl<T: Io + 'static> ClientProto<T> for WebSocketProto {
+    type Request = Message;
+    type Response = Message;
+    type Transport = Framed<T, WebSocketCodec>;
+    type BindTransport = io::Result<Self::Transport>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(io.framed(self.codec.clone()))
+    }
+}
+
+#[cfg(feature = "async-service")]
+impl ::service::NewCodec for WebSocketProto {
+    type Codec = WebSocketCodec;
+
+    fn new_codec(&self) -> WebSocketCodec {
+        self.codec.clone()
+    }
+}
+
+/// Protocol codec used by [`WebSocketProto`](./struct.WebSocketProto.html).
+///
+/// Frames with reserved bits set or a reserved opcode, fragmented or oversized control frames,
+/// continuation frames outside of a fragmented message, data frames inside one, frames breaking
+/// the masking rules of the role, and close frames with a status code which may not be sent fail
+/// the decode with
+/// [`Error::InvalidEncoding`](../error/enum.Error.html). A 64-bit payload length with its most
+/// significant bit set fails with `Error::InvalidLength`, a text message or close reason which is
+/// not UTF-8 with `Error::InvalidUtf8`, and a message longer than `max_message_length` with
+/// `Error::FrameTooLarge`. Control frames are not subject to `max_message_length`.
+///
+/// Each message is encoded as a single frame. Encoding a control frame whose payload exceeds 125
+/// bytes fails with `Error::WrongFrameLength`, and a close message with a status code which may
+/// not be sent with `Error::InvalidEncoding`. In the client role, frames are masked with keys from
+/// the random number generator of the operating system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebSocketCodec {
+    role: Role,
+    max_message_length: Option<usize>,
+    /// Whether the fragmented message being received is text, and its payload so far.
+    fragments: Option<(bool, Vec<u8>)>,
+}
+
+impl WebSocketCodec {
+    pub fn new(role: Role) -> Self {
+        WebSocketCodec {
+            role,
+            max_message_length: None,
+            fragments: None,
+        }
+    }
+
+    /// Sets the maximum length of the payload of a message, once reassembled.
+    pub fn max_message_length(mut self, max_message_length: usize) -> Self {
+        self.max_message_length = Some(max_message_length);
+        self
+    }
+
+    /// Returns the role of the codec.
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    /// Splits a frame off `buf`, returning its FIN bit, opcode and unmasked payload.
+    fn decode_frame<F: FrameBuf>(&mut self, buf: &mut F)
+                                 -> io::Result<Option<(bool, u8, Vec<u8>)>> {
+        let (fin, opcode, header_len, len, mask) = {
+            let data = buf.as_ref();
+            if data.len() < 2 {
+                return Ok(None);
+            }
+
+            let fin = data[0] & 0x80 != 0;
+            let opcode = data[0] & 0x0F;
+            let control = opcode & 0x08 != 0;
+            if data[0] & 0x70 != 0 {
+                return Err(Error::InvalidEncoding.into());
+            }
+            match opcode {
+                OP_CONTINUATION | OP_TEXT | OP_BINARY | OP_CLOSE | OP_PING | OP_PONG => {}
+                _ => return Err(Error::InvalidEncoding.into()),
+            }
+            let masked = data[1] & 0x80 != 0;
+            if masked != (self.role == Role::Server) {
+                return Err(Error::InvalidEncoding.into());
+            }
+
+            let (len, mut header_len) = match data[1] & 0x7F {
+                126 => {
+                    if data.len() < 4 {
+                        return Ok(None);
+                    }
+                    (BigEndian::read_u16(&data[2..4]) as u64, 4)
+                }
+                127 => {
+                    if data.len() < 10 {
+                        return Ok(None);
+                    }
+                    let len = BigEndian::read_u64(&data[2..10]);
+                    if len >> 63 != 0 {
+                        return Err(Error::InvalidLength.into());
+                    }
+                    (len, 10)
+                }
+                n => (n as u64, 2),
+            };
+
+            if control && (!fin || len > MAX_CONTROL_LENGTH as u64) {
+                return Err(Error::InvalidEncoding.into());
+            }
+            if let (false, Some(max)) = (control, self.max_message_length) {
+                let pending = self.fragments.as_ref().map_or(0, |(_, payload)| payload.len());
+                if len > max.saturating_sub(pending) as u64 {
+                    return Err(Error::FrameTooLarge.into());
+                }
+            }
+            if len > usize::MAX as u64 {
+                return Err(Error::FrameTooLarge.into());
+            }
+
+            let mut mask = None;
+            if masked {
+                if data.len() < header_len + 4 {
+                    return Ok(None);
+                }
+                mask = Some([data[header_len],
+                             data[header_len + 1],
+                             data[header_len + 2],
+                             data[header_len + 3]]);
+                header_len += 4;
+            }
+
+            (fin, opcode, header_len, len as usize, mask)
+        };
+
+        if buf.len() - header_len < len {
+            return Ok(None);
+        }
+        buf.split_to(header_len);
+        let mut payload = buf.split_to(len).as_ref().to_vec();
+        if let Some(key) = mask {
+            apply_mask(&mut payload, key);
+        }
+        Ok(Some((fin, opcode, payload)))
+    }
+
+    fn decode_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<Option<Message>> {
+        loop {
+            let (fin, opcode, payload) = match self.decode_frame(buf)? {
+                Some(frame) => frame,
+                None => return Ok(None),
+            };
+
+            let (text, payload) = match opcode {
+                OP_CONTINUATION => {
+                    let (text, mut message) = match self.fragments.take() {
+                        Some(fragments) => fragments,
+                        None => return Err(Error::InvalidEncoding.into()),
+                    };
+                    message.extend_from_slice(&payload);
+                    (text, message)
+                }
+                OP_TEXT | OP_BINARY => {
+                    if self.fragments.is_some() {
+                        return Err(Error::InvalidEncoding.into());
+                    }
+                    (opcode == OP_TEXT, payload)
+                }
+                OP_CLOSE => return decode_close(payload).map(Some),
+                OP_PING => return Ok(Some(Message::Ping(payload))),
+                _ => return Ok(Some(Message::Pong(payload))),
+            };
+
+            if !fin {
+                self.fragments = Some((text, payload));
+                continue;
+            }
+            if !text {
+                return Ok(Some(Message::Binary(payload)));
+            }
+            return match String::from_utf8(payload) {
+                Ok(s) => Ok(Some(Message::Text(s))),
+                Err(_) => Err(Error::InvalidUtf8.into()),
+            };
+        }
+    }
+
+    fn decode_eof_buf<F: FrameBuf>(&mut self, buf: &mut F) -> io::Result<Option<Message>> {
+        match self.decode_buf(buf)? {
+            Some(message) => Ok(Some(message)),
+            None if buf.is_empty() && self.fragments.is_none() => Ok(None),
+            None => Err(Error::UnexpectedEof.into()),
+        }
+    }
+
+    fn encode_buf<W: FrameBufMut>(&mut self, message: Message, buf: &mut W) -> io::Result<()> {
+        let (opcode, mut payload) = match message {
+            Message::Text(s) => (OP_TEXT, s.into_bytes()),
+            Message::Binary(data) => (OP_BINARY, data),
+            Message::Close(None) => (OP_CLOSE, Vec::new()),
+            Message::Close(Some((code, reason))) => {
+                if !is_valid_close_code(code) {
+                    return Err(Error::InvalidEncoding.into());
+                }
+                let mut payload = vec![0; 2];
+                BigEndian::write_u16(&mut payload, code);
+                payload.extend_from_slice(reason.as_bytes());
+                (OP_CLOSE, payload)
+            }
+            Message::Ping(data) => (OP_PING, data),
+            Message::Pong(data) => (OP_PONG, data),
+        };
+        if opcode & 0x08 != 0 && payload.len() > MAX_CONTROL_LENGTH {
+            return Err(Error::WrongFrameLength.into());
+        }
+
+        let mut header = [0; 14];
+        header[0] = 0x80 | opcode;
+        let mut header_len = if payload.len() < 126 {
+            header[1] = payload.len() as u8;
+            2
+        } else if payload.len() <= 0xFFFF {
+            header[1] = 126;
+            BigEndian::write_u16(&mut header[2..4], payload.len() as u16);
+            4
+        } else {
+            header[1] = 127;
+            BigEndian::write_u64(&mut header[2..10], payload.len() as u64);
+            10
+        };
+        if self.role == Role::Client {
+            let key = mask_key()?;
+            header[1] |= 0x80;
+            header[header_len..header_len + 4].copy_from_slice(&key);
+            header_len += 4;
+            apply_mask(&mut payload, key);
+        }
+
+        buf.reserve(header_len + payload.len());
+        buf.put_slice(&header[..header_len]);
+        buf.put_slice(&payload);
+        Ok(())
+    }
+}
+
+fn decode_close(payload: Vec<u8>) -> io::Result<Message> {
+    match payload.len() {
+        0 => Ok(Message::Close(None)),
+        1 => Err(Error::InvalidEncoding.into()),
+        _ => {
+            let code = BigEndian::read_u16(&payload[..2]);
+            if !is_valid_close_code(code) {
+                return Err(Error::InvalidEncoding.into());
+            }
+            match String::from_utf8(payload[2..].to_vec()) {
+                Ok(reason) => Ok(Message::Close(Some((code, reason)))),
+                Err(_) => Err(Error::InvalidUtf8.into()),
+            }
+        }
+    }
+}
+
+fn apply_mask(payload: &mut [u8], key: [u8; 4]) {
+    for (i, b) in payload.iter_mut().enumerate() {
+        *b ^= key[i % 4];
+    }
+}
+
+/// Returns whether `code` may be sent in a close frame.
+///
+/// Codes below 1000, the reserved 1004, the codes 1005, 1006 and 1015 which only report a
+/// condition locally, and the codes not yet assigned by the RFC or IANA are rejected.
+fn is_valid_close_code(code: u16) -> bool {
+    matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999)
+}
+
+/// Returns a new masking key from the random number generator of the operating system.
+fn mask_key() -> io::Result<[u8; 4]> {
+    let mut key = [0; 4];
+    ::getrandom::getrandom(&mut key)?;
+    Ok(key)
+}
+
+impl Codec for WebSocketCodec {
+    type In = Message;
+    type Out = Message;
+
+    fn decode(&mut self, buf: &mut EasyBuf) -> io::Result<Option<Message>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut EasyBuf) -> io::Result<Message> {
+        match self.decode_eof_buf(buf)? {
+            Some(message) => Ok(message),
+            None => Err(Error::UnexpectedEof.into()),
+        }
+    }
+
+    fn encode(&mut self, message: Message, buf: &mut Vec<u8>) -> io::Result<()> {
+        self.encode_buf(message, buf)
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl ::tokio_util::codec::Decoder for WebSocketCodec {
+    type Item = Message;
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<Message>> {
+        self.decode_buf(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> io::Result<Option<Message>> {
+        self.decode_eof_buf(buf)
+    }
+}
+
+#[cfg(feature = "tokio-util-codec")]
+impl ::tokio_util::codec::Encoder<Message> for WebSocketCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, message: Message, buf: &mut BytesMut) -> io::Result<()> {
+        self.encode_buf(message, buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::{assert_err, codec_error, decode_chunks, encode_all};
+
+    fn decode_all(p: &mut WebSocketCodec, input: &[u8]) -> Vec<Message> {
+        decode_chunks(p, input, 1)
+    }
+
+    fn decode_err(p: &mut WebSocketCodec, input: &[u8]) -> Error {
+        let mut buf = EasyBuf::from(input.to_vec());
+        loop {
+            match p.decode(&mut buf) {
+                Ok(Some(_)) => {}
+                Ok(None) => panic!("no error"),
+                Err(e) => return codec_error(&e).unwrap(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_websocket_rfc_examples() {
+        let mut client = WebSocketCodec::new(Role::Client);
+        let mut input = b"\x81\x05Hello\x01\x03Hel\x89\x05Hello\x80\x02lo".to_vec();
+        input.extend_from_slice(b"\x82\x7E\x01\x00");
+        input.extend_from_slice(&[0xAB; 256]);
+        input.extend_from_slice(b"\x82\x7F\x00\x00\x00\x00\x00\x01\x00\x00");
+        input.extend_from_slice(&[0xCD; 65536]);
+        assert_eq!(decode_all(&mut client, &input),
+                   vec![Message::Text("Hello".to_owned()),
+                        Message::Ping(b"Hello".to_vec()),
+                        Message::Text("Hello".to_owned()),
+                        Message::Binary(vec![0xAB; 256]),
+                        Message::Binary(vec![0xCD; 65536])]);
+
+        let mut server = WebSocketCodec::new(Role::Server);
+        assert_eq!(decode_all(&mut server,
+                              b"\x81\x85\x37\xfa\x21\x3d\x7f\x9f\x4d\x51\x58\
+                                \x8a\x85\x37\xfa\x21\x3d\x7f\x9f\x4d\x51\x58"),
+                   vec![Message::Text("Hello".to_owned()), Message::Pong(b"Hello".to_vec())]);
+
+        let mut v = vec![];
+        server.encode(Message::Text("Hello".to_owned()), &mut v).unwrap();
+        server.encode(Message::Close(Some((1000, "bye".to_owned()))), &mut v).unwrap();
+        server.encode(Message::Binary(vec![0; 256]), &mut v).unwrap();
+        assert_eq!(&v[..14], b"\x81\x05Hello\x88\x05\x03\xE8bye");
+        assert_eq!(&v[14..18], b"\x82\x7E\x01\x00");
+    }
+
+    #[test]
+    fn test_websocket_roundtrip() {
+        use quickcheck::quickcheck;
+
+        fn prop(frames: Vec<Vec<u8>>, chunk: usize) -> bool {
+            let messages: Vec<Message> = frames.into_iter()
+                .enumerate()
+                .map(|(i, f)| match i % 4 {
+                    0 => Message::Binary(f),
+                    1 => Message::Text(String::from_utf8_lossy(&f).into_owned()),
+                    2 => Message::Ping(f.into_iter().take(MAX_CONTROL_LENGTH).collect()),
+                    _ => Message::Close(Some((1001, "away".to_owned()))),
+                })
+                .collect();
+
+            [Role::Client, Role::Server].iter().all(|&role| {
+                let v = encode_all(&mut WebSocketCodec::new(role), messages.clone());
+
+                let peer = if role == Role::Client { Role::Server } else { Role::Client };
+                decode_chunks(&mut WebSocketCodec::new(peer), &v, chunk % 16 + 1) == messages
+            })
+        }
+
+        quickcheck(prop as fn(Vec<Vec<u8>>, usize) -> bool);
+
+        assert!(prop(vec![vec![0xFF; 70000], vec![b'a'; 300]], 5));
+    }
+
+    #[test]
+    fn test_websocket_invalid() {
+        let server = WebSocketCodec::new(Role::Server);
+        let client = WebSocketCodec::new(Role::Client);
+
+        // masking rules
+        assert_eq!(decode_err(&mut server.clone(), b"\x81\x00"), Error::InvalidEncoding);
+        assert_eq!(decode_err(&mut client.clone(), b"\x81\x80\0\0\0\0"),
+                   Error::InvalidEncoding);
+        // reserved bits and opcodes
+        assert_eq!(decode_err(&mut client.clone(), b"\xC1\x00"), Error::InvalidEncoding);
+        assert_eq!(decode_err(&mut client.clone(), b"\x83\x00"), Error::InvalidEncoding);
+        // control frames
+        assert_eq!(decode_err(&mut client.clone(), b"\x09\x00"), Error::InvalidEncoding);
+        assert_eq!(decode_err(&mut client.clone(), b"\x89\x7E\x00\x7E"), Error::InvalidEncoding);
+        assert_eq!(decode_err(&mut client.clone(), b"\x88\x01\x03"), Error::InvalidEncoding);
+        assert_eq!(decode_err(&mut client.clone(), b"\x88\x02\x03\xE7"), Error::InvalidEncoding);
+        assert_eq!(decode_err(&mut client.clone(), b"\x88\x02\x03\xED"), Error::InvalidEncoding);
+        assert_eq!(decode_err(&mut client.clone(), b"\x88\x02\x03\xEE"), Error::InvalidEncoding);
+        // fragmentation
+        assert_eq!(decode_err(&mut client.clone(), b"\x80\x00"), Error::InvalidEncoding);
+        assert_eq!(decode_err(&mut client.clone(), b"\x01\x01a\x81\x01b"),
+                   Error::InvalidEncoding);
+        // payloads
+        assert_eq!(decode_err(&mut client.clone(), b"\x82\x7F\x80\0\0\0\0\0\0\0"),
+                   Error::InvalidLength);
+        assert_eq!(decode_err(&mut client.clone(), b"\x81\x01\xFF"), Error::InvalidUtf8);
+        assert_eq!(decode_err(&mut client.clone(), b"\x01\x01\xE2\x80\x01\x82"),
+                   Error::InvalidUtf8);
+
+        let mut p = client.clone().max_message_length(4);
+        assert_eq!(decode_all(&mut p, b"\x02\x02ab\x89\x05Hello\x80\x02cd"),
+                   vec![Message::Ping(b"Hello".to_vec()), Message::Binary(b"abcd".to_vec())]);
+        assert_eq!(decode_err(&mut p, b"\x02\x02ab\x80\x03cde"), Error::FrameTooLarge);
+
+        let mut p = client.clone();
+        let mut buf = EasyBuf::from(b"\x01\x01a".to_vec());
+        assert!(p.decode(&mut buf).unwrap().is_none());
+        assert_err(p.decode_eof(&mut buf), Error::UnexpectedEof);
+
+        assert_err(server.clone().encode(Message::Ping(vec![0; 126]), &mut vec![]),
+                   Error::WrongFrameLength);
+        assert_err(server.clone().encode(Message::Close(Some((1005, String::new()))), &mut vec![]),
+                   Error::InvalidEncoding);
+    }
+}